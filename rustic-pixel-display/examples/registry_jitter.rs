@@ -0,0 +1,201 @@
+//! Measures the frame-time jitter caused by API requests, comparing a registry shared behind a
+//! `Mutex` (how the HTTP server and the render thread used to share it) with the published
+//! [`RenderSet`](rustic_pixel_display::registry::RenderSet) snapshots.
+//!
+//! The render and driver threads pass a canvas back and forth like [`MatrixDriver`] does, showing a
+//! canvas blocks for a fixed refresh time. Meanwhile an API thread handles a request every 50 ms,
+//! spending the request duration on it.
+//!
+//! ```sh
+//! cargo run --release --example registry_jitter
+//! ```
+//!
+//! [`MatrixDriver`]: rustic_pixel_display::driver::MatrixDriver
+
+use anyhow::Result;
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, Size},
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    Drawable,
+};
+use parking_lot::Mutex;
+use rustic_pixel_display::{
+    registry::{Registry, RenderState},
+    render::{FrameBuffer, Render, RenderFactory},
+};
+use std::{
+    convert::Infallible,
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const CANVAS_SIZE: Size = Size::new(128, 64);
+const FRAMES: usize = 2000;
+const REFRESH_TIME: Duration = Duration::from_millis(8);
+const REQUEST_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Fills the canvas a few times, about as much work as a simple render
+struct Fill;
+
+impl<D> Render<D> for Fill
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        for red in 0..8 {
+            Rectangle::new(Point::zero(), CANVAS_SIZE)
+                .into_styled(PrimitiveStyle::with_fill(Rgb888::new(red, 0, 0)))
+                .draw(canvas)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct FillFactory;
+
+impl<D> RenderFactory<D> for FillFactory
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        "Fill"
+    }
+
+    fn render_description(&self) -> &'static str {
+        "Fills the canvas"
+    }
+
+    fn load_from_config<R: Read>(&self, _reader: R) -> Result<Box<dyn Render<D>>> {
+        Ok(Box::new(Fill))
+    }
+}
+
+/// Draws [`FRAMES`] frames while requests are being handled and returns the time between them in
+/// milliseconds. With `locked` both the requests and the frames lock the registry.
+fn measure(locked: bool, request_duration: Duration) -> Result<Vec<f64>> {
+    let registry: Registry<FillFactory, FrameBuffer> = Registry::new(vec![FillFactory]);
+    let uuid = registry.load("Fill", "{}".as_bytes())?;
+
+    // Renders are loaded in the background
+    while matches!(registry.renders()[0].state, RenderState::Loading) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    registry.select(uuid)?;
+
+    let shared = Arc::new(Mutex::new(registry.clone()));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let api_thread = {
+        let registry = registry.clone();
+        let shared = shared.clone();
+        let done = done.clone();
+
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                let guard = locked.then(|| shared.lock());
+                let _ = registry.renders();
+                thread::sleep(request_duration);
+                drop(guard);
+
+                thread::sleep(REQUEST_INTERVAL);
+            }
+        })
+    };
+
+    let (driver_to_render_sender, driver_to_render_receiver) = mpsc::channel::<Box<FrameBuffer>>();
+    let (render_to_driver_sender, render_to_driver_receiver) = mpsc::channel::<Box<FrameBuffer>>();
+
+    let driver_thread = {
+        let done = done.clone();
+
+        thread::spawn(move || -> Result<Vec<f64>> {
+            let mut frame_times = Vec::with_capacity(FRAMES);
+            driver_to_render_sender.send(Box::new(FrameBuffer::new(CANVAS_SIZE)))?;
+
+            let mut last_frame = Instant::now();
+            while frame_times.len() <= FRAMES {
+                let canvas = render_to_driver_receiver.recv()?;
+                thread::sleep(REFRESH_TIME);
+
+                let now = Instant::now();
+                frame_times.push((now - last_frame).as_secs_f64() * 1000.0);
+                last_frame = now;
+
+                driver_to_render_sender.send(canvas)?;
+            }
+
+            done.store(true, Ordering::SeqCst);
+
+            // The first frame includes starting up
+            frame_times.remove(0);
+            Ok(frame_times)
+        })
+    };
+
+    while let Ok(mut canvas) = driver_to_render_receiver.recv() {
+        if done.load(Ordering::SeqCst) {
+            break;
+        }
+
+        match locked {
+            true => shared.lock().render(canvas.as_mut())?,
+            false => registry.render(canvas.as_mut())?,
+        }
+
+        if render_to_driver_sender.send(canvas).is_err() {
+            break;
+        }
+    }
+
+    let frame_times = driver_thread.join().expect("driver thread panicked")?;
+    api_thread.join().expect("API thread panicked");
+
+    Ok(frame_times)
+}
+
+fn report(name: &str, mut frame_times: Vec<f64>) {
+    let count = frame_times.len() as f64;
+    let mean = frame_times.iter().sum::<f64>() / count;
+    let std_dev = (frame_times
+        .iter()
+        .map(|frame_time| (frame_time - mean).powi(2))
+        .sum::<f64>()
+        / count)
+        .sqrt();
+
+    frame_times.sort_by(f64::total_cmp);
+    let p99 = frame_times[frame_times.len() * 99 / 100];
+    let max = frame_times[frame_times.len() - 1];
+
+    println!(
+        "{name}: mean {mean:.2} ms, std dev {std_dev:.2} ms, p99 {p99:.2} ms, max {max:.2} ms"
+    );
+}
+
+fn main() -> Result<()> {
+    // Loading renders spawns blocking tasks
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+
+    for request_ms in [1, 5, 20] {
+        let request_duration = Duration::from_millis(request_ms);
+
+        report(
+            &format!("{request_ms} ms requests, Mutex<Registry>"),
+            measure(true, request_duration)?,
+        );
+        report(
+            &format!("{request_ms} ms requests, RenderSet snapshots"),
+            measure(false, request_duration)?,
+        );
+    }
+
+    Ok(())
+}
@@ -12,6 +12,29 @@ pub fn derive_configurable(input: TokenStream) -> TokenStream {
 
     let name = &ast.ident;
 
+    let draw_target_ident = ast
+        .generics
+        .params
+        .iter()
+        .find_map(|param| {
+            if let GenericParam::Type(type_param) = param {
+                type_param.bounds.iter().find_map(|bound| {
+                    if let TypeParamBound::Trait(trait_bound) = bound {
+                        if let Some(last_segment) = trait_bound.path.segments.last() {
+                            if last_segment.ident == "DrawTarget" {
+                                return Some(type_param.ident.clone());
+                            }
+                        }
+                    }
+
+                    None
+                })
+            } else {
+                None
+            }
+        })
+        .expect("Could not draw DrawTarget bound in Enum");
+
     let (name_variants, description_variants, load_variants, factory_defaults) = match &ast.data {
         Data::Enum(enum_data) => {
             let mut enum_name = Vec::new();
@@ -37,19 +60,19 @@ pub fn derive_configurable(input: TokenStream) -> TokenStream {
 
                         let render_name = quote! {
                             Self::#variant_name(__self) => {
-                                __self.render_name()
+                                rustic_pixel_display::render::RenderFactory::<#draw_target_ident>::render_name(__self)
                             }
                         };
 
                         let render_description = quote! {
                             Self::#variant_name(__self) => {
-                                __self.render_description()
+                                rustic_pixel_display::render::RenderFactory::<#draw_target_ident>::render_description(__self)
                             }
                         };
 
                         let render_load_from_config = quote! {
                             Self::#variant_name(__self) => {
-                                __self.load_from_config(reader)
+                                rustic_pixel_display::render::RenderFactory::<#draw_target_ident>::load_from_config(__self, reader)
                             }
                         };
 
@@ -78,29 +101,6 @@ pub fn derive_configurable(input: TokenStream) -> TokenStream {
         _ => panic!("derive(RenderFactory) only supports enums"),
     };
 
-    let draw_target_ident = ast
-        .generics
-        .params
-        .iter()
-        .find_map(|param| {
-            if let GenericParam::Type(type_param) = param {
-                type_param.bounds.iter().find_map(|bound| {
-                    if let TypeParamBound::Trait(trait_bound) = bound {
-                        if let Some(last_segment) = trait_bound.path.segments.last() {
-                            if last_segment.ident == "DrawTarget" {
-                                return Some(type_param.ident.clone());
-                            }
-                        }
-                    }
-
-                    None
-                })
-            } else {
-                None
-            }
-        })
-        .expect("Could not draw DrawTarget bound in Enum");
-
     let (impl_generics, type_generics, where_clause) = ast.generics.split_for_impl();
 
     let expanded = quote! {
@@ -0,0 +1,39 @@
+use embedded_graphics::{pixelcolor::Rgb888, prelude::RgbColor};
+use serde::{de, Deserialize, Deserializer};
+
+/// A color written as `"#RRGGBB"` or by name (i.e. `"white"`)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Color(pub(crate) Rgb888);
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let color = String::deserialize(deserializer)?;
+
+        let rgb = match color.to_ascii_lowercase().as_str() {
+            "black" => Rgb888::BLACK,
+            "white" => Rgb888::WHITE,
+            "red" => Rgb888::RED,
+            "green" => Rgb888::GREEN,
+            "blue" => Rgb888::BLUE,
+            "yellow" => Rgb888::YELLOW,
+            "cyan" => Rgb888::CYAN,
+            "magenta" => Rgb888::MAGENTA,
+            "orange" => Rgb888::new(255, 165, 0),
+            "gray" | "grey" => Rgb888::new(128, 128, 128),
+            hex => {
+                let value = hex
+                    .strip_prefix('#')
+                    .filter(|hex| hex.len() == 6)
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| de::Error::custom(format!("Invalid color \"{color}\"")))?;
+
+                Rgb888::new((value >> 16) as u8, (value >> 8) as u8, value as u8)
+            }
+        };
+
+        Ok(Self(rgb))
+    }
+}
@@ -0,0 +1,83 @@
+//! JSON documents polled over HTTP, used by the renders that are configured at runtime to show
+//! data without any Rust code.
+
+use crate::provider::{PollSettings, Poller, Provider};
+use anyhow::Result;
+use serde::Deserialize;
+use std::time::Duration;
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+/// A JSON document fetched on an interval
+#[derive(Deserialize)]
+pub(crate) struct DataSource {
+    url: String,
+
+    /// How often the document is fetched again
+    #[serde(default = "default_interval_secs")]
+    interval_secs: u64,
+}
+
+/// Fetches the document of a single source, handing it to `update`
+struct DataSourceProvider<F> {
+    name: String,
+    url: String,
+    client: reqwest::Client,
+    update: F,
+}
+
+impl<F> Provider for DataSourceProvider<F>
+where
+    F: Fn(&str, serde_json::Value) + Send + 'static,
+{
+    type Data = serde_json::Value;
+
+    /// The documents are handed to `update` rather than kept in a shared state
+    type State = ();
+
+    fn name(&self) -> &'static str {
+        "data_source"
+    }
+
+    fn task(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn fetch(&mut self) -> Result<Self::Data> {
+        Ok(self
+            .client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    fn apply(&mut self, _state: &mut Self::State, data: Self::Data) -> Result<()> {
+        (self.update)(&self.name, data);
+        Ok(())
+    }
+}
+
+/// Polls `source` every interval and hands the document to `update` until the returned poller is
+/// dropped. Failed fetches are retried with a backoff and `update` isn't called, so the previous
+/// document is kept. The health of the fetches is reported under the name of the source.
+pub(crate) fn spawn<F>(name: String, source: DataSource, update: F) -> Result<Poller>
+where
+    F: Fn(&str, serde_json::Value) + Send + 'static,
+{
+    let DataSource { url, interval_secs } = source;
+
+    let provider = DataSourceProvider {
+        name,
+        url,
+        client: reqwest::Client::new(),
+        update,
+    };
+    let settings = PollSettings::every(Duration::from_secs(interval_secs.max(1)));
+
+    Poller::spawn(provider, settings, Default::default())
+}
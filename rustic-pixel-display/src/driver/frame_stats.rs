@@ -0,0 +1,90 @@
+use log::debug;
+use std::time::{Duration, Instant};
+
+/// How often a summary of the collected frame times is logged
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Collects the time between consecutive frames and periodically logs a summary.
+///
+/// A stall anywhere in the render path (waiting on a lock, a slow render, etc.) shows up as a frame
+/// that took longer than its peers, so the spread of the frame times is a good measure of jitter.
+pub(crate) struct FrameStats {
+    /// Name of the thread that is producing the frames, used in the log message
+    name: &'static str,
+
+    /// The time the previous frame was completed
+    last_frame: Option<Instant>,
+
+    /// The time the current reporting window was started
+    window_start: Instant,
+
+    count: u32,
+    sum_ms: f64,
+    sum_squared_ms: f64,
+    min: Duration,
+    max: Duration,
+}
+
+impl FrameStats {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            last_frame: None,
+            window_start: Instant::now(),
+            count: 0,
+            sum_ms: 0.0,
+            sum_squared_ms: 0.0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+        }
+    }
+
+    /// Marks the completion of a frame
+    pub(crate) fn frame_completed(&mut self) {
+        let now = Instant::now();
+
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            let frame_time = now - last_frame;
+            let frame_time_ms = frame_time.as_secs_f64() * 1000.0;
+
+            self.count += 1;
+            self.sum_ms += frame_time_ms;
+            self.sum_squared_ms += frame_time_ms * frame_time_ms;
+            self.min = self.min.min(frame_time);
+            self.max = self.max.max(frame_time);
+        }
+
+        if now - self.window_start >= REPORT_INTERVAL {
+            self.report();
+
+            self.window_start = now;
+            self.count = 0;
+            self.sum_ms = 0.0;
+            self.sum_squared_ms = 0.0;
+            self.min = Duration::MAX;
+            self.max = Duration::ZERO;
+        }
+    }
+
+    fn report(&self) {
+        if self.count == 0 {
+            return;
+        }
+
+        let count = self.count as f64;
+        let mean_ms = self.sum_ms / count;
+        let std_dev_ms = (self.sum_squared_ms / count - mean_ms * mean_ms)
+            .max(0.0)
+            .sqrt();
+
+        debug!(
+            "{} frame time over {} frames: mean {:.2} ms, std dev {:.2} ms, min {:.2} ms, max {:.2} ms",
+            self.name,
+            self.count,
+            mean_ms,
+            std_dev_ms,
+            self.min.as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0,
+        );
+    }
+}
@@ -1,10 +1,15 @@
-use crate::{config::HardwareConfig, render::Render};
+use crate::{
+    config::HardwareConfig,
+    metrics,
+    render::{draw_error_card, render_isolated, Render},
+};
 use anyhow::{anyhow, Result};
 use embedded_graphics::{
     pixelcolor::Rgb888,
     prelude::{DrawTarget, RgbColor},
 };
-use log::{debug, warn};
+use frame_stats::FrameStats;
+use log::{debug, error, warn};
 use std::{
     convert::Infallible,
     sync::{
@@ -17,9 +22,16 @@ use std::{
 };
 
 #[cfg(feature = "http_server")]
-use crate::{http_server::build_api_server, registry::Registry, render::RenderFactory};
+use crate::{
+    http_server::{serve_api, ApiTokens},
+    registry::Registry,
+    render::{DimmedCanvas, RenderFactory},
+};
+#[cfg(feature = "http_server")]
+use tokio_util::sync::CancellationToken;
 
 mod cpp_driver;
+mod frame_stats;
 mod rust_driver;
 
 pub use cpp_driver::CppHardwareDriver;
@@ -46,8 +58,9 @@ pub struct MatrixDriver {
     /// Handle to the driver thread
     driver_thread_handle: Option<thread::JoinHandle<Result<()>>>,
 
-    /// Handle to the HTTP thread (if any)
-    http_thread_handle: Option<thread::JoinHandle<Result<()>>>,
+    /// Token used to gracefully shutdown the HTTP server (if any)
+    #[cfg(feature = "http_server")]
+    http_cancel_token: Option<CancellationToken>,
 }
 
 impl MatrixDriver {
@@ -71,12 +84,30 @@ impl MatrixDriver {
         // Create the render thread
         let render_thread_handle = thread::spawn(move || -> Result<()> {
             debug!("Started render thread");
+            let mut frame_stats = FrameStats::new("Render thread");
+
+            // Once the render panicked it is replaced by an error card, there is nothing else to
+            // show in its place
+            let mut fault = None;
+
             while alive_render.load(Ordering::SeqCst) {
                 match driver_to_render_receiver.recv() {
                     Ok(mut canvas) => {
                         canvas.clear(Rgb888::BLACK)?;
-                        render.render(canvas.as_mut())?;
+
+                        if fault.is_none() {
+                            if let Err(e) = render_isolated(&render, canvas.as_mut()) {
+                                error!("Render panicked: {e}");
+                                fault = Some(e);
+                            }
+                        }
+                        if let Some(fault) = &fault {
+                            draw_error_card(canvas.as_mut(), "Render failed", fault)?;
+                        }
+
                         render_to_driver_sender.send(canvas)?;
+                        frame_stats.frame_completed();
+                        metrics::frame_rendered();
                     }
                     Err(_) => {
                         break;
@@ -107,6 +138,7 @@ impl MatrixDriver {
                 match render_to_driver_receiver.recv_timeout(timeout) {
                     Ok(canvas) => {
                         let canvas_new = hardware_driver.display_canvas(canvas);
+                        metrics::frame_displayed();
                         driver_to_render_sender.send(canvas_new)?;
                     }
                     Err(RecvTimeoutError::Disconnected) => {
@@ -114,6 +146,7 @@ impl MatrixDriver {
                     }
                     Err(RecvTimeoutError::Timeout) => {
                         warn!("Timeout waiting for frame from render");
+                        metrics::frame_timed_out();
                         continue;
                     }
                 }
@@ -126,30 +159,42 @@ impl MatrixDriver {
             alive,
             render_thread_handle: Some(render_thread_handle),
             driver_thread_handle: Some(driver_thread_handle),
-            http_thread_handle: None,
+            #[cfg(feature = "http_server")]
+            http_cancel_token: None,
         })
     }
 
+    /// Displays the render selected in the registry and serves the HTTP API used to control it.
+    ///
+    /// Renders draw onto a [`DimmedCanvas`] so the brightness of the display can be changed
+    /// through the registry at runtime.
     #[cfg(feature = "http_server")]
     pub fn with_register<H, A, F>(
         http_addr: A,
-        registry: Arc<parking_lot::Mutex<Registry<F, H::Canvas>>>,
+        api_tokens: Option<ApiTokens>,
+        registry: Registry<F, DimmedCanvas<H::Canvas>>,
         config: HardwareConfig,
     ) -> Result<Self>
     where
         A: std::net::ToSocketAddrs + Send + 'static,
         H: HardwareDriver,
-        F: RenderFactory<H::Canvas> + Send + Sync + 'static,
+        F: RenderFactory<DimmedCanvas<H::Canvas>> + Send + Sync + 'static,
     {
         let alive = Arc::new(AtomicBool::new(true));
 
         // Clone variable that will be moved into the thread
         let alive_render = alive.clone();
         let alive_driver = alive.clone();
-        let alive_http = alive.clone();
+
+        // Bind the HTTP listener up front so that an address that is already in use is reported to
+        // the caller. Converting to a Tokio listener requires us to be within a Tokio runtime.
+        let http_listener = std::net::TcpListener::bind(http_addr)?;
+        http_listener.set_nonblocking(true)?;
+        let http_listener = tokio::net::TcpListener::from_std(http_listener)?;
 
         // Clone variable will be move onto the respective threads
         let render_registry = registry.clone();
+        let driver_registry = registry.clone();
         let http_registry = registry;
 
         // Channels used to send the canvas between the render and driver threads
@@ -161,12 +206,28 @@ impl MatrixDriver {
         // Create the render thread
         let render_thread_handle = thread::spawn(move || -> Result<()> {
             debug!("Started render thread");
+            let mut frame_stats = FrameStats::new("Render thread");
+
             while alive_render.load(Ordering::SeqCst) {
                 match driver_to_render_receiver.recv() {
-                    Ok(mut canvas) => {
+                    Ok(canvas) => {
+                        // Draws the latest snapshot published by the registry, this never waits
+                        // on the HTTP thread
+                        let render_set = render_registry.render_set();
+
+                        let mut canvas = DimmedCanvas::new(canvas);
+                        canvas.set_brightness(render_set.display_settings().brightness);
                         canvas.clear(Rgb888::BLACK)?;
-                        render_registry.lock().render(canvas.as_mut())?;
-                        render_to_driver_sender.send(canvas)?;
+
+                        // The render set already isolates each render, this keeps the thread alive
+                        // if anything else drawn on the frame panics
+                        if let Err(e) = render_isolated(render_set.as_ref(), &mut canvas) {
+                            error!("Drawing a frame panicked: {e}");
+                        }
+
+                        render_to_driver_sender.send(canvas.into_inner())?;
+                        frame_stats.frame_completed();
+                        metrics::frame_rendered();
                     }
                     Err(_) => {
                         break;
@@ -197,6 +258,8 @@ impl MatrixDriver {
                 match render_to_driver_receiver.recv_timeout(timeout) {
                     Ok(canvas) => {
                         let canvas_new = hardware_driver.display_canvas(canvas);
+                        metrics::frame_displayed();
+                        driver_registry.frame_displayed();
                         driver_to_render_sender.send(canvas_new)?;
                     }
                     Err(RecvTimeoutError::Disconnected) => {
@@ -204,6 +267,7 @@ impl MatrixDriver {
                     }
                     Err(RecvTimeoutError::Timeout) => {
                         warn!("Timeout waiting for frame from render");
+                        metrics::frame_timed_out();
                         continue;
                     }
                 }
@@ -212,24 +276,24 @@ impl MatrixDriver {
             Ok(())
         });
 
-        // Get the handle to the created Tokio Runtime
-        let handle = tokio::runtime::Handle::current();
+        // The HTTP server runs as a task on the Tokio runtime and is shutdown once the driver is
+        // dropped
+        let http_cancel_token = CancellationToken::new();
+        let task_cancel_token = http_cancel_token.clone();
 
-        let http_thread_handle = thread::spawn(move || -> Result<()> {
-            let server = build_api_server(http_addr, handle, http_registry);
-
-            while alive_http.load(Ordering::SeqCst) {
-                server.poll();
+        tokio::task::spawn(async move {
+            if let Err(e) =
+                serve_api(http_listener, http_registry, api_tokens, task_cancel_token).await
+            {
+                error!("HTTP server encountered an error: {e}");
             }
-
-            Ok(())
         });
 
         Ok(Self {
             alive,
             render_thread_handle: Some(render_thread_handle),
             driver_thread_handle: Some(driver_thread_handle),
-            http_thread_handle: Some(http_thread_handle),
+            http_cancel_token: Some(http_cancel_token),
         })
     }
 }
@@ -240,32 +304,36 @@ impl Drop for MatrixDriver {
             alive,
             render_thread_handle,
             driver_thread_handle,
-            http_thread_handle,
+            #[cfg(feature = "http_server")]
+            http_cancel_token,
             ..
         } = self;
 
+        // Stop accepting new HTTP requests, requests that are in-flight are allowed to finish
+        #[cfg(feature = "http_server")]
+        if let Some(http_cancel_token) = http_cancel_token.take() {
+            http_cancel_token.cancel();
+        }
+
         // Stop the threads
         alive.store(false, Ordering::SeqCst);
 
+        // Errors are only reported, panicking while dropping would abort a program that is
+        // already unwinding
         if let Some(render_handle) = render_thread_handle.take() {
-            render_handle
-                .join()
-                .expect("Failed to join the render thread")
-                .expect("Render thread encountered an error");
+            match render_handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Render thread encountered an error: {e:#}"),
+                Err(_) => error!("Render thread panicked"),
+            }
         }
 
         if let Some(driver_handle) = driver_thread_handle.take() {
-            driver_handle
-                .join()
-                .expect("Failed to join the driver thread")
-                .expect("Driver thread encountered an error");
-        }
-
-        if let Some(http_handle) = http_thread_handle.take() {
-            http_handle
-                .join()
-                .expect("Failed to join the http thread")
-                .expect("HTTP thread encountered an error");
+            match driver_handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Driver thread encountered an error: {e:#}"),
+                Err(_) => error!("Driver thread panicked"),
+            }
         }
     }
 }
@@ -0,0 +1,131 @@
+//! Parser for the Glyph Bitmap Distribution Format, the text format the fonts shipped with
+//! rpi-rgb-led-matrix use.
+
+use super::{BitmapFont, Glyph};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+
+/// Parses the integers following a keyword (i.e. `BBX 6 10 0 -2`)
+fn numbers<const N: usize>(line: &str) -> Result<[i32; N]> {
+    let mut numbers = [0; N];
+    let mut values = line.split_whitespace().skip(1);
+
+    for number in numbers.iter_mut() {
+        *number = values
+            .next()
+            .ok_or_else(|| anyhow!("Expected {N} values in \"{line}\""))?
+            .parse()
+            .with_context(|| format!("Invalid number in \"{line}\""))?;
+    }
+
+    Ok(numbers)
+}
+
+/// Parses a BDF font, glyphs are expected to be encoded in Unicode (i.e. `ISO10646-1`) or
+/// ISO-8859-1, which shares its first 256 code points with Unicode
+pub(super) fn parse(source: &str) -> Result<BitmapFont> {
+    let mut lines = source.lines().map(str::trim).enumerate();
+
+    match lines.next() {
+        Some((_, line)) if line.starts_with("STARTFONT") => {}
+        _ => bail!("Not a BDF font, STARTFONT is missing"),
+    }
+
+    let mut ascent = None;
+    let mut descent = None;
+    let mut default_char = None;
+    let mut font_bounding_box = None;
+    let mut glyphs = HashMap::new();
+
+    // Properties of the glyph being parsed
+    let mut encoding = None;
+    let mut advance = None;
+    let mut bounding_box = None;
+
+    while let Some((number, line)) = lines.next() {
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        let context = || format!("Line {}", number + 1);
+
+        match keyword {
+            "FONTBOUNDINGBOX" => {
+                font_bounding_box = Some(numbers::<4>(line).with_context(context)?)
+            }
+            "FONT_ASCENT" => ascent = Some(numbers::<1>(line).with_context(context)?[0]),
+            "FONT_DESCENT" => descent = Some(numbers::<1>(line).with_context(context)?[0]),
+            "DEFAULT_CHAR" => default_char = Some(numbers::<1>(line).with_context(context)?[0]),
+            "STARTCHAR" => {
+                encoding = None;
+                advance = None;
+                bounding_box = font_bounding_box;
+            }
+            "ENCODING" => encoding = Some(numbers::<1>(line).with_context(context)?[0]),
+            "DWIDTH" => advance = Some(numbers::<1>(line).with_context(context)?[0]),
+            "BBX" => bounding_box = Some(numbers::<4>(line).with_context(context)?),
+            "BITMAP" => {
+                let [width, height, x_offset, y_offset] = bounding_box
+                    .ok_or_else(|| anyhow!("Glyph on line {} has no BBX", number + 1))?;
+                let stride = (width.max(0) as usize).div_ceil(8);
+
+                let mut bitmap = Vec::with_capacity(stride * height.max(0) as usize);
+                for (number, line) in lines.by_ref() {
+                    if line == "ENDCHAR" {
+                        break;
+                    }
+
+                    // Rows are sliced two characters at a time, which only works on ASCII
+                    if !line.is_ascii() {
+                        bail!("Invalid bitmap on line {}", number + 1);
+                    }
+
+                    // Rows can hold more bytes than needed (i.e. padded to 16 bits), extra bytes
+                    // are dropped
+                    let row = (0..line.len().min(stride * 2))
+                        .step_by(2)
+                        .map(|index| {
+                            u8::from_str_radix(&line[index..(index + 2).min(line.len())], 16)
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .with_context(|| format!("Invalid bitmap on line {}", number + 1))?;
+
+                    bitmap.extend_from_slice(&row);
+                    bitmap.resize(bitmap.len() + stride - row.len(), 0);
+                }
+                bitmap.resize(stride * height.max(0) as usize, 0);
+
+                // Glyphs that aren't mapped to a code point (ENCODING -1) are skipped
+                let character = encoding
+                    .and_then(|encoding| u32::try_from(encoding).ok())
+                    .and_then(char::from_u32);
+
+                if let Some(character) = character {
+                    glyphs.insert(
+                        character,
+                        Glyph {
+                            width: width.max(0) as u32,
+                            height: height.max(0) as u32,
+                            x_offset,
+                            y_offset,
+                            advance: advance.unwrap_or(width),
+                            bitmap,
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Fall back to the bounding box of the font for fonts that don't set their ascent and descent
+    let [_, box_height, _, box_y_offset] = font_bounding_box.unwrap_or_default();
+    let ascent = ascent.unwrap_or(box_height + box_y_offset);
+    let descent = descent.unwrap_or(-box_y_offset);
+
+    Ok(BitmapFont::new(
+        glyphs,
+        ascent,
+        descent,
+        default_char
+            .and_then(|default_char| u32::try_from(default_char).ok())
+            .and_then(char::from_u32),
+    ))
+}
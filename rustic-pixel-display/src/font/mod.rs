@@ -0,0 +1,398 @@
+//! Bitmap fonts loaded at runtime.
+//!
+//! The `mono_font` sets of embedded-graphics only cover ASCII and ISO-8859-1 and every character
+//! has the same width. Fonts in the BDF or PCF format (i.e. the ones shipped with
+//! rpi-rgb-led-matrix) can cover any Unicode character and be proportional. They are loaded from
+//! disk with [`load_fonts`] or from embedded assets with [`BitmapFont::parse`] and
+//! [`register_font`], after which render configurations can select them by name with
+//! [`FontName`].
+
+use crate::render::Font;
+use anyhow::{anyhow, Context, Result};
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, Size},
+    primitives::Rectangle,
+    text::{
+        renderer::{TextMetrics, TextRenderer},
+        Baseline,
+    },
+    Pixel,
+};
+use log::{info, warn};
+use parking_lot::RwLock;
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+mod bdf;
+mod pcf;
+
+/// Bitmap of a single character
+struct Glyph {
+    width: u32,
+    height: u32,
+
+    /// Position of the bottom left corner of the bitmap relative to the origin of the glyph, which
+    /// sits on the baseline. Positive values go right and up.
+    x_offset: i32,
+    y_offset: i32,
+
+    /// Distance from the origin of this glyph to the origin of the next one
+    advance: i32,
+
+    /// Rows from top to bottom, each row padded to a whole byte with the most significant bit
+    /// being the left most pixel
+    bitmap: Vec<u8>,
+}
+
+impl Glyph {
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let stride = self.width.div_ceil(8) as usize;
+
+        (0..self.height).flat_map(move |y| {
+            (0..self.width).filter_map(move |x| {
+                let byte = self.bitmap[y as usize * stride + x as usize / 8];
+                (byte & (0x80 >> (x % 8)) != 0).then_some((x, y))
+            })
+        })
+    }
+}
+
+/// A font made of bitmap glyphs, which can be proportional and cover any Unicode character
+pub struct BitmapFont {
+    glyphs: HashMap<char, Glyph>,
+
+    /// Number of pixels above the baseline
+    ascent: i32,
+
+    /// Number of pixels below the baseline
+    descent: i32,
+
+    /// Drawn in place of characters the font doesn't have
+    default_char: Option<char>,
+}
+
+impl BitmapFont {
+    fn new(
+        glyphs: HashMap<char, Glyph>,
+        ascent: i32,
+        descent: i32,
+        default_char: Option<char>,
+    ) -> Self {
+        Self {
+            glyphs,
+            ascent,
+            descent,
+            default_char,
+        }
+    }
+
+    /// Parses a font in the BDF or PCF format, the format is detected from the content
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if pcf::is_pcf(data) {
+            pcf::parse(data)
+        } else {
+            bdf::parse(std::str::from_utf8(data).context("Font is neither PCF nor BDF")?)
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("Could not parse {}", path.display()))
+    }
+
+    pub fn contains(&self, character: char) -> bool {
+        self.glyphs.contains_key(&character)
+    }
+
+    /// Height of a line of text in pixels
+    pub fn line_height(&self) -> u32 {
+        (self.ascent + self.descent).max(0) as u32
+    }
+
+    fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&character)
+            .or_else(|| self.default_char.and_then(|c| self.glyphs.get(&c)))
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    /// Width of `text` in pixels
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.chars()
+            .filter_map(|character| self.glyph(character))
+            .map(|glyph| glyph.advance)
+            .sum::<i32>()
+            .max(0) as u32
+    }
+
+    /// Row of the baseline for text drawn at `y` using `baseline`
+    fn baseline_y(&self, y: i32, baseline: Baseline) -> i32 {
+        match baseline {
+            Baseline::Top => y + self.ascent - 1,
+            Baseline::Bottom => y - self.descent,
+            Baseline::Middle => y + self.ascent - 1 - (self.line_height() as i32 - 1) / 2,
+            Baseline::Alphabetic => y,
+        }
+    }
+}
+
+impl fmt::Debug for BitmapFont {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BitmapFont")
+            .field("glyphs", &self.glyphs.len())
+            .field("ascent", &self.ascent)
+            .field("descent", &self.descent)
+            .finish()
+    }
+}
+
+/// Draws text using a [`BitmapFont`], can be used anywhere a `MonoTextStyle` is (i.e. with
+/// [`Text`](embedded_graphics::text::Text))
+#[derive(Clone)]
+pub struct BitmapTextStyle {
+    font: Arc<BitmapFont>,
+    color: Rgb888,
+}
+
+impl BitmapTextStyle {
+    pub fn new(font: Arc<BitmapFont>, color: Rgb888) -> Self {
+        Self { font, color }
+    }
+}
+
+impl TextRenderer for BitmapTextStyle {
+    type Color = Rgb888;
+
+    fn draw_string<D>(
+        &self,
+        text: &str,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Self { font, color } = self;
+        let baseline_y = font.baseline_y(position.y, baseline);
+
+        let mut x = position.x;
+        for glyph in text.chars().filter_map(|character| font.glyph(character)) {
+            // Top row of the bitmap
+            let top = baseline_y - glyph.y_offset - glyph.height as i32 + 1;
+            let left = x + glyph.x_offset;
+
+            target.draw_iter(glyph.pixels().map(|(glyph_x, glyph_y)| {
+                Pixel(
+                    Point::new(left + glyph_x as i32, top + glyph_y as i32),
+                    *color,
+                )
+            }))?;
+
+            x += glyph.advance;
+        }
+
+        Ok(Point::new(x, position.y))
+    }
+
+    fn draw_whitespace<D>(
+        &self,
+        width: u32,
+        position: Point,
+        _baseline: Baseline,
+        _target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        Ok(position + Point::new(width as i32, 0))
+    }
+
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
+        let Self { font, .. } = self;
+
+        let width = font.text_width(text);
+        let top = font.baseline_y(position.y, baseline) - font.ascent + 1;
+
+        TextMetrics {
+            bounding_box: Rectangle::new(
+                Point::new(position.x, top),
+                Size::new(width, font.line_height()),
+            ),
+            next_position: position + Point::new(width as i32, 0),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.line_height()
+    }
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<BitmapFont>>> {
+    static FONTS: OnceLock<RwLock<HashMap<String, Arc<BitmapFont>>>> = OnceLock::new();
+    FONTS.get_or_init(Default::default)
+}
+
+/// Makes `font` selectable by `name` in render configurations, replacing any font with the same
+/// name
+pub fn register_font(name: impl Into<String>, font: BitmapFont) {
+    registry().write().insert(name.into(), Arc::new(font));
+}
+
+/// Returns the font registered under `name`
+pub fn bitmap_font(name: &str) -> Option<Arc<BitmapFont>> {
+    registry().read().get(name).cloned()
+}
+
+/// Registers every `.bdf` and `.pcf` font found in `dir`, named after their file name without the
+/// extension (i.e. `fonts/7x13.bdf` is selected with `"7x13"`). Fonts that can't be parsed are
+/// skipped.
+///
+/// Returns the number of fonts that were registered.
+pub fn load_fonts<P: AsRef<Path>>(dir: P) -> Result<usize> {
+    let mut count = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let is_font = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("bdf") || extension.eq_ignore_ascii_case("pcf")
+            });
+        let name = path.file_stem().and_then(|stem| stem.to_str());
+
+        let (true, Some(name)) = (is_font, name) else {
+            continue;
+        };
+
+        match BitmapFont::load(&path) {
+            Ok(font) => {
+                info!("Loaded font \"{name}\" from {}", path.display());
+                register_font(name, font);
+                count += 1;
+            }
+            Err(e) => warn!("Skipping font {}: {e:#}", path.display()),
+        }
+    }
+
+    Ok(count)
+}
+
+/// A font selected by name in a render configuration.
+///
+/// Names of the built in fonts (see [`Font`]) are tried first, then the fonts registered with
+/// [`register_font`] or [`load_fonts`]. The font must exist when the configuration is loaded.
+#[derive(Clone, Debug)]
+pub enum FontName {
+    Mono(Font),
+    Bitmap(Arc<BitmapFont>),
+}
+
+impl Default for FontName {
+    fn default() -> Self {
+        Self::Mono(Font::default())
+    }
+}
+
+impl FromStr for FontName {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        if let Ok(font) = Font::from_str(name) {
+            return Ok(Self::Mono(font));
+        }
+
+        bitmap_font(name)
+            .map(Self::Bitmap)
+            .ok_or_else(|| anyhow!("Unknown font \"{name}\""))
+    }
+}
+
+impl<'de> Deserialize<'de> for FontName {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FontName {
+    /// Returns the style used to draw text in `color` with this font
+    pub fn style(&self, color: Rgb888) -> TextStyle {
+        match self {
+            FontName::Mono(font) => TextStyle::Mono(MonoTextStyle::new(font.mono_font(), color)),
+            FontName::Bitmap(font) => TextStyle::Bitmap(BitmapTextStyle::new(font.clone(), color)),
+        }
+    }
+}
+
+/// Style of text drawn with a [`FontName`], either one of the built in fonts or a bitmap font
+#[derive(Clone)]
+pub enum TextStyle {
+    Mono(MonoTextStyle<'static, Rgb888>),
+    Bitmap(BitmapTextStyle),
+}
+
+impl TextRenderer for TextStyle {
+    type Color = Rgb888;
+
+    fn draw_string<D>(
+        &self,
+        text: &str,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        match self {
+            TextStyle::Mono(style) => style.draw_string(text, position, baseline, target),
+            TextStyle::Bitmap(style) => style.draw_string(text, position, baseline, target),
+        }
+    }
+
+    fn draw_whitespace<D>(
+        &self,
+        width: u32,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        match self {
+            TextStyle::Mono(style) => style.draw_whitespace(width, position, baseline, target),
+            TextStyle::Bitmap(style) => style.draw_whitespace(width, position, baseline, target),
+        }
+    }
+
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
+        match self {
+            TextStyle::Mono(style) => style.measure_string(text, position, baseline),
+            TextStyle::Bitmap(style) => style.measure_string(text, position, baseline),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        match self {
+            TextStyle::Mono(style) => style.line_height(),
+            TextStyle::Bitmap(style) => style.line_height(),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Parser for the Portable Compiled Format, the binary format X11 compiles BDF fonts into.
+//!
+//! Only uncompressed files are supported, `.pcf.gz` fonts need to be decompressed first.
+
+use super::{BitmapFont, Glyph};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+const MAGIC: &[u8] = b"\x01fcp";
+
+const PCF_ACCELERATORS: u32 = 1 << 1;
+const PCF_METRICS: u32 = 1 << 2;
+const PCF_BITMAPS: u32 = 1 << 3;
+const PCF_BDF_ENCODINGS: u32 = 1 << 5;
+const PCF_BDF_ACCELERATORS: u32 = 1 << 8;
+
+const PCF_GLYPH_PAD_MASK: u32 = 3;
+const PCF_BYTE_MASK: u32 = 1 << 2;
+const PCF_BIT_MASK: u32 = 1 << 3;
+const PCF_SCAN_UNIT_MASK: u32 = 3 << 4;
+const PCF_COMPRESSED_METRICS: u32 = 0x100;
+
+/// Glyph indices of code points that aren't part of the font
+const NO_GLYPH: u16 = 0xFFFF;
+
+pub(super) fn is_pcf(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Reads values from a table, the byte order of a table is given by its format
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        Self {
+            data,
+            position,
+            big_endian: false,
+        }
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + N)
+            .ok_or_else(|| anyhow!("Font is truncated"))?;
+        self.position += N;
+
+        let mut bytes: [u8; N] = bytes.try_into()?;
+        if !self.big_endian {
+            bytes.reverse();
+        }
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes()?))
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes()?))
+    }
+
+    /// Reads the format that starts every table and switches to the byte order of the table
+    fn format(&mut self) -> Result<u32> {
+        self.big_endian = false;
+        let format = self.u32()?;
+        self.big_endian = format & PCF_BYTE_MASK != 0;
+
+        Ok(format)
+    }
+}
+
+/// Size and position of a glyph relative to its origin on the baseline
+struct Metrics {
+    left_bearing: i32,
+    right_bearing: i32,
+    advance: i32,
+    ascent: i32,
+    descent: i32,
+}
+
+fn read_metrics(reader: &mut Reader) -> Result<Vec<Metrics>> {
+    let format = reader.format()?;
+
+    if format & PCF_COMPRESSED_METRICS != 0 {
+        let count = reader.u16()?;
+        (0..count)
+            .map(|_| {
+                let mut value = || reader.u8().map(|value| value as i32 - 0x80);
+                Ok(Metrics {
+                    left_bearing: value()?,
+                    right_bearing: value()?,
+                    advance: value()?,
+                    ascent: value()?,
+                    descent: value()?,
+                })
+            })
+            .collect()
+    } else {
+        let count = reader.u32()?;
+        (0..count)
+            .map(|_| {
+                let metrics = Metrics {
+                    left_bearing: reader.i16()? as i32,
+                    right_bearing: reader.i16()? as i32,
+                    advance: reader.i16()? as i32,
+                    ascent: reader.i16()? as i32,
+                    descent: reader.i16()? as i32,
+                };
+                let _attributes = reader.u16()?;
+                Ok(metrics)
+            })
+            .collect()
+    }
+}
+
+/// Returns the bitmap of every glyph, rows are padded to a whole byte with the most significant
+/// bit being the left most pixel
+fn read_bitmaps(reader: &mut Reader, metrics: &[Metrics]) -> Result<Vec<Vec<u8>>> {
+    let format = reader.format()?;
+    let count = reader.u32()? as usize;
+    if count != metrics.len() {
+        bail!("Font has {count} bitmaps for {} glyphs", metrics.len());
+    }
+
+    let offsets = (0..count)
+        .map(|_| reader.u32().map(|offset| offset as usize))
+        .collect::<Result<Vec<_>>>()?;
+
+    let sizes = (0..4)
+        .map(|_| reader.u32().map(|size| size as usize))
+        .collect::<Result<Vec<_>>>()?;
+    let size = sizes[(format & PCF_GLYPH_PAD_MASK) as usize];
+
+    let start = reader.position;
+    let mut data = reader
+        .data
+        .get(start..start + size)
+        .ok_or_else(|| anyhow!("Font is truncated"))?
+        .to_vec();
+
+    // Bring the bitmaps to most significant bit and byte first
+    if format & PCF_BIT_MASK == 0 {
+        data.iter_mut().for_each(|byte| *byte = byte.reverse_bits());
+    }
+    let scan_unit = 1 << ((format & PCF_SCAN_UNIT_MASK) >> 4);
+    if (format & PCF_BIT_MASK != 0) != (format & PCF_BYTE_MASK != 0) && scan_unit > 1 {
+        data.chunks_exact_mut(scan_unit)
+            .for_each(|unit| unit.reverse());
+    }
+
+    let padding = 1 << (format & PCF_GLYPH_PAD_MASK);
+
+    metrics
+        .iter()
+        .zip(offsets)
+        .map(|(metrics, offset)| {
+            let width = (metrics.right_bearing - metrics.left_bearing).max(0) as usize;
+            let height = (metrics.ascent + metrics.descent).max(0) as usize;
+
+            let stride = width.div_ceil(8);
+            let padded_stride = width.div_ceil(8 * padding) * padding;
+
+            let mut bitmap = Vec::with_capacity(stride * height);
+            for row in 0..height {
+                let row_start = offset + row * padded_stride;
+                bitmap.extend_from_slice(
+                    data.get(row_start..row_start + stride)
+                        .ok_or_else(|| anyhow!("Glyph bitmap is out of bounds"))?,
+                );
+            }
+
+            Ok(bitmap)
+        })
+        .collect()
+}
+
+/// Returns the glyph index of every code point in the font along with the default character
+fn read_encodings(reader: &mut Reader) -> Result<(HashMap<u32, usize>, u32)> {
+    reader.format()?;
+
+    let min_byte2 = reader.i16()? as u32;
+    let max_byte2 = reader.i16()? as u32;
+    let min_byte1 = reader.i16()? as u32;
+    let max_byte1 = reader.i16()? as u32;
+    let default_char = reader.i16()? as u16 as u32;
+
+    let mut encodings = HashMap::new();
+    for byte1 in min_byte1..=max_byte1 {
+        for byte2 in min_byte2..=max_byte2 {
+            let index = reader.u16()?;
+            if index != NO_GLYPH {
+                encodings.insert((byte1 << 8) | byte2, index as usize);
+            }
+        }
+    }
+
+    Ok((encodings, default_char))
+}
+
+/// Returns the ascent and descent of the font
+fn read_accelerators(reader: &mut Reader) -> Result<(i32, i32)> {
+    reader.format()?;
+
+    // no_overlap, constant_metrics, terminal_font, constant_width, ink_inside, ink_metrics,
+    // draw_direction and padding
+    for _ in 0..8 {
+        reader.u8()?;
+    }
+
+    Ok((reader.i32()?, reader.i32()?))
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<BitmapFont> {
+    if !is_pcf(data) {
+        bail!("Not a PCF font");
+    }
+
+    let mut reader = Reader::new(data, MAGIC.len());
+    let table_count = reader.u32()?;
+
+    let mut tables = HashMap::new();
+    for _ in 0..table_count {
+        let table_type = reader.u32()?;
+        let _format = reader.u32()?;
+        let _size = reader.u32()?;
+        let offset = reader.u32()? as usize;
+        tables.insert(table_type, offset);
+    }
+
+    let table = |table_type: u32, name: &str| {
+        tables
+            .get(&table_type)
+            .map(|offset| Reader::new(data, *offset))
+            .ok_or_else(|| anyhow!("Font has no {name} table"))
+    };
+
+    let metrics = read_metrics(&mut table(PCF_METRICS, "metrics")?)?;
+    let bitmaps = read_bitmaps(&mut table(PCF_BITMAPS, "bitmaps")?, &metrics)?;
+    let (encodings, default_char) = read_encodings(&mut table(PCF_BDF_ENCODINGS, "encodings")?)?;
+    let (ascent, descent) = read_accelerators(
+        &mut table(PCF_BDF_ACCELERATORS, "accelerators")
+            .or_else(|_| table(PCF_ACCELERATORS, "accelerators"))?,
+    )?;
+
+    let glyphs = encodings
+        .into_iter()
+        .filter_map(|(code_point, index)| {
+            let character = char::from_u32(code_point)?;
+            let metrics = metrics.get(index)?;
+
+            Some((
+                character,
+                Glyph {
+                    width: (metrics.right_bearing - metrics.left_bearing).max(0) as u32,
+                    height: (metrics.ascent + metrics.descent).max(0) as u32,
+                    x_offset: metrics.left_bearing,
+                    y_offset: -metrics.descent,
+                    advance: metrics.advance,
+                    bitmap: bitmaps.get(index)?.clone(),
+                },
+            ))
+        })
+        .collect();
+
+    Ok(BitmapFont::new(
+        glyphs,
+        ascent,
+        descent,
+        char::from_u32(default_char),
+    ))
+}
@@ -0,0 +1,269 @@
+//! Liveness of the render and driver threads, and freshness of the data shown by the renders.
+
+use crate::{
+    registry::RenderState,
+    render::{panic_message, Render},
+};
+use chrono::{DateTime, Utc};
+use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
+use log::warn;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    convert::Infallible,
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// A thread that hasn't completed a frame for this long is considered stuck
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Window over which the frame rate is measured
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Outcome of the recent updates of a background task that keeps a render up to date
+#[derive(Clone, Debug, Serialize)]
+pub struct TaskHealth {
+    /// Name of the task, i.e. the data provider it fetches from
+    pub task: String,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+
+    /// Updates that failed since the last successful one
+    pub consecutive_failures: u32,
+}
+
+/// Handle used by a background task to report the outcome of its updates.
+///
+/// Clones refer to the same task, so the render can keep one and hand the other to its task.
+#[derive(Clone)]
+pub struct HealthReporter {
+    health: Arc<Mutex<TaskHealth>>,
+}
+
+impl HealthReporter {
+    pub fn new(task: impl Into<String>) -> Self {
+        Self {
+            health: Arc::new(Mutex::new(TaskHealth {
+                task: task.into(),
+                last_success: None,
+                last_error: None,
+                consecutive_failures: 0,
+            })),
+        }
+    }
+
+    pub fn success(&self) {
+        let mut health = self.health.lock();
+        health.last_success = Some(Utc::now());
+        health.consecutive_failures = 0;
+    }
+
+    /// Records that the data was restored from an update that succeeded at `at`, i.e. before a
+    /// restart
+    pub(crate) fn restored(&self, at: DateTime<Utc>) {
+        self.health.lock().last_success = Some(at);
+    }
+
+    /// Records a failed update, the error is kept until the next failure
+    pub fn failure(&self, error: impl Display) {
+        let mut health = self.health.lock();
+        health.last_error = Some(error.to_string());
+        health.consecutive_failures += 1;
+    }
+
+    /// Records a success or failure depending on `result`
+    pub fn report<T, E: Display>(&self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.success(),
+            Err(e) => self.failure(e),
+        }
+    }
+
+    pub fn health(&self) -> TaskHealth {
+        self.health.lock().clone()
+    }
+}
+
+/// Whether a thread is still producing frames
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ThreadHealth {
+    pub alive: bool,
+    pub last_frame_ms_ago: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    /// Frames are being drawn and every render is up to date
+    Ok,
+
+    /// Frames are being drawn, but a render failed or its data couldn't be updated
+    Degraded,
+
+    /// The render or driver thread stopped producing frames
+    Down,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RenderHealth {
+    pub id: Uuid,
+    pub factory_name: String,
+    #[serde(flatten)]
+    pub state: RenderState,
+    pub tasks: Vec<TaskHealth>,
+}
+
+/// Health of the whole display, as returned by
+/// [`Registry::health`](crate::registry::Registry::health)
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+
+    /// The thread drawing the renders, `None` until the first frame is drawn
+    pub render_thread: Option<ThreadHealth>,
+
+    /// The thread showing the frames on the panel, `None` when there is no hardware driver
+    pub driver_thread: Option<ThreadHealth>,
+
+    /// Frames drawn per second
+    pub fps: f64,
+    pub renders: Vec<RenderHealth>,
+}
+
+impl HealthReport {
+    pub(crate) fn new(
+        render_thread: Option<ThreadHealth>,
+        driver_thread: Option<ThreadHealth>,
+        fps: f64,
+        renders: Vec<RenderHealth>,
+    ) -> Self {
+        let stalled = [render_thread, driver_thread]
+            .iter()
+            .flatten()
+            .any(|thread| !thread.alive);
+        let degraded = renders.iter().any(|render| {
+            matches!(render.state, RenderState::Failed(_))
+                || render
+                    .tasks
+                    .iter()
+                    .any(|task| task.consecutive_failures > 0)
+        });
+
+        let status = if stalled {
+            HealthStatus::Down
+        } else if degraded {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Ok
+        };
+
+        Self {
+            status,
+            render_thread,
+            driver_thread,
+            fps,
+            renders,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FrameClock {
+    last_frame: Option<Instant>,
+    window_start: Option<Instant>,
+    window_frames: u32,
+    fps: f64,
+}
+
+impl FrameClock {
+    fn tick(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+        self.window_frames += 1;
+
+        let window_start = *self.window_start.get_or_insert(now);
+        let elapsed = now - window_start;
+        if elapsed >= FPS_WINDOW {
+            self.fps = self.window_frames as f64 / elapsed.as_secs_f64();
+            self.window_start = Some(now);
+            self.window_frames = 0;
+        }
+    }
+
+    fn thread_health(&self, now: Instant) -> Option<ThreadHealth> {
+        self.last_frame.map(|last_frame| {
+            let since = now - last_frame;
+            ThreadHealth {
+                alive: since < STALL_TIMEOUT,
+                last_frame_ms_ago: since.as_millis() as u64,
+            }
+        })
+    }
+
+    /// Frame rate over the last window, `0` once frames stopped coming
+    fn fps(&self, now: Instant) -> f64 {
+        match self.last_frame {
+            Some(last_frame) if now - last_frame < STALL_TIMEOUT => self.fps,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MonitorState {
+    render_frames: FrameClock,
+    driver_frames: FrameClock,
+}
+
+/// Keeps track of the frames drawn and shown
+#[derive(Default)]
+pub(crate) struct HealthMonitor {
+    state: Mutex<MonitorState>,
+}
+
+impl HealthMonitor {
+    /// Called by the render thread for every frame
+    pub(crate) fn frame_rendered(&self) {
+        self.state.lock().render_frames.tick(Instant::now());
+    }
+
+    /// Called by the driver thread for every frame shown on the panel
+    pub(crate) fn frame_displayed(&self) {
+        self.state.lock().driver_frames.tick(Instant::now());
+    }
+
+    pub(crate) fn render_thread(&self) -> Option<ThreadHealth> {
+        self.state
+            .lock()
+            .render_frames
+            .thread_health(Instant::now())
+    }
+
+    pub(crate) fn driver_thread(&self) -> Option<ThreadHealth> {
+        self.state
+            .lock()
+            .driver_frames
+            .thread_health(Instant::now())
+    }
+
+    pub(crate) fn fps(&self) -> f64 {
+        self.state.lock().render_frames.fps(Instant::now())
+    }
+}
+
+/// Asks a render about its health, a render that panics reports nothing
+pub(crate) fn collect<D>(uuid: Uuid, render: &dyn Render<D>) -> Vec<TaskHealth>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| render.health())).unwrap_or_else(|payload| {
+        warn!(
+            "Render {uuid} panicked reporting its health: {}",
+            panic_message(payload.as_ref())
+        );
+        Vec::new()
+    })
+}
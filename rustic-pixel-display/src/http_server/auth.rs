@@ -0,0 +1,163 @@
+use std::{fs::File, io::Read, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::warn;
+use serde::Deserialize;
+
+use super::ErrorJson;
+
+/// What a caller holding a token is allowed to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Can only query the state of the display (`GET` requests)
+    ReadOnly,
+
+    /// Can query and modify the state of the display
+    Admin,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEntry {
+    /// Human readable name of the token, only used for logging
+    name: String,
+    token: String,
+    scope: TokenScope,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenFile {
+    tokens: Vec<TokenEntry>,
+}
+
+/// The set of bearer tokens that are allowed to access the HTTP API.
+///
+/// Tokens are read from a JSON file of the following form:
+///
+/// ```json
+/// {
+///   "tokens": [
+///     { "name": "dashboard", "token": "<random string>", "scope": "read_only" },
+///     { "name": "admin", "token": "<random string>", "scope": "admin" }
+///   ]
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ApiTokens {
+    tokens: Vec<TokenEntry>,
+}
+
+impl ApiTokens {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Could not open API token file \"{}\"", path.display()))?;
+
+        Self::from_reader(file)
+            .with_context(|| format!("Could not parse API token file \"{}\"", path.display()))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let token_file: TokenFile = serde_json::from_reader(reader)?;
+
+        if let Some(entry) = token_file
+            .tokens
+            .iter()
+            .find(|entry| entry.token.is_empty())
+        {
+            anyhow::bail!("Token \"{}\" can not be empty", entry.name);
+        }
+
+        Ok(Self {
+            tokens: token_file.tokens,
+        })
+    }
+
+    /// Returns the scope of the provided token, or `None` if the token is not recognized.
+    fn scope_of(&self, token: &str) -> Option<TokenScope> {
+        // Compare against every token so the time taken doesn't reveal which token (if any) matched
+        self.tokens.iter().fold(None, |scope, entry| {
+            if constant_time_eq(entry.token.as_bytes(), token.as_bytes()) {
+                scope.max(Some(entry.scope))
+            } else {
+                scope
+            }
+        })
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(WWW_AUTHENTICATE, "Bearer")],
+        Json(ErrorJson {
+            description: "A valid bearer token is required".to_owned(),
+            cause: None,
+        }),
+    )
+        .into_response()
+}
+
+fn forbidden() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Json(ErrorJson {
+            description: "The provided token is not allowed to modify the display".to_owned(),
+            cause: None,
+        }),
+    )
+        .into_response()
+}
+
+/// Middleware that rejects requests without a valid bearer token.
+///
+/// Requests that don't carry a recognized token get a `401 Unauthorized`. Read-only tokens are
+/// limited to `GET` and `HEAD` requests, anything else results in a `403 Forbidden`.
+pub(super) async fn require_token(
+    State(api_tokens): State<Arc<ApiTokens>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let scope = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .and_then(|token| api_tokens.scope_of(token.trim()));
+
+    let required_scope = match *request.method() {
+        Method::GET | Method::HEAD => TokenScope::ReadOnly,
+        _ => TokenScope::Admin,
+    };
+
+    match scope {
+        None => {
+            warn!(
+                "Rejected unauthenticated {} request to {}",
+                request.method(),
+                request.uri().path()
+            );
+            unauthorized()
+        }
+        Some(scope) if scope < required_scope => forbidden(),
+        Some(_) => next.run(request).await,
+    }
+}
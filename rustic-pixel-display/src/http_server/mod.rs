@@ -1,40 +1,78 @@
-use std::{convert::Infallible, io::Read, net::ToSocketAddrs, sync::Arc};
-
-use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
-use parking_lot::Mutex;
-use rouille::{input::json::JsonError, router, try_or_400, try_or_404, Request, Response, Server};
-use serde::Serialize;
-use tokio::runtime::Handle;
-use try_or_400::ErrJson;
+use std::{collections::BTreeMap, convert::Infallible, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, MatchedPath, Path, Request, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, RgbColor},
+};
+use futures_util::StreamExt;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use crate::{
+    color::Color,
+    health::HealthStatus,
+    metrics,
+    notification::{Notification, Priority},
+    playlist::Playlist,
     registry::{Registry, RegistryError},
     render::RenderFactory,
+    selection::SmartSelection,
 };
 
-fn json_input_to_reader(request: &Request) -> Result<impl Read + '_, JsonError> {
-    if let Some(header) = request.header("Content-Type") {
-        if !header.starts_with("application/json") {
-            return Err(JsonError::WrongContentType);
+mod auth;
+
+pub use auth::{ApiTokens, TokenScope};
+
+/// Largest render configuration that can be uploaded, configurations can carry images and
+/// WebAssembly modules encoded in base64
+const MAX_CONFIG_SIZE: usize = 16 * 1024 * 1024;
+
+/// JSON body returned alongside a `400 Bad Request`
+#[derive(Serialize)]
+struct ErrorJson {
+    description: String,
+    cause: Option<String>,
+}
+
+impl ErrorJson {
+    fn from_err<E: std::error::Error>(error: &E) -> Self {
+        Self {
+            description: error.to_string(),
+            cause: error.source().map(|source| source.to_string()),
         }
-    } else {
-        return Err(JsonError::WrongContentType);
     }
 
-    if let Some(body) = request.data() {
-        Ok(body)
-    } else {
-        Err(JsonError::BodyAlreadyExtracted)
+    fn bad_request(description: &str) -> Response {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(Self {
+                description: description.to_owned(),
+                cause: None,
+            }),
+        )
+            .into_response()
     }
 }
 
-#[derive(Serialize)]
-struct FactoryEntry<'a> {
-    name: &'a str,
-    description: &'a str,
-}
-
 #[derive(Serialize)]
 struct RenderEntry<'a> {
     id: String,
@@ -46,6 +84,32 @@ struct LoadResponse {
     id: String,
 }
 
+fn white() -> Color {
+    Color(Rgb888::WHITE)
+}
+
+fn default_duration_secs() -> u64 {
+    10
+}
+
+/// Body of a `POST /notify` request
+#[derive(Deserialize)]
+struct NotifyRequest {
+    text: String,
+
+    /// BMP image encoded in base64
+    icon: Option<String>,
+
+    #[serde(default = "white")]
+    color: Color,
+
+    #[serde(default)]
+    priority: Priority,
+
+    #[serde(default = "default_duration_secs")]
+    duration_secs: u64,
+}
+
 #[derive(Serialize)]
 enum LayoutValues {
     Single,
@@ -60,90 +124,501 @@ struct LayoutConfig<'a> {
     renders: Vec<RenderEntry<'a>>,
 }
 
-pub struct HttpInstance<F, D>
+/// The playlists along with the one that is playing
+#[derive(Serialize)]
+struct PlaylistsResponse {
+    playlists: BTreeMap<String, Playlist>,
+    playing: Option<String>,
+}
+
+/// State shared between all the request handlers
+struct ApiState<F, D>
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
     F: RenderFactory<D>,
 {
     factory_registry: Registry<F, D>,
+
+    /// Cancelled when the server shuts down, ends the streams that would otherwise keep it alive
+    shutdown: CancellationToken,
 }
 
-pub fn build_api_server<A, D, F>(
-    addr: A,
-    runtime: Handle,
-    factory_registry: Arc<Mutex<Registry<F, D>>>,
-) -> Server<impl Send + Sync + 'static + Fn(&Request) -> Response>
+impl<F, D> Clone for ApiState<F, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            factory_registry: self.factory_registry.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
+
+async fn render_active<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    Json(state.factory_registry.renders()).into_response()
+}
+
+async fn render_unload<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(uuid): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    // A malformed UUID can't match any render, so it is treated the same as an unknown render
+    let Ok(uuid) = Uuid::parse_str(&uuid) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match state.factory_registry.unload(uuid) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn factory_discovery<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    Json(state.factory_registry.factories()).into_response()
+}
+
+async fn factory_details(Path(_factory_name): Path<String>) -> Response {
+    // TODO: Implement
+    StatusCode::BAD_REQUEST.into_response()
+}
+
+async fn factory_load<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(render_name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response
 where
-    A: ToSocketAddrs,
     D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static,
-    F: RenderFactory<D> + 'static,
-{
-    Server::new(addr, move |request| {
-        let mut registry_unlock = factory_registry.lock();
-
-        // This request will be processed in rouille's executor. Because of this, we need to ensure that
-        // any async task that are launched are tied to our tokio runtime. The enter() ensures that if a task
-        // is spawned, it will be spawned on this runtime.
-        let _guard = runtime.enter();
-
-        router!(request,
-            (GET) (/render/active) => {
-                Response::json(
-                    &registry_unlock
-                        .render_iter()
-                        .map(|(uuid, render)| RenderEntry {
-                            id: uuid.to_string(),
-                            factory_name: &render.factory_name,
-                        })
-                        .collect::<Vec<_>>(),
-                )
-            },
-            (DELETE) (/render/{uuid: Uuid}) => {
-                try_or_404!(registry_unlock.unload(uuid));
-                Response::empty_204()
-            },
-            (GET) (/factory/discovery) => {
-                Response::json(
-                    &registry_unlock
-                        .factory_iter()
-                        .map(|(_, factory)| FactoryEntry {
-                            name: factory.render_name(),
-                            description: factory.render_description(),
-                        })
-                        .collect::<Vec<_>>(),
-                )
-            },
-            (GET) (/factory/details/{_factory_name: String}) => {
-                // TODO: Implement
-                Response::empty_400()
-            },
-            (POST) (/factory/load/{render_name: String}) => {
-                // Attempt to read the JSON input from the request body
-                let json_reader = try_or_400!(json_input_to_reader(request));
-
-                // Attempt to load the render into the registry
-                let uuid = match registry_unlock.load(&render_name, json_reader) {
-                    Ok(uuid) => uuid,
-                    Err(e) => match e {
-                        RegistryError::FactoryNotFound(_) => return Response::empty_404(),
-                        _ => {
-                            let json_error = ErrJson::from_err(&e);
-                            return Response::json(&json_error).with_status_code(400);
-                        }
-                    }
-                };
-
-                Response::json(&LoadResponse {
-                    id: uuid.to_string()
-                })
-            },
-            (POST) (/layout_manager/select/{uuid: Uuid}) => {
-                try_or_404!(registry_unlock.select(uuid));
-                Response::empty_204()
-            },
-            // If none of the other blocks matches the request, return a 404 response.
-            _ => Response::empty_404()
-        )
+    F: RenderFactory<D> + Send + Sync + 'static,
+{
+    // The configuration must be provided as a JSON document
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|header| header.to_str().ok())
+        .map(|header| header.starts_with("application/json"))
+        .unwrap_or(false);
+
+    if !is_json {
+        return ErrorJson::bad_request("the request didn't have a JSON content type");
+    }
+
+    // Start loading the render, the factory runs in the background so the caller has to poll
+    // /render/active to find out if the render was constructed successfully
+    let uuid = match state.factory_registry.load(&render_name, body.as_ref()) {
+        Ok(uuid) => uuid,
+        Err(e) => match e {
+            RegistryError::FactoryNotFound(_) => return StatusCode::NOT_FOUND.into_response(),
+            _ => {
+                return (StatusCode::BAD_REQUEST, Json(ErrorJson::from_err(&e))).into_response();
+            }
+        },
+    };
+
+    Json(LoadResponse {
+        id: uuid.to_string(),
+    })
+    .into_response()
+}
+
+async fn layout_manager_select<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(uuid): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    // A malformed UUID can't match any render, so it is treated the same as an unknown render
+    let Ok(uuid) = Uuid::parse_str(&uuid) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match state.factory_registry.select(uuid) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn notify_list<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    Json(state.factory_registry.notifications()).into_response()
+}
+
+async fn notify_post<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Json(request): Json<NotifyRequest>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    let NotifyRequest {
+        text,
+        icon,
+        color,
+        priority,
+        duration_secs,
+    } = request;
+
+    let notification = Notification::new(text, color.0, Duration::from_secs(duration_secs.max(1)))
+        .with_priority(priority);
+
+    let notification = match icon {
+        Some(icon) => {
+            let Ok(icon) = STANDARD.decode(icon) else {
+                return ErrorJson::bad_request("the icon is not valid base64");
+            };
+
+            match notification.with_icon(icon) {
+                Ok(notification) => notification,
+                Err(e) => return ErrorJson::bad_request(&e.to_string()),
+            }
+        }
+        None => notification,
+    };
+
+    match state.factory_registry.notify(notification) {
+        Ok(uuid) => Json(LoadResponse {
+            id: uuid.to_string(),
+        })
+        .into_response(),
+        Err(e) => (StatusCode::TOO_MANY_REQUESTS, Json(ErrorJson::from_err(&e))).into_response(),
+    }
+}
+
+async fn notify_dismiss<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(uuid): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    // A malformed UUID can't match any notification, so it is treated the same as an unknown one
+    let Ok(uuid) = Uuid::parse_str(&uuid) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match state.factory_registry.dismiss_notification(uuid) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn smart_selection<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    Json(state.factory_registry.smart_selection()).into_response()
+}
+
+async fn smart_selection_enable<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Json(smart_selection): Json<SmartSelection>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    state
+        .factory_registry
+        .set_smart_selection(Some(smart_selection));
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn smart_selection_disable<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    state.factory_registry.set_smart_selection(None);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn playlists<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    Json(PlaylistsResponse {
+        playlists: state.factory_registry.playlists(),
+        playing: state.factory_registry.playing(),
     })
-    .unwrap()
+    .into_response()
+}
+
+async fn playlist_save<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(name): Path<String>,
+    Json(playlist): Json<Playlist>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    match state.factory_registry.set_playlist(&name, playlist) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(ErrorJson::from_err(&e))).into_response(),
+    }
+}
+
+async fn playlist_remove<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(name): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    match state.factory_registry.remove_playlist(&name) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn playlist_play<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(name): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    match state.factory_registry.play(Some(&name)) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn playlist_stop<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    match state.factory_registry.play(None) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(ErrorJson::from_err(&e))).into_response(),
+    }
+}
+
+async fn display_settings<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    Json(state.factory_registry.display_settings()).into_response()
+}
+
+async fn display_power<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(power): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    let power = match power.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return ErrorJson::bad_request("the power state must be either \"on\" or \"off\""),
+    };
+
+    state.factory_registry.set_power(power);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn display_brightness<F, D>(
+    State(state): State<ApiState<F, D>>,
+    Path(brightness): Path<String>,
+) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    let Ok(brightness) = brightness.parse::<u8>() else {
+        return ErrorJson::bad_request("the brightness must be a value between 0 and 255");
+    };
+
+    state.factory_registry.set_brightness(brightness);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Streams the changes made to the registry as server-sent events.
+///
+/// Each event is named after the [`RegistryEvent`](crate::registry::RegistryEvent) variant and
+/// carries it as JSON. A client that falls too far behind receives a `lagged` event with the
+/// number of events it missed, it should refresh its state through the other endpoints. The
+/// stream ends when the server shuts down.
+async fn events<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    let stream = BroadcastStream::new(state.factory_registry.subscribe())
+        .map(|event| match event {
+            Ok(event) => Event::default().event(event.name()).json_data(&event),
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                Ok(Event::default().event("lagged").data(missed.to_string()))
+            }
+        })
+        .take_until(state.shutdown.cancelled_owned());
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+async fn health<F, D>(State(state): State<ApiState<F, D>>) -> Response
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    let health = state.factory_registry.health();
+
+    // Monitors only look at the status code, a display that stopped drawing is unavailable
+    let status_code = match health.status {
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        HealthStatus::Ok | HealthStatus::Degraded => StatusCode::OK,
+    };
+
+    (status_code, Json(health)).into_response()
+}
+
+async fn metrics_export() -> Response {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::encode(),
+    )
+        .into_response()
+}
+
+/// Counts every request by the route it matched and the status it was answered with
+async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |matched_path| matched_path.as_str())
+        .to_owned();
+    let method = request.method().clone();
+
+    let response = next.run(request).await;
+    metrics::record_http_request(&route, method.as_str(), response.status().as_u16());
+
+    response
+}
+
+/// Builds the router that serves the REST API for the provided registry.
+///
+/// When `api_tokens` is provided every request must carry one of the tokens as a bearer token,
+/// otherwise the API is open to anyone that can reach it. Streamed responses end once `shutdown`
+/// is cancelled.
+pub fn build_api_server<D, F>(
+    factory_registry: Registry<F, D>,
+    api_tokens: Option<ApiTokens>,
+    shutdown: CancellationToken,
+) -> Router
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static,
+    F: RenderFactory<D> + Send + Sync + 'static,
+{
+    // If none of the routes matches the request, the router will return a 404 response
+    let router = Router::new()
+        .route("/render/active", get(render_active::<F, D>))
+        .route("/render/:uuid", delete(render_unload::<F, D>))
+        .route("/factory/discovery", get(factory_discovery::<F, D>))
+        .route("/factory/details/:factory_name", get(factory_details))
+        .route(
+            "/factory/load/:render_name",
+            post(factory_load::<F, D>).layer(DefaultBodyLimit::max(MAX_CONFIG_SIZE)),
+        )
+        .route(
+            "/layout_manager/select/:uuid",
+            post(layout_manager_select::<F, D>),
+        )
+        .route(
+            "/layout_manager/smart",
+            get(smart_selection::<F, D>)
+                .post(smart_selection_enable::<F, D>)
+                .delete(smart_selection_disable::<F, D>),
+        )
+        .route("/layout_manager/playlists", get(playlists::<F, D>))
+        .route(
+            "/layout_manager/playlists/:name",
+            put(playlist_save::<F, D>).delete(playlist_remove::<F, D>),
+        )
+        .route("/layout_manager/play", delete(playlist_stop::<F, D>))
+        .route("/layout_manager/play/:name", post(playlist_play::<F, D>))
+        .route("/display", get(display_settings::<F, D>))
+        .route("/display/power/:power", post(display_power::<F, D>))
+        .route(
+            "/display/brightness/:brightness",
+            post(display_brightness::<F, D>),
+        )
+        .route(
+            "/notify",
+            get(notify_list::<F, D>).post(notify_post::<F, D>),
+        )
+        .route("/notify/:uuid", delete(notify_dismiss::<F, D>))
+        .route("/events", get(events::<F, D>))
+        .route("/health", get(health::<F, D>))
+        .route("/metrics", get(metrics_export))
+        .with_state(ApiState {
+            factory_registry,
+            shutdown,
+        });
+
+    let router = match api_tokens {
+        Some(api_tokens) => router.layer(middleware::from_fn_with_state(
+            Arc::new(api_tokens),
+            auth::require_token,
+        )),
+        None => {
+            warn!("No API tokens were provided, the HTTP API does not require authentication");
+            router
+        }
+    };
+
+    // Added last so requests rejected for their token are counted as well
+    router.layer(middleware::from_fn(track_requests))
+}
+
+/// Serves the REST API on the provided listener until `shutdown` is cancelled.
+///
+/// In-flight requests are allowed to complete once the shutdown has been requested.
+pub async fn serve_api<D, F>(
+    listener: TcpListener,
+    factory_registry: Registry<F, D>,
+    api_tokens: Option<ApiTokens>,
+    shutdown: CancellationToken,
+) -> Result<()>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static,
+    F: RenderFactory<D> + Send + Sync + 'static,
+{
+    let router = build_api_server(factory_registry, api_tokens, shutdown.clone());
+
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await?;
+
+    Ok(())
 }
@@ -0,0 +1,209 @@
+use anyhow::{anyhow, bail, Context, Result};
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Repeat};
+use image::{
+    imageops::{self, FilterType},
+    io::{Limits, Reader},
+    ImageFormat, Rgba, RgbaImage,
+};
+use std::{io::Cursor, time::Duration};
+
+/// Larger images are shrunk when they are loaded, displays are far smaller than this and scaling
+/// the frames for every new canvas size stays cheap
+const MAX_SOURCE_SIZE: u32 = 1024;
+
+/// Upper bound on the memory taken by the decoded frames of an animation
+const MAX_DECODED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Images wider or taller than this are rejected before they are decoded
+const MAX_DIMENSION: u32 = 16 * 1024;
+
+/// Browsers play frames shorter than this at [`DEFAULT_FRAME_DELAY`], so GIFs are made with that
+/// in mind
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+pub(super) struct Frame {
+    pub(super) image: RgbaImage,
+
+    /// How long the frame is shown for
+    pub(super) delay: Duration,
+}
+
+/// The frames of an image, still images have a single frame
+pub(super) struct Animation {
+    pub(super) frames: Vec<Frame>,
+
+    /// Number of times the frames are played, `None` plays them forever
+    plays: Option<u32>,
+}
+
+impl Animation {
+    /// Decodes a PNG, BMP, JPEG or GIF image, the format is detected from the content
+    pub(super) fn decode(data: &[u8]) -> Result<Self> {
+        let format = image::guess_format(data).context("Unrecognized image format")?;
+
+        let animation = match format {
+            ImageFormat::Gif => Self::decode_gif(data)?,
+            ImageFormat::Png | ImageFormat::Bmp | ImageFormat::Jpeg => {
+                let image = Self::decode_still(data, format)?;
+                Self {
+                    frames: vec![Frame {
+                        image,
+                        delay: Duration::ZERO,
+                    }],
+                    plays: Some(1),
+                }
+            }
+            format => bail!("Unsupported image format {format:?}"),
+        };
+
+        Ok(animation.shrink())
+    }
+
+    fn decode_still(data: &[u8], format: ImageFormat) -> Result<RgbaImage> {
+        // The header is enough to tell how large the image is, so nothing is allocated for images
+        // that would be rejected
+        let (width, height) = Reader::with_format(Cursor::new(data), format).into_dimensions()?;
+        if width > MAX_DIMENSION
+            || height > MAX_DIMENSION
+            || width as usize * height as usize * 4 > MAX_DECODED_BYTES
+        {
+            bail!("Image is too large, it is {width}x{height}");
+        }
+
+        // The header could be lying, the decoder is limited as well
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_DIMENSION);
+        limits.max_image_height = Some(MAX_DIMENSION);
+        limits.max_alloc = Some(MAX_DECODED_BYTES as u64);
+
+        let mut reader = Reader::with_format(Cursor::new(data), format);
+        reader.limits(limits);
+        Ok(reader.decode()?.into_rgba8())
+    }
+
+    fn decode_gif(data: &[u8]) -> Result<Self> {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = options.read_info(Cursor::new(data))?;
+
+        let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+        let frame_bytes = width as usize * height as usize * 4;
+        if frame_bytes > MAX_DECODED_BYTES {
+            bail!("Animation is too large, it is {width}x{height}");
+        }
+
+        // Frames of a GIF usually only hold the area that changed since the previous frame, they
+        // are drawn on top of each other onto the screen
+        let mut screen = RgbaImage::new(width, height);
+        let mut frames = Vec::new();
+
+        while let Some(frame) = decoder.read_next_frame()? {
+            if (frames.len() + 1) * frame_bytes > MAX_DECODED_BYTES {
+                bail!(
+                    "Animation is too large, it has more than {} frames",
+                    frames.len()
+                );
+            }
+
+            let previous = (frame.dispose == DisposalMethod::Previous).then(|| screen.clone());
+            let (left, top) = (frame.left as u32, frame.top as u32);
+
+            let area = RgbaImage::from_raw(
+                frame.width as u32,
+                frame.height as u32,
+                frame.buffer.to_vec(),
+            )
+            .ok_or_else(|| anyhow!("Frame {} is truncated", frames.len()))?;
+
+            for (x, y, pixel) in area.enumerate_pixels() {
+                let (x, y) = (left + x, top + y);
+                if pixel[3] != 0 && x < width && y < height {
+                    screen.put_pixel(x, y, *pixel);
+                }
+            }
+
+            let delay = Duration::from_millis(frame.delay as u64 * 10);
+            frames.push(Frame {
+                image: screen.clone(),
+                delay: if delay < MIN_FRAME_DELAY {
+                    DEFAULT_FRAME_DELAY
+                } else {
+                    delay
+                },
+            });
+
+            // Prepare the screen for the next frame
+            match (frame.dispose, previous) {
+                (DisposalMethod::Background, _) => {
+                    for y in top..(top + frame.height as u32).min(height) {
+                        for x in left..(left + frame.width as u32).min(width) {
+                            screen.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                        }
+                    }
+                }
+                (DisposalMethod::Previous, Some(previous)) => screen = previous,
+                _ => {}
+            }
+        }
+
+        if frames.is_empty() {
+            bail!("GIF doesn't have any frames");
+        }
+
+        // A GIF without a loop count is played once
+        let plays = match decoder.repeat() {
+            Repeat::Infinite => None,
+            Repeat::Finite(repeats) => Some(repeats as u32 + 1),
+        };
+
+        Ok(Self { frames, plays })
+    }
+
+    fn shrink(mut self) -> Self {
+        for frame in &mut self.frames {
+            let (width, height) = frame.image.dimensions();
+            let largest = width.max(height);
+
+            if largest > MAX_SOURCE_SIZE {
+                let shrink = |length: u32| (length * MAX_SOURCE_SIZE / largest).max(1);
+                frame.image = imageops::resize(
+                    &frame.image,
+                    shrink(width),
+                    shrink(height),
+                    FilterType::Triangle,
+                );
+            }
+        }
+
+        self
+    }
+
+    /// Index of the frame shown `elapsed` after the animation started, the last frame stays on
+    /// once the animation played the number of times it asks for
+    pub(super) fn frame_at(&self, elapsed: Duration) -> usize {
+        let Self { frames, plays } = self;
+
+        let cycle: Duration = frames.iter().map(|frame| frame.delay).sum();
+        if cycle.is_zero() {
+            return 0;
+        }
+
+        let finished = plays
+            .and_then(|plays| cycle.checked_mul(plays))
+            .is_some_and(|total| elapsed >= total);
+        if finished {
+            return frames.len() - 1;
+        }
+
+        let mut time = Duration::from_nanos((elapsed.as_nanos() % cycle.as_nanos()) as u64);
+        for (index, frame) in frames.iter().enumerate() {
+            if time < frame.delay {
+                return index;
+            }
+            time -= frame.delay;
+        }
+
+        frames.len() - 1
+    }
+}
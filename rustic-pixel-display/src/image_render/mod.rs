@@ -0,0 +1,223 @@
+use crate::render::{Render, RenderFactory};
+use animation::Animation;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, Size},
+    Pixel,
+};
+use image::{
+    imageops::{self, FilterType},
+    RgbaImage,
+};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{convert::Infallible, io::Read, time::Instant};
+
+mod animation;
+
+/// How the image is sized to the canvas
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Scaling {
+    /// The whole image is shown as large as possible, the canvas is left black where the aspect
+    /// ratios differ
+    #[default]
+    Fit,
+
+    /// The image covers the whole canvas, the parts that don't fit are cut off
+    Fill,
+
+    /// The image keeps its size and is placed in the middle of the canvas
+    Center,
+}
+
+/// How pixels are picked when the image is scaled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Filter {
+    /// Nearest when the image is enlarged, so pixel art stays sharp, and smooth when it is reduced
+    #[default]
+    Auto,
+    Nearest,
+    Smooth,
+}
+
+#[derive(Deserialize)]
+struct ImageConfig {
+    /// A PNG, BMP, JPEG or (animated) GIF image, encoded in base64
+    image: String,
+
+    #[serde(default)]
+    scaling: Scaling,
+
+    #[serde(default)]
+    filter: Filter,
+}
+
+/// Constructs renders that show an uploaded image.
+///
+/// PNG, BMP, JPEG and GIF images are supported. Animated GIFs are played using the delays of
+/// their frames and looped as many times as they ask for, after which the last frame stays on.
+/// The image is scaled to the canvas it is drawn on, so it adapts to the display or the slot of
+/// the layout it is placed in.
+///
+/// ```json
+/// { "image": "R0lGODlhEAAQAPcAAAAAAP...", "scaling": "fit", "filter": "nearest" }
+/// ```
+#[derive(Default)]
+pub struct ImageRenderFactory;
+
+impl<D> RenderFactory<D> for ImageRenderFactory
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        "Image"
+    }
+
+    fn render_description(&self) -> &'static str {
+        "Shows an uploaded PNG, BMP, JPEG or animated GIF image"
+    }
+
+    fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
+        let config: ImageConfig = serde_json::from_reader(reader)?;
+        Ok(Box::new(ImageRender::new(config)?))
+    }
+}
+
+/// A frame that was scaled to the canvas, only the part that lands on the canvas is kept
+struct ScaledFrame {
+    image: RgbaImage,
+
+    /// Position of the top left corner of the image on the canvas
+    position: Point,
+}
+
+struct ImageRender {
+    animation: Animation,
+    scaling: Scaling,
+    filter: Filter,
+    started: Instant,
+
+    /// Frames scaled to the size of the canvas they were last drawn on
+    scaled: Mutex<Option<(Size, Vec<ScaledFrame>)>>,
+}
+
+impl ImageRender {
+    fn new(config: ImageConfig) -> Result<Self> {
+        let ImageConfig {
+            image,
+            scaling,
+            filter,
+        } = config;
+
+        let image = STANDARD
+            .decode(image)
+            .context("Image is not valid base64")?;
+
+        Ok(Self {
+            animation: Animation::decode(&image)?,
+            scaling,
+            filter,
+            started: Instant::now(),
+            scaled: Mutex::new(None),
+        })
+    }
+
+    fn scale(&self, image: &RgbaImage, canvas: Size) -> ScaledFrame {
+        let Self {
+            scaling, filter, ..
+        } = self;
+
+        let (width, height) = image.dimensions();
+        let horizontal = canvas.width as f64 / width as f64;
+        let vertical = canvas.height as f64 / height as f64;
+
+        let factor = match scaling {
+            Scaling::Fit => horizontal.min(vertical),
+            Scaling::Fill => horizontal.max(vertical),
+            Scaling::Center => 1.0,
+        };
+        let scaled_width = ((width as f64 * factor).round() as u32).max(1);
+        let scaled_height = ((height as f64 * factor).round() as u32).max(1);
+
+        let resized = if (scaled_width, scaled_height) == (width, height) {
+            image.clone()
+        } else {
+            let filter = match filter {
+                Filter::Auto if factor > 1.0 => FilterType::Nearest,
+                Filter::Auto | Filter::Smooth => FilterType::Triangle,
+                Filter::Nearest => FilterType::Nearest,
+            };
+            imageops::resize(image, scaled_width, scaled_height, filter)
+        };
+
+        // The image is centered, the parts that end up outside of the canvas are cut off
+        let x = (canvas.width as i64 - scaled_width as i64) / 2;
+        let y = (canvas.height as i64 - scaled_height as i64) / 2;
+
+        let visible = imageops::crop_imm(
+            &resized,
+            (-x).max(0) as u32,
+            (-y).max(0) as u32,
+            scaled_width.min(canvas.width),
+            scaled_height.min(canvas.height),
+        )
+        .to_image();
+
+        ScaledFrame {
+            image: visible,
+            position: Point::new(x.max(0) as i32, y.max(0) as i32),
+        }
+    }
+}
+
+/// Transparent pixels are blended with black, the display can't show what's behind them
+fn blend([red, green, blue, alpha]: [u8; 4]) -> Rgb888 {
+    let blend = |channel: u8| (channel as u16 * alpha as u16 / 255) as u8;
+    Rgb888::new(blend(red), blend(green), blend(blue))
+}
+
+impl<D> Render<D> for ImageRender
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        let bounding_box = canvas.bounding_box();
+        let mut scaled = self.scaled.lock();
+
+        // Frames are only scaled again when the canvas changes size (i.e. the render was moved
+        // to another slot of the layout)
+        if scaled
+            .as_ref()
+            .is_some_and(|(size, _)| *size != bounding_box.size)
+        {
+            *scaled = None;
+        }
+
+        let (_, frames) = scaled.get_or_insert_with(|| {
+            let frames = self
+                .animation
+                .frames
+                .iter()
+                .map(|frame| self.scale(&frame.image, bounding_box.size))
+                .collect();
+            (bounding_box.size, frames)
+        });
+
+        let ScaledFrame { image, position } =
+            &frames[self.animation.frame_at(self.started.elapsed())];
+        let origin = bounding_box.top_left + *position;
+
+        canvas.draw_iter(
+            image
+                .enumerate_pixels()
+                .filter(|(_, _, pixel)| pixel[3] != 0)
+                .map(|(x, y, pixel)| {
+                    Pixel(origin + Point::new(x as i32, y as i32), blend(pixel.0))
+                }),
+        )
+    }
+}
@@ -0,0 +1,41 @@
+//! BMP images embedded in render configurations, used by the renders that are configured at
+//! runtime.
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use embedded_graphics::pixelcolor::Rgb888;
+use std::collections::HashMap;
+use tinybmp::Bmp;
+
+/// Images keyed by name, every image is validated when the set is decoded
+#[derive(Default)]
+pub(crate) struct Images(HashMap<String, Vec<u8>>);
+
+impl Images {
+    /// Decodes images encoded in base64, fails if any of them isn't a valid BMP
+    pub(crate) fn decode(images: HashMap<String, String>) -> Result<Self> {
+        images
+            .into_iter()
+            .map(|(name, image)| {
+                let image = STANDARD
+                    .decode(image)
+                    .with_context(|| format!("Image \"{name}\" is not valid base64"))?;
+                Bmp::<Rgb888>::from_slice(&image)
+                    .map_err(|e| anyhow!("Image \"{name}\" is not a valid BMP: {e:?}"))?;
+
+                Ok((name, image))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Bmp<'_, Rgb888>> {
+        self.0
+            .get(name)
+            .and_then(|image| Bmp::from_slice(image).ok())
+    }
+}
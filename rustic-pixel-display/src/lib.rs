@@ -1,10 +1,33 @@
 // TODO: Remove when more mature
 #![allow(dead_code)]
 
+mod color;
 pub mod config;
+#[cfg(any(feature = "scripting", feature = "widgets"))]
+mod data_source;
 pub mod driver;
+pub mod font;
+pub mod health;
 #[cfg(feature = "http_server")]
 pub mod http_server;
+#[cfg(feature = "image_render")]
+pub mod image_render;
+#[cfg(any(feature = "scripting", feature = "widgets"))]
+mod images;
 pub mod layout_manager;
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod notification;
+pub mod playlist;
+pub mod plugin;
+pub mod provider;
 pub mod registry;
 pub mod render;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod selection;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "widgets")]
+pub mod widget;
@@ -0,0 +1,289 @@
+//! Counters and histograms describing the health of the display, exported in the Prometheus text
+//! format.
+//!
+//! Metrics are collected process wide, so renders can report how their data providers are doing
+//! without being handed anything by the registry.
+
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Upper bounds of the render duration buckets, in seconds
+const RENDER_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+
+/// Upper bounds of the provider fetch duration buckets, in seconds
+const FETCH_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+
+    /// Number of observations that fell in each bucket, the last bucket holds observations past
+    /// the largest bound
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// Writes the `_bucket`, `_sum` and `_count` series of the histogram, `labels` are added to
+    /// every series
+    fn encode(&self, output: &mut String, name: &str, labels: &str) {
+        let Self {
+            bounds,
+            buckets,
+            sum,
+            count,
+        } = self;
+
+        let mut cumulative = 0;
+        for (bound, bucket) in bounds.iter().zip(buckets) {
+            cumulative += bucket;
+            let _ = writeln!(
+                output,
+                "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(output, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+        let _ = writeln!(output, "{name}_sum{{{labels}}} {sum}");
+        let _ = writeln!(output, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+struct RenderMetrics {
+    factory_name: String,
+    duration: Histogram,
+}
+
+struct FetchMetrics {
+    successes: u64,
+    failures: u64,
+    duration: Histogram,
+}
+
+#[derive(Default)]
+struct Metrics {
+    frames_rendered: AtomicU64,
+    frames_displayed: AtomicU64,
+    frame_timeouts: AtomicU64,
+    renders: Mutex<BTreeMap<Uuid, RenderMetrics>>,
+
+    /// Requests by route, method and status
+    http_requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    fetches: Mutex<BTreeMap<&'static str, FetchMetrics>>,
+}
+
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Default::default)
+}
+
+pub(crate) fn frame_rendered() {
+    metrics().frames_rendered.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn frame_displayed() {
+    metrics().frames_displayed.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a frame the driver gave up waiting on
+pub(crate) fn frame_timed_out() {
+    metrics().frame_timeouts.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Starts exporting the metrics of a render that is being loaded
+pub(crate) fn track_render(uuid: Uuid, factory_name: &str) {
+    metrics().renders.lock().insert(
+        uuid,
+        RenderMetrics {
+            factory_name: factory_name.to_owned(),
+            duration: Histogram::new(RENDER_BUCKETS),
+        },
+    );
+}
+
+/// Records how long a loaded render took to draw a frame.
+///
+/// The render thread can still be drawing a render that was just unloaded, renders that aren't
+/// tracked anymore are skipped so their metrics don't come back.
+pub(crate) fn record_render(uuid: Uuid, duration: Duration) {
+    if let Some(render) = metrics().renders.lock().get_mut(&uuid) {
+        render.duration.observe(duration);
+    }
+}
+
+/// Stops exporting the metrics of a render that was unloaded
+pub(crate) fn forget_render(uuid: Uuid) {
+    metrics().renders.lock().remove(&uuid);
+}
+
+pub(crate) fn record_http_request(route: &str, method: &str, status: u16) {
+    *metrics()
+        .http_requests
+        .lock()
+        .entry((route.to_owned(), method.to_owned(), status))
+        .or_default() += 1;
+}
+
+/// Records the outcome of a request made to an external data provider (i.e. `"septa"`), along
+/// with how long it took
+pub fn record_fetch(provider: &'static str, duration: Duration, success: bool) {
+    let mut fetches = metrics().fetches.lock();
+    let fetch_metrics = fetches.entry(provider).or_insert_with(|| FetchMetrics {
+        successes: 0,
+        failures: 0,
+        duration: Histogram::new(FETCH_BUCKETS),
+    });
+
+    if success {
+        fetch_metrics.successes += 1;
+    } else {
+        fetch_metrics.failures += 1;
+    }
+    fetch_metrics.duration.observe(duration);
+}
+
+/// Escapes a label value as required by the Prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(output: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {name} {help}");
+    let _ = writeln!(output, "# TYPE {name} {kind}");
+}
+
+/// Returns every metric in the Prometheus text exposition format
+pub fn encode() -> String {
+    let Metrics {
+        frames_rendered,
+        frames_displayed,
+        frame_timeouts,
+        renders,
+        http_requests,
+        fetches,
+    } = metrics();
+
+    let mut output = String::new();
+
+    let counters = [
+        (
+            "rustic_pixel_frames_rendered_total",
+            "Frames drawn by the render thread",
+            frames_rendered,
+        ),
+        (
+            "rustic_pixel_frames_displayed_total",
+            "Frames shown on the display by the driver thread",
+            frames_displayed,
+        ),
+        (
+            "rustic_pixel_frame_timeouts_total",
+            "Times the driver thread timed out waiting for a frame from the render thread",
+            frame_timeouts,
+        ),
+    ];
+    for (name, help, counter) in counters {
+        write_header(&mut output, name, "counter", help);
+        let _ = writeln!(output, "{name} {}", counter.load(Ordering::Relaxed));
+    }
+
+    let name = "rustic_pixel_render_duration_seconds";
+    write_header(
+        &mut output,
+        name,
+        "histogram",
+        "Time loaded renders took to draw a frame",
+    );
+    for (uuid, render_metrics) in renders.lock().iter() {
+        let labels = format!(
+            "render=\"{uuid}\",factory=\"{}\"",
+            escape(&render_metrics.factory_name)
+        );
+        render_metrics.duration.encode(&mut output, name, &labels);
+    }
+
+    let name = "rustic_pixel_http_requests_total";
+    write_header(
+        &mut output,
+        name,
+        "counter",
+        "HTTP API requests by route and status",
+    );
+    for ((route, method, status), count) in http_requests.lock().iter() {
+        let _ = writeln!(
+            output,
+            "{name}{{route=\"{}\",method=\"{method}\",status=\"{status}\"}} {count}",
+            escape(route)
+        );
+    }
+
+    let fetches = fetches.lock();
+
+    let name = "rustic_pixel_provider_fetches_total";
+    write_header(
+        &mut output,
+        name,
+        "counter",
+        "Requests made to data providers by outcome",
+    );
+    for (provider, fetch_metrics) in fetches.iter() {
+        let provider = escape(provider);
+        let _ = writeln!(
+            output,
+            "{name}{{provider=\"{provider}\",result=\"success\"}} {}",
+            fetch_metrics.successes
+        );
+        let _ = writeln!(
+            output,
+            "{name}{{provider=\"{provider}\",result=\"failure\"}} {}",
+            fetch_metrics.failures
+        );
+    }
+
+    let name = "rustic_pixel_provider_fetch_duration_seconds";
+    write_header(
+        &mut output,
+        name,
+        "histogram",
+        "Time requests to data providers took",
+    );
+    for (provider, fetch_metrics) in fetches.iter() {
+        let labels = format!("provider=\"{}\"", escape(provider));
+        fetch_metrics.duration.encode(&mut output, name, &labels);
+    }
+
+    output
+}
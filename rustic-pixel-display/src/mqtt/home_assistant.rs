@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// Groups all the entities of the display under a single device in Home Assistant
+#[derive(Serialize)]
+pub(super) struct Device<'a> {
+    pub(super) identifiers: [&'a str; 1],
+    pub(super) name: &'a str,
+    pub(super) manufacturer: &'static str,
+    pub(super) model: &'static str,
+}
+
+/// Discovery payload of an [MQTT light](https://www.home-assistant.io/integrations/light.mqtt/)
+/// using the JSON schema
+#[derive(Serialize)]
+pub(super) struct LightConfig<'a> {
+    pub(super) name: &'static str,
+    pub(super) unique_id: String,
+    pub(super) schema: &'static str,
+    pub(super) command_topic: &'a str,
+    pub(super) state_topic: &'a str,
+    pub(super) availability_topic: &'a str,
+    pub(super) brightness: bool,
+    pub(super) brightness_scale: u8,
+    pub(super) device: &'a Device<'a>,
+}
+
+/// Discovery payload of an [MQTT select](https://www.home-assistant.io/integrations/select.mqtt/)
+#[derive(Serialize)]
+pub(super) struct SelectConfig<'a> {
+    pub(super) name: &'static str,
+    pub(super) unique_id: String,
+    pub(super) icon: &'static str,
+    pub(super) command_topic: &'a str,
+    pub(super) state_topic: &'a str,
+    pub(super) availability_topic: &'a str,
+    pub(super) options: Vec<String>,
+    pub(super) device: &'a Device<'a>,
+}
+
+/// State of the light, published to the light's state topic
+#[derive(Serialize)]
+pub(super) struct LightState {
+    pub(super) state: &'static str,
+    pub(super) brightness: u8,
+}
+
+/// Command sent by Home Assistant to the light's command topic, only the fields that are being
+/// changed are present
+#[derive(Deserialize)]
+pub(super) struct LightCommand {
+    pub(super) state: Option<String>,
+    pub(super) brightness: Option<u8>,
+}
@@ -0,0 +1,512 @@
+use std::{convert::Infallible, env::var, time::Duration};
+
+use anyhow::{Context, Result};
+use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    playlist::NO_PLAYLIST,
+    registry::{Registry, RegistryEvent, RenderInfo, RenderState},
+    render::RenderFactory,
+};
+
+use home_assistant::{Device, LightCommand, LightConfig, LightState, SelectConfig};
+
+mod home_assistant;
+
+/// How long to wait before reconnecting after the connection to the broker was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long to wait for the offline message to be delivered when shutting down
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_node_id() -> String {
+    "rustic_pixel_display".to_owned()
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_owned()
+}
+
+/// Connection details of the MQTT broker and how the display announces itself to Home Assistant
+#[derive(Clone, Debug, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Used as the MQTT client id, the root of all the display's topics and to build the unique
+    /// ids of the Home Assistant entities. Must be unique if there are multiple displays.
+    #[serde(default = "default_node_id")]
+    pub node_id: String,
+
+    /// Prefix Home Assistant listens on for discovery messages
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+impl MqttConfig {
+    pub fn new(host: String) -> Self {
+        Self {
+            host,
+            port: default_port(),
+            username: None,
+            password: None,
+            node_id: default_node_id(),
+            discovery_prefix: default_discovery_prefix(),
+        }
+    }
+
+    /// Builds the configuration from the `MQTT_*` environment variables.
+    ///
+    /// Returns `None` if `MQTT_HOST` is not set, meaning MQTT should not be used.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(host) = var("MQTT_HOST") else {
+            return Ok(None);
+        };
+
+        let mut config = Self::new(host);
+
+        if let Ok(port) = var("MQTT_PORT") {
+            config.port = port.parse().context("MQTT_PORT is not a valid port")?;
+        }
+
+        config.username = var("MQTT_USERNAME").ok();
+        config.password = var("MQTT_PASSWORD").ok();
+
+        if let Ok(node_id) = var("MQTT_NODE_ID") {
+            config.node_id = node_id;
+        }
+
+        if let Ok(discovery_prefix) = var("MQTT_DISCOVERY_PREFIX") {
+            config.discovery_prefix = discovery_prefix;
+        }
+
+        Ok(Some(config))
+    }
+}
+
+/// All the topics used by the display
+struct Topics {
+    availability: String,
+    light_config: String,
+    light_command: String,
+    light_state: String,
+    select_config: String,
+    select_command: String,
+    select_state: String,
+    playlist_config: String,
+    playlist_command: String,
+    playlist_state: String,
+}
+
+impl Topics {
+    fn new(config: &MqttConfig) -> Self {
+        let MqttConfig {
+            node_id,
+            discovery_prefix,
+            ..
+        } = config;
+
+        Self {
+            availability: format!("{node_id}/availability"),
+            light_config: format!("{discovery_prefix}/light/{node_id}/display/config"),
+            light_command: format!("{node_id}/display/set"),
+            light_state: format!("{node_id}/display/state"),
+            select_config: format!("{discovery_prefix}/select/{node_id}/render/config"),
+            select_command: format!("{node_id}/render/set"),
+            select_state: format!("{node_id}/render/state"),
+            playlist_config: format!("{discovery_prefix}/select/{node_id}/playlist/config"),
+            playlist_command: format!("{node_id}/playlist/set"),
+            playlist_state: format!("{node_id}/playlist/state"),
+        }
+    }
+}
+
+/// Name of a render as shown in Home Assistant, renders are only known by their id so the start of
+/// the id is included to tell apart renders that were created by the same factory.
+fn render_label(id: &Uuid, factory_name: &str) -> String {
+    let id = id.simple().to_string();
+    format!("{factory_name} ({})", &id[..8])
+}
+
+/// Translates between the MQTT topics and the operations of the [`Registry`].
+///
+/// Requests are queued with the non-blocking `try_*` methods of the client, the queue is only
+/// drained by polling the event loop, which happens in the same task.
+struct Bridge<F, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    client: AsyncClient,
+    node_id: String,
+    topics: Topics,
+    registry: Registry<F, D>,
+}
+
+impl<F, D> Bridge<F, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    fn publish<P: Serialize>(&self, topic: &str, payload: &P) -> Result<()> {
+        self.client
+            .try_publish(topic, QoS::AtLeastOnce, true, serde_json::to_vec(payload)?)?;
+
+        Ok(())
+    }
+
+    fn ready_renders(&self) -> Vec<RenderInfo> {
+        self.registry
+            .renders()
+            .into_iter()
+            .filter(|render_info| matches!(render_info.state, RenderState::Ready))
+            .collect()
+    }
+
+    /// Subscribes to the command topics and publishes the discovery messages along with the current
+    /// state. Needs to be done every time the connection to the broker is (re)established.
+    fn announce(&self) -> Result<()> {
+        let Self {
+            client,
+            node_id,
+            topics,
+            ..
+        } = self;
+
+        client.try_subscribe(&topics.light_command, QoS::AtLeastOnce)?;
+        client.try_subscribe(&topics.select_command, QoS::AtLeastOnce)?;
+        client.try_subscribe(&topics.playlist_command, QoS::AtLeastOnce)?;
+
+        let device = self.device();
+
+        self.publish(
+            &topics.light_config,
+            &LightConfig {
+                name: "Display",
+                unique_id: format!("{node_id}_display"),
+                schema: "json",
+                command_topic: &topics.light_command,
+                state_topic: &topics.light_state,
+                availability_topic: &topics.availability,
+                brightness: true,
+                brightness_scale: u8::MAX,
+                device: &device,
+            },
+        )?;
+
+        self.publish_select_config()?;
+        self.publish_playlist_config()?;
+
+        client.try_publish(&topics.availability, QoS::AtLeastOnce, true, "online")?;
+
+        self.publish_state()
+    }
+
+    /// Publishes the state of every entity
+    fn publish_state(&self) -> Result<()> {
+        self.publish_light_state()?;
+        self.publish_select_state()?;
+        self.publish_playlist_state()
+    }
+
+    /// The options of the select change whenever a render is loaded or unloaded, Home Assistant
+    /// picks up the new options when the discovery message is published again
+    fn publish_select_config(&self) -> Result<()> {
+        let Self {
+            node_id, topics, ..
+        } = self;
+
+        let options = self
+            .ready_renders()
+            .iter()
+            .map(|render_info| render_label(&render_info.id, &render_info.factory_name))
+            .collect();
+
+        self.publish(
+            &topics.select_config,
+            &SelectConfig {
+                name: "Render",
+                unique_id: format!("{node_id}_render"),
+                icon: "mdi:view-dashboard",
+                command_topic: &topics.select_command,
+                state_topic: &topics.select_state,
+                availability_topic: &topics.availability,
+                options,
+                device: &self.device(),
+            },
+        )
+    }
+
+    fn publish_light_state(&self) -> Result<()> {
+        let display_settings = self.registry.display_settings();
+
+        self.publish(
+            &self.topics.light_state,
+            &LightState {
+                state: if display_settings.power { "ON" } else { "OFF" },
+                brightness: display_settings.brightness,
+            },
+        )
+    }
+
+    fn publish_select_state(&self) -> Result<()> {
+        let selected = self.registry.selected().and_then(|uuid| {
+            self.registry
+                .renders()
+                .into_iter()
+                .find(|render_info| render_info.id == uuid)
+        });
+
+        // An empty retained message clears the state when nothing is selected
+        let payload = match selected {
+            Some(render_info) => render_label(&render_info.id, &render_info.factory_name),
+            None => String::new(),
+        };
+
+        self.client
+            .try_publish(&self.topics.select_state, QoS::AtLeastOnce, true, payload)?;
+
+        Ok(())
+    }
+
+    /// Like the render select, the options change whenever a playlist is created or removed
+    fn publish_playlist_config(&self) -> Result<()> {
+        let Self {
+            node_id, topics, ..
+        } = self;
+
+        let options = std::iter::once(NO_PLAYLIST.to_owned())
+            .chain(self.registry.playlists().into_keys())
+            .collect();
+
+        self.publish(
+            &topics.playlist_config,
+            &SelectConfig {
+                name: "Playlist",
+                unique_id: format!("{node_id}_playlist"),
+                icon: "mdi:playlist-play",
+                command_topic: &topics.playlist_command,
+                state_topic: &topics.playlist_state,
+                availability_topic: &topics.availability,
+                options,
+                device: &self.device(),
+            },
+        )
+    }
+
+    fn publish_playlist_state(&self) -> Result<()> {
+        let payload = self
+            .registry
+            .playing()
+            .unwrap_or_else(|| NO_PLAYLIST.to_owned());
+
+        self.client
+            .try_publish(&self.topics.playlist_state, QoS::AtLeastOnce, true, payload)?;
+
+        Ok(())
+    }
+
+    fn handle_command(&self, topic: &str, payload: &[u8]) {
+        if topic == self.topics.light_command {
+            let command: LightCommand = match serde_json::from_slice(payload) {
+                Ok(command) => command,
+                Err(e) => {
+                    warn!("Ignoring malformed light command: {e}");
+                    return;
+                }
+            };
+
+            match command.state.as_deref() {
+                Some("ON") => self.registry.set_power(true),
+                Some("OFF") => self.registry.set_power(false),
+                Some(state) => warn!("Ignoring unknown light state \"{state}\""),
+                None => {}
+            }
+
+            if let Some(brightness) = command.brightness {
+                self.registry.set_brightness(brightness);
+            }
+        } else if topic == self.topics.select_command {
+            let label = String::from_utf8_lossy(payload);
+
+            let render_info = self.ready_renders().into_iter().find(|render_info| {
+                render_label(&render_info.id, &render_info.factory_name) == label
+            });
+
+            match render_info {
+                Some(render_info) => {
+                    if let Err(e) = self.registry.select(render_info.id) {
+                        warn!("Could not select render \"{label}\": {e}");
+                    }
+                }
+                None => warn!("Ignoring selection of unknown render \"{label}\""),
+            }
+        } else if topic == self.topics.playlist_command {
+            let name = String::from_utf8_lossy(payload);
+            let playlist = Some(name.as_ref()).filter(|name| *name != NO_PLAYLIST);
+
+            if let Err(e) = self.registry.play(playlist) {
+                warn!("Could not play playlist \"{name}\": {e}");
+            }
+        } else {
+            debug!("Ignoring message on unexpected topic {topic}");
+        }
+    }
+
+    fn handle_registry_event(&self, event: RegistryEvent) -> Result<()> {
+        match event {
+            RegistryEvent::PowerChanged { .. } | RegistryEvent::BrightnessChanged { .. } => {
+                self.publish_light_state()
+            }
+            // Renders that panic drop out of the list of ready renders until they recover
+            RegistryEvent::RenderLoaded { .. }
+            | RegistryEvent::RenderFailed { .. }
+            | RegistryEvent::RenderRecovered { .. }
+            | RegistryEvent::RenderUnloaded { .. } => self.publish_select_config(),
+            RegistryEvent::SelectionChanged { .. } => self.publish_select_state(),
+            RegistryEvent::PlaylistsChanged => self.publish_playlist_config(),
+            RegistryEvent::PlaylistChanged { .. } => self.publish_playlist_state(),
+            RegistryEvent::SmartSelectionChanged { .. }
+            | RegistryEvent::NotificationPosted { .. }
+            | RegistryEvent::NotificationDismissed { .. }
+            | RegistryEvent::NotificationExpired { .. } => Ok(()),
+        }
+    }
+
+    fn device(&self) -> Device<'_> {
+        Device {
+            identifiers: [&self.node_id],
+            name: &self.node_id,
+            manufacturer: "Rustic Pixel Display",
+            model: "RGB LED Matrix",
+        }
+    }
+}
+
+/// Exposes the display to Home Assistant over MQTT until `shutdown` is cancelled.
+///
+/// The display is announced through [MQTT discovery] as a light, which controls the power and
+/// brightness of the display, and two selects, which control the render that is being displayed
+/// and the playlist that is playing. Commands are applied to the registry and any change made to
+/// the registry (including through the HTTP API) is published back to the broker.
+///
+/// [MQTT discovery]: https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
+pub async fn serve_mqtt<D, F>(
+    config: MqttConfig,
+    registry: Registry<F, D>,
+    shutdown: CancellationToken,
+) -> Result<()>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static,
+    F: RenderFactory<D> + Send + Sync + 'static,
+{
+    let topics = Topics::new(&config);
+
+    let mut options = MqttOptions::new(&config.node_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        &topics.availability,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    let (client, mut event_loop) = AsyncClient::new(options, 64);
+    let mut registry_events = registry.subscribe();
+
+    let bridge = Bridge {
+        client,
+        node_id: config.node_id,
+        topics,
+        registry,
+    };
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            event = event_loop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker {}:{}", config.host, config.port);
+
+                    if let Err(e) = bridge.announce() {
+                        warn!("Could not announce the display to the MQTT broker: {e}");
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    bridge.handle_command(&publish.topic, &publish.payload);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("MQTT connection error: {e}, reconnecting in {RECONNECT_DELAY:?}");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+            event = registry_events.recv() => {
+                let result = match event {
+                    Ok(event) => bridge.handle_registry_event(event),
+                    // Some changes were missed, publish everything again
+                    Err(RecvError::Lagged(_)) => bridge
+                        .publish_select_config()
+                        .and_then(|_| bridge.publish_playlist_config())
+                        .and_then(|_| bridge.publish_state()),
+                    Err(RecvError::Closed) => break,
+                };
+
+                // The state is published again on the next change or when reconnecting
+                if let Err(e) = result {
+                    warn!("Could not publish the display state to the MQTT broker: {e}");
+                }
+            }
+        }
+    }
+
+    // Let Home Assistant know the display is gone instead of waiting for the last will, the event
+    // loop has to be driven until the disconnect is sent out
+    let goodbye = bridge
+        .client
+        .try_publish(
+            &bridge.topics.availability,
+            QoS::AtLeastOnce,
+            true,
+            "offline",
+        )
+        .and_then(|_| bridge.client.try_disconnect());
+
+    if let Err(e) = goodbye {
+        warn!("Could not disconnect from the MQTT broker: {e}");
+        return Ok(());
+    }
+
+    let flush = async {
+        while let Ok(event) = event_loop.poll().await {
+            if let Event::Outgoing(Outgoing::Disconnect) = event {
+                break;
+            }
+        }
+    };
+
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, flush).await.is_err() {
+        warn!("Timed out disconnecting from the MQTT broker");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,439 @@
+use crate::registry::{EventSender, RegistryEvent};
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    image::Image,
+    mono_font::{ascii::FONT_6X10, MonoFont, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, DrawTargetExt, OriginDimensions, Point, Primitive, RgbColor, Size},
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use log::debug;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    time::{Duration, Instant},
+};
+use tinybmp::Bmp;
+use uuid::Uuid;
+
+/// Notifications that can wait to be shown, new notifications are rejected past this point
+const MAX_QUEUED: usize = 32;
+
+const FONT: MonoFont<'static> = FONT_6X10;
+
+/// Space between the content of a notification and its edges
+const PADDING: u32 = 2;
+
+/// Speed at which text that is too wide for the banner scrolls, in pixels per second
+const SCROLL_SPEED: f32 = 20.0;
+
+/// Blank space between the end of scrolling text and its next repetition
+const SCROLL_GAP: u32 = 24;
+
+/// How important a notification is, the most important notification is shown first
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+
+    /// Takes over the whole display instead of being shown as a banner
+    Urgent,
+}
+
+/// A transient message drawn on top of whatever the display is showing
+#[derive(Clone, Debug)]
+pub struct Notification {
+    text: String,
+
+    /// BMP image shown next to the text
+    icon: Option<Vec<u8>>,
+    color: Rgb888,
+    priority: Priority,
+
+    /// How long the notification stays on, counted from the moment it first appears
+    duration: Duration,
+}
+
+impl Notification {
+    pub fn new(text: impl Into<String>, color: Rgb888, duration: Duration) -> Self {
+        Self {
+            text: text.into(),
+            icon: None,
+            color,
+            priority: Priority::default(),
+            duration,
+        }
+    }
+
+    /// Shows a BMP image next to the text, fails if `icon` isn't a valid BMP
+    pub fn with_icon(mut self, icon: Vec<u8>) -> Result<Self> {
+        Bmp::<Rgb888>::from_slice(&icon).map_err(|e| anyhow!("Icon is not a valid BMP: {e:?}"))?;
+
+        self.icon = Some(icon);
+        Ok(self)
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn icon(&self) -> Option<Bmp<'_, Rgb888>> {
+        self.icon
+            .as_ref()
+            .and_then(|icon| Bmp::from_slice(icon).ok())
+    }
+}
+
+/// A point in time copy of a queued notification
+#[derive(Clone, Debug, Serialize)]
+pub struct NotificationInfo {
+    pub id: Uuid,
+    pub text: String,
+    pub priority: Priority,
+    pub duration_secs: u64,
+
+    /// If the notification was already shown, it can be hidden behind a more important one
+    pub shown: bool,
+}
+
+struct QueuedNotification {
+    id: Uuid,
+    notification: Notification,
+
+    /// Order in which the notifications were posted, breaks ties between equal priorities
+    sequence: u64,
+    shown_at: Option<Instant>,
+}
+
+impl QueuedNotification {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.shown_at
+            .is_some_and(|shown_at| now.duration_since(shown_at) >= self.notification.duration)
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    notifications: Vec<QueuedNotification>,
+    next_sequence: u64,
+}
+
+/// The notifications waiting to be shown, shared between the
+/// [`Registry`](crate::registry::Registry) and the [`RenderSet`](crate::registry::RenderSet)s it
+/// publishes.
+///
+/// A single notification is shown at a time: the one with the highest [`Priority`], and of those
+/// the one that was posted first. A more important notification hides the one being shown, whose
+/// time keeps running in the background.
+pub(crate) struct NotificationQueue {
+    state: Mutex<QueueState>,
+    events: EventSender,
+}
+
+impl NotificationQueue {
+    pub(crate) fn new(events: EventSender) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            events,
+        }
+    }
+
+    /// Queues `notification`, returns `None` if the queue is full
+    pub(crate) fn push(&self, notification: Notification) -> Option<Uuid> {
+        let id = Uuid::new_v4();
+        let priority = notification.priority;
+
+        {
+            let mut state = self.state.lock();
+            if state.notifications.len() >= MAX_QUEUED {
+                return None;
+            }
+
+            let sequence = state.next_sequence;
+            state.next_sequence += 1;
+            state.notifications.push(QueuedNotification {
+                id,
+                notification,
+                sequence,
+                shown_at: None,
+            });
+        }
+
+        debug!("Notification {id} was posted");
+        self.events
+            .publish(RegistryEvent::NotificationPosted { id, priority });
+        Some(id)
+    }
+
+    /// Removes a notification, whether it was shown or not. Returns `false` if it wasn't queued.
+    pub(crate) fn dismiss(&self, id: Uuid) -> bool {
+        let dismissed = {
+            let mut state = self.state.lock();
+            let count = state.notifications.len();
+            state.notifications.retain(|queued| queued.id != id);
+            state.notifications.len() != count
+        };
+
+        if dismissed {
+            self.events
+                .publish(RegistryEvent::NotificationDismissed { id });
+        }
+
+        dismissed
+    }
+
+    pub(crate) fn list(&self) -> Vec<NotificationInfo> {
+        let mut notifications: Vec<_> = self
+            .state
+            .lock()
+            .notifications
+            .iter()
+            .map(|queued| {
+                (
+                    queued.sequence,
+                    NotificationInfo {
+                        id: queued.id,
+                        text: queued.notification.text.clone(),
+                        priority: queued.notification.priority,
+                        duration_secs: queued.notification.duration.as_secs(),
+                        shown: queued.shown_at.is_some(),
+                    },
+                )
+            })
+            .collect();
+
+        // Listed in the order they are shown
+        notifications.sort_by_key(|(sequence, info)| (std::cmp::Reverse(info.priority), *sequence));
+        notifications.into_iter().map(|(_, info)| info).collect()
+    }
+
+    /// Draws the most important notification over the whole canvas, notifications that ran for
+    /// their duration are dropped first
+    pub(crate) fn render<D>(&self, canvas: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+
+        let mut expired = Vec::new();
+        state.notifications.retain(|queued| {
+            let is_expired = queued.is_expired(now);
+            if is_expired {
+                expired.push(queued.id);
+            }
+            !is_expired
+        });
+
+        let current = state.notifications.iter_mut().max_by_key(|queued| {
+            (
+                queued.notification.priority,
+                std::cmp::Reverse(queued.sequence),
+            )
+        });
+
+        if let Some(queued) = current {
+            let shown_at = *queued.shown_at.get_or_insert(now);
+            let elapsed = now.duration_since(shown_at);
+
+            match queued.notification.priority {
+                Priority::Urgent => draw_full_screen(&queued.notification, elapsed, canvas)?,
+                _ => draw_banner(&queued.notification, elapsed, canvas)?,
+            }
+        }
+
+        drop(state);
+
+        for id in expired {
+            debug!("Notification {id} expired");
+            self.events
+                .publish(RegistryEvent::NotificationExpired { id });
+        }
+
+        Ok(())
+    }
+}
+
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * (FONT.character_size.width + FONT.character_spacing)
+}
+
+/// Draws the notification as a strip along the bottom of the canvas, text that doesn't fit scrolls
+fn draw_banner<D>(
+    notification: &Notification,
+    elapsed: Duration,
+    canvas: &mut D,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    let bounding_box = canvas.bounding_box();
+    let icon = notification.icon();
+    let icon_size = icon.as_ref().map(|icon| icon.size()).unwrap_or_default();
+
+    let height = (FONT.character_size.height.max(icon_size.height) + PADDING * 2)
+        .min(bounding_box.size.height);
+    let banner = Rectangle::new(
+        bounding_box.top_left + Point::new(0, (bounding_box.size.height - height) as i32),
+        Size::new(bounding_box.size.width, height),
+    );
+
+    banner
+        .into_styled(PrimitiveStyle::with_fill(Rgb888::BLACK))
+        .draw(canvas)?;
+    Line::new(
+        banner.top_left,
+        banner.top_left + Point::new(banner.size.width as i32 - 1, 0),
+    )
+    .into_styled(PrimitiveStyle::with_stroke(notification.color, 1))
+    .draw(canvas)?;
+
+    let mut text_left = banner.top_left.x + PADDING as i32;
+    if let Some(icon) = &icon {
+        let top = banner.top_left.y + (height.saturating_sub(icon_size.height) / 2) as i32;
+        Image::new(icon, Point::new(text_left, top)).draw(&mut canvas.clipped(&banner))?;
+        text_left += (icon_size.width + PADDING) as i32;
+    }
+
+    let text_area = Rectangle::with_corners(
+        Point::new(text_left, banner.top_left.y + 1),
+        banner.bottom_right().unwrap_or(banner.top_left),
+    );
+    let width = text_width(&notification.text);
+    let top = banner.top_left.y + (height.saturating_sub(FONT.character_size.height) / 2) as i32;
+    let character_style = MonoTextStyle::new(&FONT, notification.color);
+    let mut text_canvas = canvas.clipped(&text_area);
+
+    if width <= text_area.size.width {
+        Text::with_baseline(
+            &notification.text,
+            Point::new(text_left, top),
+            character_style,
+            Baseline::Top,
+        )
+        .draw(&mut text_canvas)?;
+    } else {
+        // The text is drawn twice so the start of the next repetition follows the end of the text
+        let cycle = width + SCROLL_GAP;
+        let offset = (elapsed.as_secs_f32() * SCROLL_SPEED) as u32 % cycle;
+
+        for repetition in 0..2 {
+            let x = text_left + (repetition * cycle) as i32 - offset as i32;
+            Text::with_baseline(
+                &notification.text,
+                Point::new(x, top),
+                character_style,
+                Baseline::Top,
+            )
+            .draw(&mut text_canvas)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Breaks `text` into lines of at most `columns` characters, words longer than a line are split
+pub(crate) fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+
+            let line_length = line.chars().count();
+            if line_length > 0 && line_length + 1 + word.len() > columns {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            while word.len() > columns {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                lines.push(word.drain(..columns).collect());
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.extend(word);
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Draws the notification in place of everything else, with a border that flashes every second
+fn draw_full_screen<D>(
+    notification: &Notification,
+    elapsed: Duration,
+    canvas: &mut D,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    let bounding_box = canvas.bounding_box();
+    canvas.fill_solid(&bounding_box, Rgb888::BLACK)?;
+
+    if elapsed.as_millis() % 1000 < 500 {
+        bounding_box
+            .into_styled(PrimitiveStyle::with_stroke(notification.color, 1))
+            .draw(canvas)?;
+    }
+
+    let content = bounding_box.offset(-((PADDING + 1) as i32));
+    let center_x = content.center().x;
+    let mut top = content.top_left.y;
+
+    if let Some(icon) = notification.icon() {
+        let icon_size = icon.size();
+        Image::new(
+            &icon,
+            Point::new(center_x - icon_size.width as i32 / 2, top),
+        )
+        .draw(&mut canvas.clipped(&content))?;
+        top += (icon_size.height + PADDING) as i32;
+    }
+
+    let columns = content.size.width / (FONT.character_size.width + FONT.character_spacing);
+    let lines = wrap(&notification.text, columns as usize);
+    let line_height = FONT.character_size.height as i32;
+
+    // Text is centered in the space left under the icon
+    let free_height = (content.bottom_right().map_or(top, |corner| corner.y + 1) - top)
+        .saturating_sub(line_height * lines.len() as i32)
+        .max(0);
+    top += free_height / 2;
+
+    let character_style = MonoTextStyle::new(&FONT, notification.color);
+    let text_style = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Top)
+        .build();
+    let mut text_canvas = canvas.clipped(&content);
+
+    for (index, line) in lines.iter().enumerate() {
+        Text::with_text_style(
+            line,
+            Point::new(center_x, top + line_height * index as i32),
+            character_style,
+            text_style,
+        )
+        .draw(&mut text_canvas)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use crate::registry::{EventSender, RegistryEvent};
+use log::debug;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Stands for "no playlist" where a playlist is picked by name (e.g. the MQTT playlist select), so
+/// no playlist can be called that
+pub const NO_PLAYLIST: &str = "None";
+
+fn default_interval_secs() -> u64 {
+    30
+}
+
+/// Renders shown one after the other, in order, starting over after the last one
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub renders: Vec<Uuid>,
+
+    /// How long each render stays on the display
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+/// Keeps track of the render of the playing playlist that is on the display.
+///
+/// Like the [`SmartSelector`](crate::selection::SmartSelector), the next render is picked while
+/// drawing so the playlist advances without a task of its own.
+pub(crate) struct PlaylistPlayer {
+    /// Position in the playlist of the render on the display, the render and since when
+    shown: Mutex<Option<(usize, Uuid, Instant)>>,
+    events: EventSender,
+}
+
+impl PlaylistPlayer {
+    pub(crate) fn new(events: EventSender) -> Self {
+        Self {
+            shown: Mutex::new(None),
+            events,
+        }
+    }
+
+    /// Goes back to the start of the playlist on the next frame
+    pub(crate) fn reset(&self) {
+        *self.shown.lock() = None;
+    }
+
+    /// Returns the id of the render on the display
+    pub(crate) fn shown(&self) -> Option<Uuid> {
+        self.shown.lock().map(|(_, uuid, _)| uuid)
+    }
+
+    /// Picks the render of the `playlist` to show, skipping the renders that are not `ready`.
+    ///
+    /// Returns `None` if none of the renders of the playlist can be shown.
+    pub(crate) fn select(&self, playlist: &Playlist, ready: impl Fn(Uuid) -> bool) -> Option<Uuid> {
+        let now = Instant::now();
+        let mut shown = self.shown.lock();
+        let previous = shown.map(|(_, uuid, _)| uuid);
+
+        let interval = Duration::from_secs(playlist.interval_secs);
+        let current = shown.filter(|(index, uuid, since)| {
+            playlist.renders.get(*index) == Some(uuid) && ready(*uuid) && now - *since < interval
+        });
+
+        if current.is_none() {
+            // Starts from the top, or from the render after the one that was shown
+            let start = shown.map_or(0, |(index, _, _)| index + 1);
+            let count = playlist.renders.len();
+
+            *shown = (0..count)
+                .map(|offset| (start + offset) % count)
+                .map(|index| (index, playlist.renders[index]))
+                .find(|(_, uuid)| ready(*uuid))
+                .map(|(index, uuid)| (index, uuid, now));
+        }
+
+        let current = shown.map(|(_, uuid, _)| uuid);
+        drop(shown);
+
+        if current != previous {
+            debug!("Playlist moved on to render {current:?}");
+            self.events
+                .publish(RegistryEvent::SelectionChanged { id: current });
+        }
+
+        current
+    }
+}
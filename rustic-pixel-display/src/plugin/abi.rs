@@ -0,0 +1,68 @@
+//! The C ABI shared between the display and render factory plugins.
+//!
+//! A plugin is a shared library (i.e. a `cdylib`) that exports a function named
+//! `rustic_pixel_plugin` of type [`EntryFn`]. The function returns a pointer to a
+//! [`PluginDescriptor`] that must stay valid for as long as the library is loaded. The descriptor
+//! tells the display the name of the factory and provides the functions used to create, draw and
+//! destroy render instances.
+//!
+//! All strings are NUL terminated UTF-8. The functions of the descriptor can be called from
+//! different threads, but calls for the same render instance never overlap. Plugins must not let a
+//! panic (or exception) unwind across the ABI.
+
+use std::ffi::{c_char, c_void};
+
+/// Version of the ABI described in this module, plugins built against a different version are
+/// rejected
+pub const ABI_VERSION: u32 = 1;
+
+/// Name of the function every plugin must export
+pub const ENTRY_SYMBOL: &[u8] = b"rustic_pixel_plugin\0";
+
+/// Signature of the function every plugin must export
+pub type EntryFn = unsafe extern "C" fn() -> *const PluginDescriptor;
+
+/// A frame the plugin draws onto.
+///
+/// `pixels` points to `width * height * 3` bytes, row by row with each pixel being 3 bytes in red,
+/// green, blue order. The frame is cleared to black before every call to
+/// [`PluginDescriptor::render`].
+#[repr(C)]
+pub struct PluginFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: *mut u8,
+}
+
+#[repr(C)]
+pub struct PluginDescriptor {
+    /// Must be set to [`ABI_VERSION`]
+    pub abi_version: u32,
+
+    /// Unique name of the render factory, see [`RenderFactory::render_name`](crate::render::RenderFactory::render_name)
+    pub name: *const c_char,
+
+    /// Short description of the render, see [`RenderFactory::render_description`](crate::render::RenderFactory::render_description)
+    pub description: *const c_char,
+
+    /// Creates a render instance from a JSON configuration of `config_len` bytes.
+    ///
+    /// Returns an opaque pointer to the instance, or null if the configuration is invalid. On
+    /// failure a NUL terminated error message can be written into `error`, which is `error_len`
+    /// bytes long.
+    pub create: unsafe extern "C" fn(
+        config: *const u8,
+        config_len: usize,
+        error: *mut c_char,
+        error_len: usize,
+    ) -> *mut c_void,
+
+    /// Draws the current state of the instance onto `frame`
+    pub render: unsafe extern "C" fn(instance: *mut c_void, frame: *mut PluginFrame),
+
+    /// Destroys an instance returned by `create`, the instance is never used afterwards
+    pub destroy: unsafe extern "C" fn(instance: *mut c_void),
+}
+
+// The descriptor is immutable, this allows plugins written in Rust to declare it as a `static`
+unsafe impl Sync for PluginDescriptor {}
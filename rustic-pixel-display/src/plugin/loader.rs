@@ -0,0 +1,217 @@
+use super::abi::{EntryFn, PluginDescriptor, PluginFrame, ABI_VERSION, ENTRY_SYMBOL};
+use crate::render::{FrameBuffer, Render, RenderFactory};
+use anyhow::{anyhow, bail, Context, Result};
+use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
+use libloading::Library;
+use log::{info, warn};
+use parking_lot::Mutex;
+use std::{
+    convert::Infallible,
+    env::consts::DLL_EXTENSION,
+    ffi::{c_char, c_void, CStr},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Size of the buffer plugins can write an error message into
+const ERROR_LEN: usize = 512;
+
+/// A [`RenderFactory`] provided by a plugin loaded at runtime.
+///
+/// The library stays loaded for as long as the factory, or any render it constructed, is
+/// alive.
+pub struct PluginFactory {
+    name: &'static str,
+    description: &'static str,
+    path: PathBuf,
+    descriptor: *const PluginDescriptor,
+    library: Arc<Library>,
+}
+
+// The descriptor is immutable and the plugin ABI requires its functions to be callable from
+// any thread
+unsafe impl Send for PluginFactory {}
+unsafe impl Sync for PluginFactory {}
+
+/// Copies a string owned by the plugin. Factories live for the remainder of the program, so
+/// leaking the copy gives us the `'static` lifetime the [`RenderFactory`] trait requires.
+unsafe fn leak_string(string: *const c_char) -> Result<&'static str> {
+    if string.is_null() {
+        bail!("String is null");
+    }
+
+    let string = CStr::from_ptr(string)
+        .to_str()
+        .context("String is not valid UTF-8")?;
+    Ok(Box::leak(string.to_owned().into_boxed_str()))
+}
+
+impl PluginFactory {
+    /// Loads the plugin in the shared library at `path`.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialization code and the plugin's functions are trusted
+    /// to follow the ABI described in [`abi`](super::abi). Only load plugins from trusted
+    /// sources.
+    pub unsafe fn load(path: &Path) -> Result<Self> {
+        let library = Library::new(path).context("Could not load the library")?;
+
+        let entry = library
+            .get::<EntryFn>(ENTRY_SYMBOL)
+            .context("Library doesn't export rustic_pixel_plugin")?;
+
+        let descriptor = entry();
+        if descriptor.is_null() {
+            bail!("Plugin returned a null descriptor");
+        }
+
+        let abi_version = (*descriptor).abi_version;
+        if abi_version != ABI_VERSION {
+            bail!("Plugin was built for ABI version {abi_version}, expected {ABI_VERSION}");
+        }
+
+        Ok(Self {
+            name: leak_string((*descriptor).name).context("Invalid plugin name")?,
+            description: leak_string((*descriptor).description)
+                .context("Invalid plugin description")?,
+            path: path.to_owned(),
+            descriptor,
+            library: Arc::new(library),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<D> RenderFactory<D> for PluginFactory
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn render_description(&self) -> &'static str {
+        self.description
+    }
+
+    fn load_from_config<R: Read>(&self, mut reader: R) -> Result<Box<dyn Render<D>>> {
+        let mut config = Vec::new();
+        reader.read_to_end(&mut config)?;
+
+        let mut error = [0 as c_char; ERROR_LEN];
+
+        // Safety: the descriptor is valid for as long as the library is loaded
+        let instance = unsafe {
+            ((*self.descriptor).create)(
+                config.as_ptr(),
+                config.len(),
+                error.as_mut_ptr(),
+                ERROR_LEN,
+            )
+        };
+
+        if instance.is_null() {
+            // Don't trust the plugin to have terminated the message
+            error[ERROR_LEN - 1] = 0;
+            let error = unsafe { CStr::from_ptr(error.as_ptr()) }.to_string_lossy();
+
+            return Err(if error.is_empty() {
+                anyhow!("Plugin \"{}\" could not create the render", self.name)
+            } else {
+                anyhow!(
+                    "Plugin \"{}\" could not create the render: {error}",
+                    self.name
+                )
+            });
+        }
+
+        Ok(Box::new(PluginRender {
+            instance,
+            descriptor: self.descriptor,
+            frame: Mutex::new(FrameBuffer::new(Default::default())),
+            _library: self.library.clone(),
+        }))
+    }
+}
+
+struct PluginRender {
+    instance: *mut c_void,
+    descriptor: *const PluginDescriptor,
+    frame: Mutex<FrameBuffer>,
+
+    // Must be dropped after the instance has been destroyed
+    _library: Arc<Library>,
+}
+
+// The ABI allows an instance to be used from any thread as long as the calls never overlap, which
+// holding the frame lock while drawing and `&mut self` while destroying guarantee
+unsafe impl Send for PluginRender {}
+unsafe impl Sync for PluginRender {}
+
+impl<D> Render<D> for PluginRender
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        let mut frame = self.frame.lock();
+        frame.resize(canvas.bounding_box().size);
+        frame.clear(Rgb888::default())?;
+
+        let size = canvas.bounding_box().size;
+        let mut plugin_frame = PluginFrame {
+            width: size.width,
+            height: size.height,
+            pixels: frame.as_bytes_mut().as_mut_ptr(),
+        };
+
+        // Safety: the instance was created by this plugin and hasn't been destroyed
+        unsafe { ((*self.descriptor).render)(self.instance, &mut plugin_frame) };
+
+        frame.draw_onto(canvas)
+    }
+}
+
+impl Drop for PluginRender {
+    fn drop(&mut self) {
+        unsafe { ((*self.descriptor).destroy)(self.instance) };
+    }
+}
+
+/// Loads every plugin found in `directory`.
+///
+/// Plugins that fail to load are logged and skipped, so a single broken plugin doesn't prevent
+/// the display from starting. Returns an error if the directory can't be read.
+pub fn load_plugins<P: AsRef<Path>>(directory: P) -> Result<Vec<PluginFactory>> {
+    let directory = directory.as_ref();
+    let entries = std::fs::read_dir(directory).with_context(|| {
+        format!(
+            "Could not read plugin directory \"{}\"",
+            directory.display()
+        )
+    })?;
+
+    let mut plugins = Vec::new();
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(DLL_EXTENSION) {
+            continue;
+        }
+
+        // Safety: the plugin directory is under the control of whoever runs the display
+        match unsafe { PluginFactory::load(&path) } {
+            Ok(plugin) => {
+                info!("Loaded plugin \"{}\" from {}", plugin.name, path.display());
+                plugins.push(plugin);
+            }
+            Err(e) => warn!("Skipping plugin {}: {e:#}", path.display()),
+        }
+    }
+
+    Ok(plugins)
+}
@@ -0,0 +1,6 @@
+pub mod abi;
+#[cfg(feature = "plugins")]
+mod loader;
+
+#[cfg(feature = "plugins")]
+pub use loader::{load_plugins, PluginFactory};
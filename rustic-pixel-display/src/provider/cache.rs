@@ -0,0 +1,105 @@
+//! Last successful payload of the cached providers, kept on disk so renders have something to show
+//! right after a restart instead of waiting for their first fetch.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use parking_lot::RwLock;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+#[derive(Serialize, Deserialize)]
+struct CachedPayload<T> {
+    /// When the payload was fetched, so it is restored with its real age
+    fetched_at: DateTime<Utc>,
+    data: T,
+}
+
+fn cache_dir() -> &'static RwLock<Option<PathBuf>> {
+    static CACHE_DIR: OnceLock<RwLock<Option<PathBuf>>> = OnceLock::new();
+    CACHE_DIR.get_or_init(Default::default)
+}
+
+/// Keeps the payloads of the providers polled with
+/// [`Poller::spawn_cached`](super::Poller::spawn_cached) in `dir`, which is created if needed.
+/// Nothing is cached until this is called.
+pub fn set_cache_dir<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Could not create the cache directory {}", dir.display()))?;
+
+    *cache_dir().write() = Some(dir.to_owned());
+    Ok(())
+}
+
+/// File the payload cached under `key` is kept in, `None` when caching is disabled
+fn path(key: &str) -> Option<PathBuf> {
+    let file_name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    Some(
+        cache_dir()
+            .read()
+            .as_ref()?
+            .join(format!("{file_name}.json")),
+    )
+}
+
+/// Returns the payload cached under `key` along with when it was fetched. A missing cache is
+/// expected on the first start, an unreadable one is logged and ignored.
+pub(super) fn load<T: DeserializeOwned>(key: &str) -> Option<(DateTime<Utc>, T)> {
+    let path = path(key)?;
+
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Could not read the cache {}: {e}", path.display());
+            return None;
+        }
+    };
+
+    match serde_json::from_slice::<CachedPayload<T>>(&contents) {
+        Ok(CachedPayload { fetched_at, data }) => Some((fetched_at, data)),
+        Err(e) => {
+            warn!("Ignoring the unreadable cache {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Serializes data that was just fetched, the provider consumes the data when applying it so it
+/// has to be encoded beforehand
+pub(super) fn encode<T: Serialize>(data: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&CachedPayload {
+        fetched_at: Utc::now(),
+        data,
+    })?)
+}
+
+/// Replaces the payload cached under `key` with `contents` from [`encode`]
+pub(super) async fn store(key: &str, contents: Vec<u8>) -> Result<()> {
+    let Some(path) = path(key) else {
+        return Ok(());
+    };
+
+    // Written next to the cache and renamed over it, so a crash never leaves half a payload behind
+    let temp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, &path).await?;
+
+    Ok(())
+}
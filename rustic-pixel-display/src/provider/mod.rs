@@ -0,0 +1,271 @@
+//! Background polling of the external services renders get their data from.
+//!
+//! A render implements [`Provider`] to describe how to fetch its data and how to apply it to the
+//! state it draws from, [`Poller`] takes care of scheduling the fetches, backing off while the
+//! service is failing, timing out stuck requests and reporting how the fetches went.
+
+use crate::{
+    health::{HealthReporter, TaskHealth},
+    metrics,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{select, task::JoinHandle, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+mod cache;
+
+pub use cache::set_cache_dir;
+
+/// Requests taking longer than this are abandoned and count as failures
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before retrying the first failed fetch, doubled for every consecutive failure
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest delay between retries, however many fetches failed
+const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// A source of data polled in the background on behalf of a render
+pub trait Provider: Send + 'static {
+    /// What a single fetch returns
+    type Data: Send;
+
+    /// State shared with the render, updated with the fetched data
+    type State: Send + 'static;
+
+    /// Name of the service (i.e. `"septa"`), used in the logs, metrics and health of the fetches
+    fn name(&self) -> &'static str;
+
+    /// Name the health of the fetches is reported under, when a render polls the same service
+    /// more than once
+    fn task(&self) -> String {
+        self.name().to_owned()
+    }
+
+    /// Requests the latest data from the service
+    fn fetch(&mut self) -> impl Future<Output = Result<Self::Data>> + Send;
+
+    /// Updates the render's state with freshly fetched data. An error counts as a failed fetch.
+    fn apply(&mut self, state: &mut Self::State, data: Self::Data) -> Result<()>;
+}
+
+/// How often a [`Provider`] is polled and how failures are retried
+#[derive(Clone, Copy, Debug)]
+pub struct PollSettings {
+    /// Time between the start of two successful fetches
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub retry_delay: Duration,
+    pub max_retry_delay: Duration,
+}
+
+impl PollSettings {
+    pub const fn every(interval: Duration) -> Self {
+        Self {
+            interval,
+            timeout: DEFAULT_TIMEOUT,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            max_retry_delay: DEFAULT_MAX_RETRY_DELAY,
+        }
+    }
+
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub const fn with_retry_delay(
+        mut self,
+        retry_delay: Duration,
+        max_retry_delay: Duration,
+    ) -> Self {
+        self.retry_delay = retry_delay;
+        self.max_retry_delay = max_retry_delay;
+        self
+    }
+
+    /// Delay before the next fetch after `failures` consecutive failed ones: exponential backoff
+    /// with jitter, so that many displays don't all hammer a service as soon as it comes back
+    fn backoff(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(16);
+        let delay = self
+            .retry_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_retry_delay);
+
+        // Only needs to spread the retries out, the randomly seeded std hasher is plenty for that
+        let random = RandomState::new().build_hasher().finish();
+        delay.mul_f64(0.5 + random as f64 / u64::MAX as f64 / 2.0)
+    }
+}
+
+/// Polls a [`Provider`] on the Tokio runtime until dropped
+pub struct Poller {
+    health: HealthReporter,
+
+    /// Data that never arrived is as old as the poller
+    started_at: DateTime<Utc>,
+
+    /// Used to signal the polling task to stop
+    cancel_token: CancellationToken,
+
+    handle: JoinHandle<()>,
+}
+
+/// Key the payloads of a provider are cached under, along with how they are encoded
+type CacheSettings<T> = (String, fn(&T) -> Result<Vec<u8>>);
+
+impl Poller {
+    /// Starts polling `provider`, the first fetch is made right away. The data is applied to
+    /// `state`, which is shared with the render.
+    pub fn spawn<P: Provider>(
+        provider: P,
+        settings: PollSettings,
+        state: Arc<Mutex<P::State>>,
+    ) -> Result<Self> {
+        let health = HealthReporter::new(provider.task());
+        Self::start(provider, settings, state, health, None)
+    }
+
+    /// Like [`Poller::spawn`], but every successful payload is also written to the cache
+    /// directory under `cache_key` (see [`set_cache_dir`]). The payload cached by a previous run
+    /// is applied to `state` before polling starts, and counts as a success from when it was
+    /// fetched, so the render has something to show right away and knows how old it is.
+    pub fn spawn_cached<P>(
+        mut provider: P,
+        settings: PollSettings,
+        state: Arc<Mutex<P::State>>,
+        cache_key: impl Into<String>,
+    ) -> Result<Self>
+    where
+        P: Provider,
+        P::Data: Serialize + DeserializeOwned,
+    {
+        let name = provider.name();
+        let cache_key = cache_key.into();
+        let health = HealthReporter::new(provider.task());
+
+        if let Some((fetched_at, data)) = cache::load(&cache_key) {
+            match provider.apply(&mut state.lock(), data) {
+                Ok(()) => {
+                    info!("Restored {name} from the cache, fetched at {fetched_at}");
+                    health.restored(fetched_at);
+                }
+                Err(e) => warn!("Could not restore {name} from the cache: {e:#}"),
+            }
+        }
+
+        let cache: CacheSettings<P::Data> = (cache_key, cache::encode);
+        Self::start(provider, settings, state, health, Some(cache))
+    }
+
+    fn start<P: Provider>(
+        mut provider: P,
+        settings: PollSettings,
+        state: Arc<Mutex<P::State>>,
+        health: HealthReporter,
+        cache: Option<CacheSettings<P::Data>>,
+    ) -> Result<Self> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .context("Providers can only be polled from within a Tokio runtime")?;
+
+        let name = provider.name();
+        let cancel_token = CancellationToken::new();
+
+        let task_health = health.clone();
+        let task_cancel_token = cancel_token.clone();
+
+        let handle = runtime.spawn(async move {
+            let mut failures = 0;
+
+            loop {
+                let start_time = Instant::now();
+
+                let result = tokio::time::timeout(settings.timeout, provider.fetch())
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("Timed out after {:?}", settings.timeout)));
+                metrics::record_fetch(name, start_time.elapsed(), result.is_ok());
+
+                let result = result.and_then(|data| {
+                    // Applying the data consumes it, so it is encoded for the cache beforehand
+                    let payload = cache.as_ref().map(|(_, encode)| encode(&data));
+                    provider.apply(&mut state.lock(), data)?;
+                    Ok(payload)
+                });
+                task_health.report(&result);
+
+                let next_fetch = match result {
+                    Ok(payload) => {
+                        failures = 0;
+
+                        if let (Some((cache_key, _)), Some(payload)) = (&cache, payload) {
+                            let stored = match payload {
+                                Ok(contents) => cache::store(cache_key, contents).await,
+                                Err(e) => Err(e),
+                            };
+                            if let Err(e) = stored {
+                                warn!("Could not cache {name}: {e:#}");
+                            }
+                        }
+
+                        start_time + settings.interval
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        warn!("Could not update {name} ({failures} consecutive failures): {e:#}");
+                        Instant::now() + settings.backoff(failures)
+                    }
+                };
+
+                select! {
+                    _ = tokio::time::sleep_until(next_fetch) => {},
+                    _ = task_cancel_token.cancelled() => break,
+                }
+            }
+        });
+
+        Ok(Self {
+            health,
+            started_at: Utc::now(),
+            cancel_token,
+            handle,
+        })
+    }
+
+    /// Outcome of the recent fetches, for [`Render::health`](crate::render::Render::health)
+    pub fn health(&self) -> TaskHealth {
+        self.health.health()
+    }
+
+    /// Time since the data was last fetched successfully, or since polling started if no fetch
+    /// succeeded yet
+    pub fn age(&self) -> Duration {
+        let last_success = self.health.health().last_success.unwrap_or(self.started_at);
+        (Utc::now() - last_success).to_std().unwrap_or_default()
+    }
+
+    /// Returns the age of the data once it is older than `stale_after`, renders use it to tell
+    /// viewers they are looking at out of date information. Renders whose fetches never succeed
+    /// become stale as well, once polling started more than `stale_after` ago.
+    pub fn stale(&self, stale_after: Duration) -> Option<Duration> {
+        Some(self.age()).filter(|age| *age > stale_after)
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
+        self.handle.abort();
+    }
+}
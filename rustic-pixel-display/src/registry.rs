@@ -1,32 +1,538 @@
-use crate::render::{Render, RenderFactory};
-use anyhow::Result;
+use crate::{
+    health::{self, HealthMonitor, HealthReport, RenderHealth},
+    metrics,
+    notification::{Notification, NotificationInfo, NotificationQueue, Priority},
+    playlist::{Playlist, PlaylistPlayer, NO_PLAYLIST},
+    render::{
+        draw_error_card, panic_message, render_isolated, Render, RenderFactory, UsefulnessVal,
+    },
+    selection::{SmartSelection, SmartSelector},
+};
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
 use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
-use std::{collections::HashMap, convert::Infallible, error::Error, io::Read};
+use log::{debug, warn};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    error::Error,
+    io::Read,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+#[cfg(feature = "plugins")]
+use crate::plugin::PluginFactory;
+
+/// The number of events a slow subscriber can fall behind before it starts missing events
+const EVENT_CAPACITY: usize = 64;
+
+/// How long a render that panicked shows an error card before it is given another try, the delay
+/// doubles every time the render panics again
+const FAULT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest delay between two tries of a render that keeps panicking
+const FAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// The lifecycle state of a render that was requested to be loaded
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "state", content = "error", rename_all = "snake_case")]
+pub enum RenderState {
+    /// The factory is still constructing the render in the background
+    Loading,
+
+    /// The render was constructed and can be displayed
+    Ready,
+
+    /// The factory failed to construct the render, or the render panicked while drawing
+    Failed(String),
+}
+
 pub struct RenderEntry<D>
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
 {
-    pub render: Box<dyn Render<D>>,
+    /// The constructed render, only present once the entry is [`RenderState::Ready`]
+    pub render: Option<Arc<dyn Render<D>>>,
     pub factory_name: String,
+    pub state: RenderState,
 }
 
-pub struct Registry<F, D>
+impl<D> Clone for RenderEntry<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            render: self.render.clone(),
+            factory_name: self.factory_name.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Settings that apply to the whole display, regardless of what is being rendered
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct DisplaySettings {
+    /// If the display is showing anything at all
+    pub power: bool,
+
+    /// Brightness of the display, `0` being off and `255` being full brightness
+    pub brightness: u8,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            power: true,
+            brightness: u8::MAX,
+        }
+    }
+}
+
+/// Notification of a change made to the [`Registry`].
+///
+/// There is no event for layout changes: the registry draws the selected render on the whole
+/// display, and a [`LayoutManager`](crate::layout_manager::LayoutManager) splits the display
+/// the same way for as long as it is loaded. Switching to another layout is loading and selecting
+/// another render, which is reported as such.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RegistryEvent {
+    RenderLoaded {
+        id: Uuid,
+        factory_name: String,
+    },
+    RenderFailed {
+        id: Uuid,
+        factory_name: String,
+        error: String,
+    },
+    RenderRecovered {
+        id: Uuid,
+    },
+    RenderUnloaded {
+        id: Uuid,
+    },
+    SelectionChanged {
+        id: Option<Uuid>,
+    },
+    PowerChanged {
+        power: bool,
+    },
+    BrightnessChanged {
+        brightness: u8,
+    },
+    SmartSelectionChanged {
+        smart_selection: Option<SmartSelection>,
+    },
+    /// A playlist was created, changed or removed
+    PlaylistsChanged,
+    /// A playlist started or stopped playing
+    PlaylistChanged {
+        name: Option<String>,
+    },
+    NotificationPosted {
+        id: Uuid,
+        priority: Priority,
+    },
+    NotificationDismissed {
+        id: Uuid,
+    },
+    NotificationExpired {
+        id: Uuid,
+    },
+}
+
+impl RegistryEvent {
+    /// Returns the name of the event, matches the `event` field of the serialized event
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RenderLoaded { .. } => "render_loaded",
+            Self::RenderFailed { .. } => "render_failed",
+            Self::RenderRecovered { .. } => "render_recovered",
+            Self::RenderUnloaded { .. } => "render_unloaded",
+            Self::SelectionChanged { .. } => "selection_changed",
+            Self::PowerChanged { .. } => "power_changed",
+            Self::BrightnessChanged { .. } => "brightness_changed",
+            Self::SmartSelectionChanged { .. } => "smart_selection_changed",
+            Self::PlaylistsChanged => "playlists_changed",
+            Self::PlaylistChanged { .. } => "playlist_changed",
+            Self::NotificationPosted { .. } => "notification_posted",
+            Self::NotificationDismissed { .. } => "notification_dismissed",
+            Self::NotificationExpired { .. } => "notification_expired",
+        }
+    }
+}
+
+/// Sends [`RegistryEvent`]s to the subscribers of a registry, shared by the parts of the registry
+/// that make changes
+#[derive(Clone)]
+pub(crate) struct EventSender(broadcast::Sender<RegistryEvent>);
+
+impl EventSender {
+    fn new() -> Self {
+        Self(broadcast::channel(EVENT_CAPACITY).0)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.0.subscribe()
+    }
+
+    /// Sends the event to the current subscribers, it is dropped if there are none
+    pub(crate) fn publish(&self, event: RegistryEvent) {
+        let _ = self.0.send(event);
+    }
+}
+
+/// Name and description of a factory the registry can load renders from
+#[derive(Clone, Debug, Serialize)]
+pub struct FactoryInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// A point in time copy of a loaded render's bookkeeping
+#[derive(Clone, Debug, Serialize)]
+pub struct RenderInfo {
+    pub id: Uuid,
+    pub factory_name: String,
+    #[serde(flatten)]
+    pub state: RenderState,
+
+    /// Last usefulness the render reported, only known while renders are selected automatically
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usefulness: Option<UsefulnessVal>,
+}
+
+struct Fault {
+    error: String,
+    retry_at: Instant,
+    retry_delay: Duration,
+}
+
+/// Renders that panicked while drawing.
+///
+/// A faulted render is replaced by an error card, and is drawn again once its retry delay has
+/// passed. Renders that draw a frame without panicking are cleared from their fault.
+struct RenderFaults {
+    faults: Mutex<HashMap<Uuid, Fault>>,
+    events: EventSender,
+}
+
+impl RenderFaults {
+    fn new(events: EventSender) -> Self {
+        Self {
+            faults: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Records that the render panicked, it isn't drawn again until the retry delay has passed
+    fn record(&self, uuid: Uuid, factory_name: &str, error: String) {
+        let now = Instant::now();
+        let mut faults = self.faults.lock();
+
+        let retry_delay = match faults.get(&uuid) {
+            Some(fault) => (fault.retry_delay * 2).min(FAULT_RETRY_MAX_DELAY),
+            None => FAULT_RETRY_DELAY,
+        };
+        warn!("Render {uuid} panicked, trying again in {retry_delay:?}: {error}");
+
+        faults.insert(
+            uuid,
+            Fault {
+                error: error.clone(),
+                retry_at: now + retry_delay,
+                retry_delay,
+            },
+        );
+        drop(faults);
+
+        self.events.publish(RegistryEvent::RenderFailed {
+            id: uuid,
+            factory_name: factory_name.to_owned(),
+            error,
+        });
+    }
+
+    /// Clears the fault of a render that drew a frame without panicking
+    fn recovered(&self, uuid: Uuid) {
+        if self.faults.lock().remove(&uuid).is_some() {
+            debug!("Render {uuid} recovered");
+
+            self.events
+                .publish(RegistryEvent::RenderRecovered { id: uuid });
+        }
+    }
+
+    /// Returns the error of a faulted render that is still waiting to be tried again
+    fn waiting(&self, uuid: Uuid) -> Option<String> {
+        self.faults
+            .lock()
+            .get(&uuid)
+            .filter(|fault| Instant::now() < fault.retry_at)
+            .map(|fault| fault.error.clone())
+    }
+
+    /// Returns the error of a faulted render, whether or not it is due to be tried again
+    fn error(&self, uuid: Uuid) -> Option<String> {
+        self.faults
+            .lock()
+            .get(&uuid)
+            .map(|fault| fault.error.clone())
+    }
+
+    fn forget(&self, uuid: Uuid) {
+        self.faults.lock().remove(&uuid);
+    }
+}
+
+/// Immutable snapshot of everything the render thread needs to draw a frame.
+///
+/// Every control operation on the [`Registry`] builds a new `RenderSet` and atomically swaps it in,
+/// so the render thread never has to wait on a lock held by the HTTP API (or any other caller).
+pub struct RenderSet<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    renders: HashMap<Uuid, RenderEntry<D>>,
+    selected: Option<Uuid>,
+    display: DisplaySettings,
+
+    smart_selection: Option<SmartSelection>,
+
+    /// Every render that is ready to be shown, only collected in smart selection mode
+    candidates: Vec<(Uuid, Arc<dyn Render<D>>)>,
+    selector: Arc<SmartSelector>,
+
+    /// The playlist being played, if any
+    playlist: Option<Playlist>,
+    player: Arc<PlaylistPlayer>,
+
+    faults: Arc<RenderFaults>,
+    health: Arc<HealthMonitor>,
+    notifications: Arc<NotificationQueue>,
+}
+
+impl<D> RenderSet<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    pub fn display_settings(&self) -> DisplaySettings {
+        self.display
+    }
+
+    /// Draws a render, or an error card if it failed to load or panicked
+    fn render_entry(
+        &self,
+        uuid: Uuid,
+        render_entry: &RenderEntry<D>,
+        canvas: &mut D,
+    ) -> Result<(), <D as DrawTarget>::Error> {
+        let RenderEntry {
+            render,
+            factory_name,
+            state,
+        } = render_entry;
+
+        if let RenderState::Failed(error) = state {
+            return draw_error_card(canvas, &format!("{factory_name} failed to load"), error);
+        }
+
+        // Still loading
+        let Some(render) = render else {
+            return Ok(());
+        };
+
+        if let Some(error) = self.faults.waiting(uuid) {
+            return draw_error_card(canvas, &format!("{factory_name} failed"), &error);
+        }
+
+        let start = Instant::now();
+        let result = render_isolated(render.as_ref(), canvas);
+        metrics::record_render(uuid, start.elapsed());
+
+        match result {
+            Ok(()) => {
+                self.faults.recovered(uuid);
+                Ok(())
+            }
+            Err(error) => {
+                let title = format!("{factory_name} failed");
+                draw_error_card(canvas, &title, &error)?;
+                self.faults.record(uuid, factory_name, error);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<D> Render<D> for RenderSet<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), <D as DrawTarget>::Error> {
+        // Renders keep running while the display is off, so the render thread is still alive
+        self.health.frame_rendered();
+
+        if !self.display.power {
+            return Ok(());
+        }
+
+        // A playing playlist takes over from the selection, unless none of its renders can be shown
+        let playing = self.playlist.as_ref().and_then(|playlist| {
+            self.player.select(playlist, |uuid| {
+                let loaded = self
+                    .renders
+                    .get(&uuid)
+                    .is_some_and(|render_entry| render_entry.render.is_some());
+
+                loaded && self.faults.waiting(uuid).is_none()
+            })
+        });
+
+        let shown = match (playing, &self.smart_selection) {
+            (Some(uuid), _) => Some(uuid),
+            (None, Some(settings)) => {
+                // Faulted renders are left out until they are due to be tried again
+                let candidates: Vec<_> = self
+                    .candidates
+                    .iter()
+                    .filter(|(uuid, _)| self.faults.waiting(*uuid).is_none())
+                    .cloned()
+                    .collect();
+
+                self.selector.select(settings, &candidates, self.selected)
+            }
+            (None, None) => self.selected,
+        };
+
+        let render_entry = shown.and_then(|uuid| Some((uuid, self.renders.get(&uuid)?)));
+        if let Some((uuid, render_entry)) = render_entry {
+            self.render_entry(uuid, render_entry, canvas)?;
+        }
+
+        // Notifications are drawn on top of the selected render
+        self.notifications.render(canvas)
+    }
+}
+
+/// The mutable bookkeeping of the registry, only touched by control operations
+struct RegistryState<D>
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
-    F: RenderFactory<D>,
 {
-    factory_entries: HashMap<String, F>,
     render_entries: HashMap<Uuid, RenderEntry<D>>,
     selected: Option<Uuid>,
+    display: DisplaySettings,
+    smart_selection: Option<SmartSelection>,
+    selector: Arc<SmartSelector>,
+    playlists: BTreeMap<String, Playlist>,
+    playing: Option<String>,
+    player: Arc<PlaylistPlayer>,
+    faults: Arc<RenderFaults>,
+    health: Arc<HealthMonitor>,
+    notifications: Arc<NotificationQueue>,
+}
+
+impl<D> RegistryState<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn snapshot(&self) -> RenderSet<D> {
+        let Self {
+            render_entries,
+            selected,
+            display,
+            smart_selection,
+            selector,
+            playlists,
+            playing,
+            player,
+            faults,
+            health,
+            notifications,
+        } = self;
+
+        let mut candidates = Vec::new();
+        if smart_selection.is_some() {
+            candidates.extend(render_entries.iter().filter_map(|(uuid, render_entry)| {
+                render_entry.render.clone().map(|render| (*uuid, render))
+            }));
+
+            // Keep the order stable between snapshots, ties are broken by it
+            candidates.sort_by_key(|(uuid, _)| *uuid);
+        }
+
+        RenderSet {
+            renders: render_entries.clone(),
+            selected: *selected,
+            display: *display,
+            smart_selection: *smart_selection,
+            candidates,
+            selector: selector.clone(),
+            playlist: playing
+                .as_ref()
+                .and_then(|name| playlists.get(name))
+                .cloned(),
+            player: player.clone(),
+            faults: faults.clone(),
+            health: health.clone(),
+            notifications: notifications.clone(),
+        }
+    }
+}
+
+/// Keeps track of the available [`RenderFactory`]s and the renders they constructed.
+///
+/// The registry is a cheap to clone handle, all clones refer to the same set of renders. Control
+/// operations (loading, unloading, selecting, etc.) only hold an internal lock for as long as it
+/// takes to update the bookkeeping and publish a new [`RenderSet`]. Rendering through the
+/// registry reads the latest published [`RenderSet`] without taking any locks.
+pub struct Registry<F, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    factory_entries: Arc<HashMap<String, Arc<F>>>,
+    #[cfg(feature = "plugins")]
+    plugin_entries: Arc<HashMap<String, Arc<PluginFactory>>>,
+    state: Arc<Mutex<RegistryState<D>>>,
+    render_set: Arc<ArcSwap<RenderSet<D>>>,
+    selector: Arc<SmartSelector>,
+    player: Arc<PlaylistPlayer>,
+    faults: Arc<RenderFaults>,
+    health: Arc<HealthMonitor>,
+    notifications: Arc<NotificationQueue>,
+    events: EventSender,
 }
 
-unsafe impl<F, D> Send for Registry<F, D>
+impl<F, D> Clone for Registry<F, D>
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
     F: RenderFactory<D>,
 {
+    fn clone(&self) -> Self {
+        Self {
+            factory_entries: self.factory_entries.clone(),
+            #[cfg(feature = "plugins")]
+            plugin_entries: self.plugin_entries.clone(),
+            state: self.state.clone(),
+            render_set: self.render_set.clone(),
+            selector: self.selector.clone(),
+            player: self.player.clone(),
+            faults: self.faults.clone(),
+            health: self.health.clone(),
+            notifications: self.notifications.clone(),
+            events: self.events.clone(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -36,6 +542,10 @@ pub enum RegistryError {
     RenderNotLoaded,
     RenderNotUnload,
     FileIoError,
+    NotificationNotFound(Uuid),
+    NotificationQueueFull,
+    PlaylistNotFound(String),
+    InvalidPlaylist(String),
 }
 
 impl Error for RegistryError {}
@@ -48,6 +558,12 @@ impl std::fmt::Display for RegistryError {
             Self::RenderNotLoaded => write!(f, "Render was not loaded"),
             Self::RenderNotUnload => write!(f, "Render was not unloaded"),
             Self::FileIoError => write!(f, "File IO error"),
+            Self::NotificationNotFound(uuid) => {
+                write!(f, "Notification \"{}\" was not found", uuid)
+            }
+            Self::NotificationQueueFull => write!(f, "Too many notifications are waiting"),
+            Self::PlaylistNotFound(name) => write!(f, "Playlist \"{}\" was not found", name),
+            Self::InvalidPlaylist(reason) => write!(f, "Invalid playlist: {}", reason),
         }
     }
 }
@@ -58,110 +574,523 @@ where
     F: RenderFactory<D>,
 {
     pub fn new(factories: Vec<F>) -> Self {
-        Self {
-            factory_entries: factories
-                .into_iter()
-                .map(|factory| (factory.render_name().to_owned(), factory))
-                .collect::<HashMap<_, _>>(),
+        let events = EventSender::new();
+        let selector = Arc::new(SmartSelector::new(events.clone()));
+        let player = Arc::new(PlaylistPlayer::new(events.clone()));
+        let faults = Arc::new(RenderFaults::new(events.clone()));
+        let health = Arc::new(HealthMonitor::default());
+        let notifications = Arc::new(NotificationQueue::new(events.clone()));
+        let state = RegistryState {
             render_entries: HashMap::new(),
             selected: None,
+            display: DisplaySettings::default(),
+            smart_selection: None,
+            selector: selector.clone(),
+            playlists: BTreeMap::new(),
+            playing: None,
+            player: player.clone(),
+            faults: faults.clone(),
+            health: health.clone(),
+            notifications: notifications.clone(),
+        };
+
+        Self {
+            factory_entries: Arc::new(
+                factories
+                    .into_iter()
+                    .map(|factory| (factory.render_name().to_owned(), Arc::new(factory)))
+                    .collect::<HashMap<_, _>>(),
+            ),
+            #[cfg(feature = "plugins")]
+            plugin_entries: Arc::new(HashMap::new()),
+            render_set: Arc::new(ArcSwap::from_pointee(state.snapshot())),
+            state: Arc::new(Mutex::new(state)),
+            selector,
+            player,
+            faults,
+            health,
+            notifications,
+            events,
         }
     }
 
-    pub fn load<R: Read>(&mut self, factory_name: &str, reader: R) -> Result<Uuid, RegistryError> {
-        let Self {
-            factory_entries,
-            render_entries,
-            ..
-        } = self;
+    /// Adds factories provided by plugins alongside the factories compiled into the program.
+    ///
+    /// Plugins with the same name as a compiled in factory (or another plugin) are skipped.
+    #[cfg(feature = "plugins")]
+    pub fn with_plugins(mut self, plugins: Vec<PluginFactory>) -> Self {
+        let mut plugin_entries = HashMap::new();
 
-        let render = match factory_entries.get(factory_name) {
-            Some(factory) => match factory.load_from_config(reader) {
-                Ok(render) => render,
-                Err(_) => return Err(RegistryError::FileIoError),
-            },
-            None => return Err(RegistryError::FactoryNotFound(factory_name.to_owned())),
-        };
+        for plugin in plugins {
+            let name = RenderFactory::<D>::render_name(&plugin);
+
+            if self.factory_entries.contains_key(name) || plugin_entries.contains_key(name) {
+                warn!(
+                    "Skipping plugin {}, a factory named \"{name}\" already exists",
+                    plugin.path().display()
+                );
+                continue;
+            }
+
+            plugin_entries.insert(name.to_owned(), Arc::new(plugin));
+        }
+
+        self.plugin_entries = Arc::new(plugin_entries);
+        self
+    }
+
+    /// Subscribes to the changes made to the registry from this point on
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.events.subscribe()
+    }
+
+    /// Runs `operation` against the registry state and publishes the resulting [`RenderSet`].
+    fn update<T>(&self, operation: impl FnOnce(&mut RegistryState<D>) -> T) -> T {
+        let mut state_unlocked = self.state.lock();
+        let result = operation(&mut state_unlocked);
+        self.render_set.store(Arc::new(state_unlocked.snapshot()));
+
+        result
+    }
+
+    /// Starts loading a render using the factory named `factory_name`.
+    ///
+    /// The factory is run on a blocking thread of the current Tokio runtime, so this call returns
+    /// as soon as the configuration has been read. The returned UUID starts out in the
+    /// [`RenderState::Loading`] state and transitions to either [`RenderState::Ready`] or
+    /// [`RenderState::Failed`] once the factory is done.
+    pub fn load<R: Read>(&self, factory_name: &str, mut reader: R) -> Result<Uuid, RegistryError>
+    where
+        D: 'static,
+        F: Send + Sync + 'static,
+    {
+        let factory = self.find_factory(factory_name)?;
+
+        // Buffer the configuration, the reader is tied to the caller and can't be moved onto the
+        // loading thread
+        let mut config = Vec::new();
+        if reader.read_to_end(&mut config).is_err() {
+            return Err(RegistryError::FileIoError);
+        }
 
         let uuid = Uuid::new_v4();
-        render_entries.insert(
-            uuid,
-            RenderEntry {
-                render,
-                factory_name: factory_name.to_owned(),
-            },
-        );
+        metrics::track_render(uuid, factory_name);
+        self.update(|state| {
+            state.render_entries.insert(
+                uuid,
+                RenderEntry {
+                    render: None,
+                    factory_name: factory_name.to_owned(),
+                    state: RenderState::Loading,
+                },
+            );
+        });
+
+        let registry = self.clone();
+        tokio::task::spawn_blocking(move || {
+            // A factory that panics fails the load instead of leaving the render loading forever
+            let result = panic::catch_unwind(AssertUnwindSafe(|| factory(config.as_slice())))
+                .unwrap_or_else(|payload| {
+                    Err(anyhow!("Panicked: {}", panic_message(payload.as_ref())))
+                })
+                .map(Arc::from)
+                .map_err(|e| format!("{e:#}"));
+
+            registry.finish_load(uuid, result);
+        });
 
         Ok(uuid)
     }
 
-    pub fn unload(&mut self, uuid: Uuid) -> Result<(), RegistryError> {
-        let Self {
-            render_entries,
-            selected,
-            ..
-        } = self;
+    /// Looks up a factory by name and returns a function that runs it on a configuration
+    #[allow(clippy::type_complexity)]
+    fn find_factory(
+        &self,
+        factory_name: &str,
+    ) -> Result<Box<dyn FnOnce(&[u8]) -> Result<Box<dyn Render<D>>> + Send>, RegistryError>
+    where
+        F: Send + Sync + 'static,
+    {
+        if let Some(factory) = self.factory_entries.get(factory_name) {
+            let factory = factory.clone();
+            return Ok(Box::new(move |config| factory.load_from_config(config)));
+        }
 
-        if let Some(selected_uuid) = selected {
-            if selected_uuid == &uuid {
-                *selected = None;
-            }
+        #[cfg(feature = "plugins")]
+        if let Some(plugin) = self.plugin_entries.get(factory_name) {
+            let plugin = plugin.clone();
+            return Ok(Box::new(move |config| plugin.load_from_config(config)));
+        }
+
+        Err(RegistryError::FactoryNotFound(factory_name.to_owned()))
+    }
+
+    fn finish_load(&self, uuid: Uuid, result: Result<Arc<dyn Render<D>>, String>) {
+        // If the render was unloaded while it was being constructed, drop it outside of the lock
+        let (event, _unloaded_render) =
+            self.update(|state| match state.render_entries.get_mut(&uuid) {
+                Some(render_entry) => {
+                    let factory_name = render_entry.factory_name.clone();
+
+                    match result {
+                        Ok(render) => {
+                            debug!("Render {uuid} finished loading");
+                            render_entry.render = Some(render);
+                            render_entry.state = RenderState::Ready;
+
+                            let event = RegistryEvent::RenderLoaded {
+                                id: uuid,
+                                factory_name,
+                            };
+                            (Some(event), None)
+                        }
+                        Err(e) => {
+                            warn!("Render {uuid} failed to load: {e}");
+                            render_entry.state = RenderState::Failed(e.clone());
+
+                            let event = RegistryEvent::RenderFailed {
+                                id: uuid,
+                                factory_name,
+                                error: e,
+                            };
+                            (Some(event), None)
+                        }
+                    }
+                }
+                None => {
+                    debug!("Render {uuid} was unloaded before it finished loading");
+                    (None, result.ok())
+                }
+            });
+
+        if let Some(event) = event {
+            self.events.publish(event);
         }
+    }
+
+    pub fn unload(&self, uuid: Uuid) -> Result<(), RegistryError> {
+        // Hold on to the removed entry until the lock is released, dropping a render cancels its
+        // background tasks which we don't want to do while holding the lock
+        let (_render_entry, was_selected, in_playlist) = self.update(|state| {
+            let render_entry = state
+                .render_entries
+                .remove(&uuid)
+                .ok_or(RegistryError::RenderNotFound(uuid))?;
+
+            let was_selected = state.selected == Some(uuid);
+            if was_selected {
+                state.selected = None;
+            }
 
-        match render_entries.remove(&uuid) {
-            Some(_) => Ok(()),
-            None => Err(RegistryError::RenderNotFound(uuid)),
+            let mut in_playlist = false;
+            for playlist in state.playlists.values_mut() {
+                let len = playlist.renders.len();
+                playlist.renders.retain(|render| *render != uuid);
+                in_playlist |= playlist.renders.len() != len;
+            }
+
+            Ok((render_entry, was_selected, in_playlist))
+        })?;
+        self.faults.forget(uuid);
+        metrics::forget_render(uuid);
+
+        self.events
+            .publish(RegistryEvent::RenderUnloaded { id: uuid });
+        if was_selected {
+            self.events
+                .publish(RegistryEvent::SelectionChanged { id: None });
         }
+        if in_playlist {
+            self.events.publish(RegistryEvent::PlaylistsChanged);
+        }
+
+        Ok(())
     }
 
-    pub fn select(&mut self, uuid: Uuid) -> Result<(), RegistryError> {
-        let Self {
-            render_entries,
-            selected,
-            ..
-        } = self;
+    pub fn select(&self, uuid: Uuid) -> Result<(), RegistryError> {
+        self.update(|state| {
+            if !state.render_entries.contains_key(&uuid) {
+                Err(RegistryError::RenderNotFound(uuid))
+            } else {
+                state.selected = Some(uuid);
+                Ok(())
+            }
+        })?;
 
-        if !render_entries.contains_key(&uuid) {
-            Err(RegistryError::RenderNotFound(uuid))
-        } else {
-            *selected = Some(uuid);
+        self.events
+            .publish(RegistryEvent::SelectionChanged { id: Some(uuid) });
+        Ok(())
+    }
+
+    /// Turns the display on or off, the renders keep running while the display is off
+    pub fn set_power(&self, power: bool) {
+        self.update(|state| state.display.power = power);
+        self.events.publish(RegistryEvent::PowerChanged { power });
+    }
+
+    pub fn set_brightness(&self, brightness: u8) {
+        self.update(|state| state.display.brightness = brightness);
+        self.events
+            .publish(RegistryEvent::BrightnessChanged { brightness });
+    }
+
+    /// Queues a notification to be drawn on top of the selected render
+    pub fn notify(&self, notification: Notification) -> Result<Uuid, RegistryError> {
+        self.notifications
+            .push(notification)
+            .ok_or(RegistryError::NotificationQueueFull)
+    }
+
+    /// Removes a notification, whether it is being shown or still waiting
+    pub fn dismiss_notification(&self, uuid: Uuid) -> Result<(), RegistryError> {
+        if self.notifications.dismiss(uuid) {
             Ok(())
+        } else {
+            Err(RegistryError::NotificationNotFound(uuid))
         }
     }
 
+    /// Returns the queued notifications, in the order they will be shown
+    pub fn notifications(&self) -> Vec<NotificationInfo> {
+        self.notifications.list()
+    }
+
+    /// Shows the most useful render instead of the selected one, `None` goes back to showing the
+    /// selected render.
+    ///
+    /// Selecting a render while smart selection is enabled shows it right away, it is then
+    /// replaced like any other render.
+    pub fn set_smart_selection(&self, smart_selection: Option<SmartSelection>) {
+        let manual = self.update(|state| {
+            state.smart_selection = smart_selection;
+            state.selected
+        });
+        self.selector.reset();
+
+        self.events
+            .publish(RegistryEvent::SmartSelectionChanged { smart_selection });
+        if smart_selection.is_none() {
+            self.events
+                .publish(RegistryEvent::SelectionChanged { id: manual });
+        }
+    }
+
+    pub fn smart_selection(&self) -> Option<SmartSelection> {
+        self.state.lock().smart_selection
+    }
+
+    /// Creates or replaces the playlist called `name`, a playlist that is playing starts over
+    pub fn set_playlist(&self, name: &str, playlist: Playlist) -> Result<(), RegistryError> {
+        if name == NO_PLAYLIST {
+            return Err(RegistryError::InvalidPlaylist(format!(
+                "\"{NO_PLAYLIST}\" is reserved for stopping the playlist"
+            )));
+        }
+        // Every frame would move on to the next render
+        if playlist.interval_secs == 0 {
+            return Err(RegistryError::InvalidPlaylist(
+                "interval_secs has to be at least 1".to_owned(),
+            ));
+        }
+
+        let playing = self.update(|state| {
+            if let Some(uuid) = playlist
+                .renders
+                .iter()
+                .find(|uuid| !state.render_entries.contains_key(uuid))
+            {
+                return Err(RegistryError::RenderNotFound(*uuid));
+            }
+
+            state.playlists.insert(name.to_owned(), playlist);
+            Ok(state.playing.as_deref() == Some(name))
+        })?;
+
+        if playing {
+            self.player.reset();
+        }
+
+        self.events.publish(RegistryEvent::PlaylistsChanged);
+        Ok(())
+    }
+
+    /// Removes the playlist called `name`, stopping it if it is playing
+    pub fn remove_playlist(&self, name: &str) -> Result<(), RegistryError> {
+        let was_playing = self.update(|state| {
+            state
+                .playlists
+                .remove(name)
+                .ok_or_else(|| RegistryError::PlaylistNotFound(name.to_owned()))?;
+
+            let was_playing = state.playing.as_deref() == Some(name);
+            if was_playing {
+                state.playing = None;
+            }
+
+            Ok(was_playing)
+        })?;
+
+        self.events.publish(RegistryEvent::PlaylistsChanged);
+        if was_playing {
+            self.stopped_playing();
+        }
+
+        Ok(())
+    }
+
+    pub fn playlists(&self) -> BTreeMap<String, Playlist> {
+        self.state.lock().playlists.clone()
+    }
+
+    /// Plays the playlist called `name` from the start, `None` stops the playlist that is playing
+    /// and goes back to the selected render.
+    ///
+    /// While a playlist is playing it takes over from both the selected render and smart
+    /// selection, unless none of its renders can be shown.
+    pub fn play(&self, name: Option<&str>) -> Result<(), RegistryError> {
+        self.update(|state| match name {
+            Some(name) if !state.playlists.contains_key(name) => {
+                Err(RegistryError::PlaylistNotFound(name.to_owned()))
+            }
+            _ => {
+                state.playing = name.map(str::to_owned);
+                Ok(())
+            }
+        })?;
+        self.player.reset();
+
+        self.events.publish(RegistryEvent::PlaylistChanged {
+            name: name.map(str::to_owned),
+        });
+        if name.is_none() {
+            self.stopped_playing();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the name of the playlist that is playing, if any
+    pub fn playing(&self) -> Option<String> {
+        self.state.lock().playing.clone()
+    }
+
+    /// Lets subscribers know which render is back on the display once the playlist stopped
+    fn stopped_playing(&self) {
+        let id = self.selected();
+        self.events.publish(RegistryEvent::SelectionChanged { id });
+    }
+
+    /// Returns the id of the render on the display, if any
+    pub fn selected(&self) -> Option<Uuid> {
+        let state = self.state.lock();
+
+        if state.playing.is_some() {
+            if let Some(uuid) = self.player.shown() {
+                return Some(uuid);
+            }
+        }
+
+        match state.smart_selection {
+            Some(_) => self.selector.shown(),
+            None => state.selected,
+        }
+    }
+
+    pub fn display_settings(&self) -> DisplaySettings {
+        self.render_set.load().display
+    }
+
     pub fn factory_iter(&self) -> impl Iterator<Item = (&String, &F)> {
-        let Self {
-            factory_entries, ..
-        } = self;
+        self.factory_entries
+            .iter()
+            .map(|(name, factory)| (name, factory.as_ref()))
+    }
+
+    /// Returns every factory the registry can load renders from, including plugins
+    pub fn factories(&self) -> Vec<FactoryInfo> {
+        let factories = self.factory_iter().map(|(_, factory)| FactoryInfo {
+            name: factory.render_name(),
+            description: factory.render_description(),
+        });
+
+        #[cfg(feature = "plugins")]
+        let factories = factories.chain(self.plugin_entries.values().map(|plugin| FactoryInfo {
+            name: RenderFactory::<D>::render_name(plugin.as_ref()),
+            description: RenderFactory::<D>::render_description(plugin.as_ref()),
+        }));
+
+        factories.collect()
+    }
+
+    /// Returns the liveness of the render and driver threads and the health of every loaded
+    /// render's background tasks
+    pub fn health(&self) -> HealthReport {
+        let render_set = self.render_set.load();
+        let renders = self
+            .renders()
+            .into_iter()
+            .map(|render_info| RenderHealth {
+                id: render_info.id,
+                tasks: render_set
+                    .renders
+                    .get(&render_info.id)
+                    .and_then(|render_entry| render_entry.render.as_ref())
+                    .map(|render| health::collect(render_info.id, render.as_ref()))
+                    .unwrap_or_default(),
+                factory_name: render_info.factory_name,
+                state: render_info.state,
+            })
+            .collect();
+
+        HealthReport::new(
+            self.health.render_thread(),
+            self.health.driver_thread(),
+            self.health.fps(),
+            renders,
+        )
+    }
 
-        factory_entries.iter()
+    /// Records that the driver thread showed a frame on the panel
+    pub(crate) fn frame_displayed(&self) {
+        self.health.frame_displayed();
     }
 
-    pub fn render_iter(&self) -> impl Iterator<Item = (&Uuid, &RenderEntry<D>)> {
-        let Self { render_entries, .. } = self;
+    /// Returns the bookkeeping information of every loaded render
+    pub fn renders(&self) -> Vec<RenderInfo> {
+        self.state
+            .lock()
+            .render_entries
+            .iter()
+            .map(|(uuid, render_entry)| RenderInfo {
+                id: *uuid,
+                factory_name: render_entry.factory_name.clone(),
+                state: match self.faults.error(*uuid) {
+                    Some(error) => RenderState::Failed(error),
+                    None => render_entry.state.clone(),
+                },
+                usefulness: self.selector.usefulness(*uuid),
+            })
+            .collect()
+    }
+}
 
-        render_entries.iter()
+impl<F, D> Registry<F, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    F: RenderFactory<D>,
+{
+    /// Returns the latest [`RenderSet`] published by the registry
+    pub fn render_set(&self) -> Arc<RenderSet<D>> {
+        self.render_set.load_full()
     }
 }
 
 impl<F, D> Render<D> for Registry<F, D>
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
-    F: RenderFactory<D>,
+    F: RenderFactory<D> + Send + Sync,
 {
     fn render(&self, canvas: &mut D) -> Result<(), <D as DrawTarget>::Error> {
-        let Self {
-            render_entries,
-            selected,
-            ..
-        } = self;
-
-        if let Some(selected) = selected {
-            if let Some(render_entry) = render_entries.get(selected) {
-                render_entry.render.render(canvas)?;
-            }
-        }
-
-        Ok(())
+        self.render_set.load().render(canvas)
     }
 }
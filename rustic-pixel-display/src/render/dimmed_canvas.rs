@@ -0,0 +1,100 @@
+use anyhow::Result;
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::{Dimensions, DrawTarget, RgbColor},
+    primitives::Rectangle,
+    Pixel,
+};
+use std::convert::Infallible;
+
+/// Owns a canvas and scales the color of everything drawn onto it by a brightness factor.
+///
+/// Neither of the hardware drivers can change the brightness of the panel once it has been
+/// initialized, so the brightness is applied in software as the pixels are drawn.
+pub struct DimmedCanvas<D> {
+    brightness: u8,
+    canvas: Box<D>,
+}
+
+impl<D> DimmedCanvas<D> {
+    pub fn new(canvas: Box<D>) -> Self {
+        DimmedCanvas {
+            brightness: u8::MAX,
+            canvas,
+        }
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Sets the brightness, `0` being off and `255` drawing the colors unmodified
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.canvas
+    }
+
+    pub fn into_inner(self) -> Box<D> {
+        self.canvas
+    }
+}
+
+pub(super) fn dim(brightness: u8, color: Rgb888) -> Rgb888 {
+    if brightness == u8::MAX {
+        return color;
+    }
+
+    let scale = |channel: u8| (channel as u16 * brightness as u16 / u8::MAX as u16) as u8;
+    Rgb888::new(scale(color.r()), scale(color.g()), scale(color.b()))
+}
+
+impl<D> Dimensions for DimmedCanvas<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn bounding_box(&self) -> Rectangle {
+        self.canvas.bounding_box()
+    }
+}
+
+impl<D> DrawTarget for DimmedCanvas<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let brightness = self.brightness;
+
+        self.canvas.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, dim(brightness, color))),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let brightness = self.brightness;
+
+        self.canvas
+            .fill_contiguous(area, colors.into_iter().map(|color| dim(brightness, color)))
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.canvas.fill_solid(area, dim(self.brightness, color))
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.canvas.clear(dim(self.brightness, color))
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{notification::wrap, render::Render};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoFont, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, RgbColor},
+    text::{Baseline, Text},
+    Drawable,
+};
+use std::{
+    any::Any,
+    convert::Infallible,
+    panic::{self, AssertUnwindSafe},
+};
+
+const FONT: MonoFont<'static> = FONT_6X10;
+
+/// Draws a frame of `render`, a panic is caught and returned as an error instead of unwinding
+/// into the caller.
+///
+/// The render is left in whatever state it was when it panicked, callers should stop drawing it
+/// (or at least give it some time) after an error.
+pub fn render_isolated<D, R>(render: &R, canvas: &mut D) -> Result<(), String>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    R: Render<D> + ?Sized,
+{
+    match panic::catch_unwind(AssertUnwindSafe(|| render.render(canvas))) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => match e {},
+        Err(payload) => Err(panic_message(payload.as_ref())),
+    }
+}
+
+/// Extracts the message a panic was raised with
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Panicked without a message".to_owned()
+    }
+}
+
+/// Draws a card explaining why a render can't be shown, in place of the render.
+///
+/// The title goes on the first line and the error is wrapped below it, whatever doesn't fit on
+/// the canvas is cut off.
+pub fn draw_error_card<D>(canvas: &mut D, title: &str, error: &str) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb888>,
+{
+    canvas.clear(Rgb888::BLACK)?;
+
+    let bounding_box = canvas.bounding_box();
+    let columns = (bounding_box.size.width / FONT.character_size.width) as usize;
+    let line_height = FONT.character_size.height as i32;
+
+    let title_style = MonoTextStyle::new(&FONT, Rgb888::RED);
+    let error_style = MonoTextStyle::new(&FONT, Rgb888::WHITE);
+
+    let title_lines = wrap(title, columns);
+    let error_lines = wrap(error, columns);
+
+    let lines = title_lines
+        .iter()
+        .map(|line| (line, title_style))
+        .chain(error_lines.iter().map(|line| (line, error_style)));
+
+    for (index, (line, style)) in lines.enumerate() {
+        let position = bounding_box.top_left + Point::new(0, index as i32 * line_height);
+        if !bounding_box.contains(position) {
+            break;
+        }
+
+        Text::with_baseline(line, position, style, Baseline::Top).draw(canvas)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use embedded_graphics::mono_font::{ascii, MonoFont};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, EnumString};
+
+/// The monospaced fonts that can be selected by name in render configurations (i.e. `"6x10"`)
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, EnumString, AsRefStr,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum Font {
+    #[serde(rename = "4x6")]
+    #[strum(serialize = "4x6")]
+    Font4x6,
+    #[serde(rename = "5x7")]
+    #[strum(serialize = "5x7")]
+    Font5x7,
+    #[serde(rename = "5x8")]
+    #[strum(serialize = "5x8")]
+    Font5x8,
+    #[serde(rename = "6x9")]
+    #[strum(serialize = "6x9")]
+    Font6x9,
+    #[default]
+    #[serde(rename = "6x10")]
+    #[strum(serialize = "6x10")]
+    Font6x10,
+    #[serde(rename = "6x12")]
+    #[strum(serialize = "6x12")]
+    Font6x12,
+    #[serde(rename = "6x13")]
+    #[strum(serialize = "6x13")]
+    Font6x13,
+    #[serde(rename = "6x13 Bold")]
+    #[strum(serialize = "6x13 Bold")]
+    Font6x13Bold,
+    #[serde(rename = "6x13 Italic")]
+    #[strum(serialize = "6x13 Italic")]
+    Font6x13Italic,
+    #[serde(rename = "7x13")]
+    #[strum(serialize = "7x13")]
+    Font7x13,
+    #[serde(rename = "7x13 Bold")]
+    #[strum(serialize = "7x13 Bold")]
+    Font7x13Bold,
+    #[serde(rename = "7x13 Italic")]
+    #[strum(serialize = "7x13 Italic")]
+    Font7x13Italic,
+    #[serde(rename = "7x14")]
+    #[strum(serialize = "7x14")]
+    Font7x14,
+    #[serde(rename = "7x14 Bold")]
+    #[strum(serialize = "7x14 Bold")]
+    Font7x14Bold,
+    #[serde(rename = "8x13")]
+    #[strum(serialize = "8x13")]
+    Font8x13,
+    #[serde(rename = "8x13 Bold")]
+    #[strum(serialize = "8x13 Bold")]
+    Font8x13Bold,
+    #[serde(rename = "8x13 Italic")]
+    #[strum(serialize = "8x13 Italic")]
+    Font8x13Italic,
+    #[serde(rename = "9x15")]
+    #[strum(serialize = "9x15")]
+    Font9x15,
+    #[serde(rename = "9x15 Bold")]
+    #[strum(serialize = "9x15 Bold")]
+    Font9x15Bold,
+    #[serde(rename = "9x18")]
+    #[strum(serialize = "9x18")]
+    Font9x18,
+    #[serde(rename = "9x18 Bold")]
+    #[strum(serialize = "9x18 Bold")]
+    Font9x18Bold,
+    #[serde(rename = "10x20")]
+    #[strum(serialize = "10x20")]
+    Font10x20,
+}
+
+impl Font {
+    pub fn mono_font(&self) -> &'static MonoFont<'static> {
+        match self {
+            Font::Font4x6 => &ascii::FONT_4X6,
+            Font::Font5x7 => &ascii::FONT_5X7,
+            Font::Font5x8 => &ascii::FONT_5X8,
+            Font::Font6x9 => &ascii::FONT_6X9,
+            Font::Font6x10 => &ascii::FONT_6X10,
+            Font::Font6x12 => &ascii::FONT_6X12,
+            Font::Font6x13 => &ascii::FONT_6X13,
+            Font::Font6x13Bold => &ascii::FONT_6X13_BOLD,
+            Font::Font6x13Italic => &ascii::FONT_6X13_ITALIC,
+            Font::Font7x13 => &ascii::FONT_7X13,
+            Font::Font7x13Bold => &ascii::FONT_7X13_BOLD,
+            Font::Font7x13Italic => &ascii::FONT_7X13_ITALIC,
+            Font::Font7x14 => &ascii::FONT_7X14,
+            Font::Font7x14Bold => &ascii::FONT_7X14_BOLD,
+            Font::Font8x13 => &ascii::FONT_8X13,
+            Font::Font8x13Bold => &ascii::FONT_8X13_BOLD,
+            Font::Font8x13Italic => &ascii::FONT_8X13_ITALIC,
+            Font::Font9x15 => &ascii::FONT_9X15,
+            Font::Font9x15Bold => &ascii::FONT_9X15_BOLD,
+            Font::Font9x18 => &ascii::FONT_9X18,
+            Font::Font9x18Bold => &ascii::FONT_9X18_BOLD,
+            Font::Font10x20 => &ascii::FONT_10X20,
+        }
+    }
+}
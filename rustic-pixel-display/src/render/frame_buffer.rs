@@ -0,0 +1,153 @@
+use anyhow::Result;
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::{Dimensions, DrawTarget, OriginDimensions, Point, PointsIter, RgbColor, Size},
+    primitives::Rectangle,
+    Pixel,
+};
+use std::convert::Infallible;
+
+/// Number of bytes used to store a single pixel
+const BYTES_PER_PIXEL: usize = 3;
+
+/// An in-memory RGB888 canvas.
+///
+/// Used to hand a frame to code that can't draw onto the hardware canvas directly (i.e. code that
+/// is loaded at runtime). Pixels are stored row by row, each pixel being 3 bytes in red, green,
+/// blue order.
+pub struct FrameBuffer {
+    size: Size,
+    pixels: Vec<u8>,
+}
+
+impl FrameBuffer {
+    pub fn new(size: Size) -> Self {
+        FrameBuffer {
+            size,
+            pixels: vec![0; size.width as usize * size.height as usize * BYTES_PER_PIXEL],
+        }
+    }
+
+    /// Changes the size of the frame, the content of the frame is cleared if the size changed
+    pub fn resize(&mut self, size: Size) {
+        if self.size != size {
+            *self = Self::new(size);
+        }
+    }
+
+    /// Offset in `pixels` of the first byte of the pixel at `point`, which has to be in the frame
+    fn index(&self, point: Point) -> usize {
+        (point.y as usize * self.size.width as usize + point.x as usize) * BYTES_PER_PIXEL
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+
+    /// Copies the whole frame onto the top left corner of `canvas`
+    pub fn draw_onto<D>(&self, canvas: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888>,
+    {
+        canvas.fill_contiguous(
+            &Rectangle::new(Default::default(), self.size),
+            self.pixels
+                .chunks_exact(BYTES_PER_PIXEL)
+                .map(|pixel| Rgb888::new(pixel[0], pixel[1], pixel[2])),
+        )
+    }
+}
+
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let Self {
+            size,
+            pixels: frame,
+        } = self;
+
+        for Pixel(point, color) in pixels {
+            // Pixels outside of the frame are silently dropped
+            if point.x < 0
+                || point.y < 0
+                || point.x as u32 >= size.width
+                || point.y as u32 >= size.height
+            {
+                continue;
+            }
+
+            let index =
+                (point.y as usize * size.width as usize + point.x as usize) * BYTES_PER_PIXEL;
+            frame[index..index + BYTES_PER_PIXEL].copy_from_slice(&[
+                color.r(),
+                color.g(),
+                color.b(),
+            ]);
+        }
+
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        // Stops after the last visible row, otherwise a huge area would keep the caller busy
+        let visible = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = visible.bottom_right() else {
+            return Ok(());
+        };
+        let rows = (bottom_right.y - area.top_left.y + 1) as usize;
+
+        self.draw_iter(
+            area.points()
+                .zip(colors)
+                .take(rows.saturating_mul(area.size.width as usize))
+                .filter(|(point, _)| visible.contains(*point))
+                .map(|(point, color)| Pixel(point, color)),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        // Only the part of the area that is in the frame is drawn
+        let area = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+
+        let color = [color.r(), color.g(), color.b()];
+        for y in area.top_left.y..=bottom_right.y {
+            let start = self.index(Point::new(area.top_left.x, y));
+            let end = self.index(Point::new(bottom_right.x, y)) + BYTES_PER_PIXEL;
+
+            for pixel in self.pixels[start..end].chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        for pixel in self.pixels.chunks_exact_mut(BYTES_PER_PIXEL) {
+            pixel.copy_from_slice(&[color.r(), color.g(), color.b()]);
+        }
+
+        Ok(())
+    }
+}
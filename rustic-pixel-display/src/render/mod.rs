@@ -1,19 +1,67 @@
+use crate::health::TaskHealth;
 use anyhow::Result;
 use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
+use serde::{Deserialize, Serialize};
 use std::{convert::Infallible, io::Read};
 
+mod dimmed_canvas;
+mod fault;
+mod font;
+mod frame_buffer;
+mod stale;
 mod sub_canvas;
 
+pub use dimmed_canvas::DimmedCanvas;
+pub use fault::{draw_error_card, render_isolated};
+pub use font::Font;
+pub use frame_buffer::FrameBuffer;
+pub use stale::{draw_stale_badge, FadedCanvas, STALE_BRIGHTNESS};
 pub use sub_canvas::SubCanvas;
 
+pub(crate) use fault::panic_message;
+
+/// How relevant something is to show right now, from least to most relevant
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsefulnessVal {
+    NotUseful,
+    BarelyUseful,
+    SomewhatUseful,
+    Useful,
+    VeryUseful,
+    Essential,
+}
+
+pub trait Usefulness {
+    fn usefulness(&self) -> UsefulnessVal;
+}
+
 /// Performs drawing operations on a embedded-graphics target
 ///
 /// Encapsulates drawing operations into a
-pub trait Render<D>
+///
+/// Renders are drawn on the render thread but loaded, swapped and dropped from the Tokio runtime
+/// and the API, so they have to be `Send` and `Sync`.
+pub trait Render<D>: Send + Sync
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
 {
     fn render(&self, canvas: &mut D) -> Result<(), D::Error>;
+
+    /// Reports how relevant the content of the render is right now.
+    ///
+    /// Used by the [`Registry`](crate::registry::Registry) to pick the render that is shown when
+    /// renders are selected automatically.
+    fn usefulness(&self) -> UsefulnessVal {
+        UsefulnessVal::SomewhatUseful
+    }
+
+    /// Reports how the background tasks keeping the render up to date are doing.
+    ///
+    /// Renders without background tasks have nothing to report.
+    fn health(&self) -> Vec<TaskHealth> {
+        Vec::new()
+    }
 }
 
 /// Constructs a [`Render`] from a configuration.
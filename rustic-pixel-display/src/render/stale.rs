@@ -0,0 +1,119 @@
+use super::dimmed_canvas::dim;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_4X6, MonoFont, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{Dimensions, DrawTarget, Point, RgbColor, Size, WebColors},
+    primitives::Rectangle,
+    text::{Baseline, Text},
+    Drawable, Pixel,
+};
+use std::{convert::Infallible, time::Duration};
+
+const FONT: MonoFont<'static> = FONT_4X6;
+
+/// Brightness renders draw their content with once it is stale, see [`FadedCanvas`]
+pub const STALE_BRIGHTNESS: u8 = 96;
+
+/// Borrows a canvas and draws everything onto it at a reduced brightness, used to grey out content
+/// that is out of date.
+///
+/// Unlike [`DimmedCanvas`](super::DimmedCanvas) it only lives for as long as the render needs it,
+/// so a render can fade part of a frame and draw the rest at full brightness.
+pub struct FadedCanvas<'a, D> {
+    brightness: u8,
+    canvas: &'a mut D,
+}
+
+impl<'a, D> FadedCanvas<'a, D> {
+    /// `brightness` is `0` for off and `255` for the colors unmodified
+    pub fn new(canvas: &'a mut D, brightness: u8) -> Self {
+        Self { brightness, canvas }
+    }
+}
+
+impl<D> Dimensions for FadedCanvas<'_, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn bounding_box(&self) -> Rectangle {
+        self.canvas.bounding_box()
+    }
+}
+
+impl<D> DrawTarget for FadedCanvas<'_, D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let brightness = self.brightness;
+
+        self.canvas.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, dim(brightness, color))),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let brightness = self.brightness;
+
+        self.canvas
+            .fill_contiguous(area, colors.into_iter().map(|color| dim(brightness, color)))
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.canvas.fill_solid(area, dim(self.brightness, color))
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.canvas.clear(dim(self.brightness, color))
+    }
+}
+
+/// Formats how old data is in its largest unit, i.e. `42m` or `3h`
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Draws a badge saying the content is stale and how old it is, in the top right corner of the
+/// canvas
+pub fn draw_stale_badge<D>(canvas: &mut D, age: Duration) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb888>,
+{
+    let text = format!("STALE {}", format_age(age));
+
+    let bounding_box = canvas.bounding_box();
+    let size = Size::new(
+        text.len() as u32 * FONT.character_size.width + 2,
+        FONT.character_size.height + 2,
+    );
+    let top_left = bounding_box.top_left
+        + Point::new(bounding_box.size.width.saturating_sub(size.width) as i32, 0);
+
+    canvas.fill_solid(&Rectangle::new(top_left, size), Rgb888::CSS_ORANGE)?;
+    Text::with_baseline(
+        &text,
+        top_left + Point::new(1, 1),
+        MonoTextStyle::new(&FONT, Rgb888::BLACK),
+        Baseline::Top,
+    )
+    .draw(canvas)?;
+
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use crate::{font::FontName, images::Images, render::FrameBuffer};
+use chrono::Local;
+use embedded_graphics::{
+    image::Image,
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, OriginDimensions, Point, Primitive, Size},
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
+    text::{renderer::TextRenderer, Baseline, Text},
+    Drawable, Pixel,
+};
+use parking_lot::Mutex;
+use rhai::{Engine, EvalAltResult, ImmutableString, Map, INT};
+use std::{
+    fmt::Write,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// The `ctx` handed to the script's `render` function.
+///
+/// Everything the script draws ends up in `frame`, which is copied onto the canvas once the script
+/// returns.
+#[derive(Clone)]
+pub(super) struct ScriptContext {
+    pub(super) frame: Arc<Mutex<FrameBuffer>>,
+
+    /// BMP images the script can draw, keyed by name
+    pub(super) images: Arc<Images>,
+
+    /// Latest JSON document fetched from each data source, keyed by the name of the source
+    pub(super) data: Arc<Mutex<Map>>,
+}
+
+/// Colors are passed as `0xRRGGBB`
+fn to_color(color: INT) -> Rgb888 {
+    Rgb888::new((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+/// Coordinates are kept far from the limits of `i32`, so embedded-graphics can add sizes to them
+/// without overflowing
+const MAX_COORDINATE: INT = 1 << 30;
+
+/// Circles are at most this many times as large as the frame, so a single call can't keep the
+/// render busy
+const MAX_CIRCLE_FRAMES: INT = 16;
+
+fn to_coordinate(value: INT) -> i32 {
+    value.clamp(-MAX_COORDINATE, MAX_COORDINATE) as i32
+}
+
+fn to_point(x: INT, y: INT) -> Point {
+    Point::new(to_coordinate(x), to_coordinate(y))
+}
+
+/// Clips the line from `start` to `end` to the pixels of a frame of `size` (Liang-Barsky), returns
+/// `None` if none of the line is in the frame
+fn clip_line(start: (INT, INT), end: (INT, INT), size: Size) -> Option<(Point, Point)> {
+    let (x, y) = (start.0 as f64, start.1 as f64);
+    let (dx, dy) = (end.0 as f64 - x, end.1 as f64 - y);
+    let (max_x, max_y) = (size.width as f64 - 1.0, size.height as f64 - 1.0);
+
+    // Portion of the line that is in the frame, 0 being `start` and 1 `end`
+    let (mut enter, mut exit) = (0.0_f64, 1.0_f64);
+    for (step, distance) in [(-dx, x), (dx, max_x - x), (-dy, y), (dy, max_y - y)] {
+        if step == 0.0 {
+            // Parallel to this edge of the frame
+            if distance < 0.0 {
+                return None;
+            }
+        } else if step < 0.0 {
+            enter = enter.max(distance / step);
+        } else {
+            exit = exit.min(distance / step);
+        }
+    }
+
+    if enter > exit {
+        return None;
+    }
+
+    let point = |t: f64| Point::new((x + t * dx).round() as i32, (y + t * dy).round() as i32);
+    Some((point(enter), point(exit)))
+}
+
+fn to_font(name: &str) -> ScriptResult<FontName> {
+    FontName::from_str(name).map_err(|e| e.to_string().into())
+}
+
+impl ScriptContext {
+    fn width(&mut self) -> INT {
+        self.frame.lock().size().width as INT
+    }
+
+    fn height(&mut self) -> INT {
+        self.frame.lock().size().height as INT
+    }
+
+    fn data(&mut self) -> Map {
+        self.data.lock().clone()
+    }
+
+    fn clear(&mut self, color: INT) {
+        let _ = self.frame.lock().clear(to_color(color));
+    }
+
+    fn pixel(&mut self, x: INT, y: INT, color: INT) {
+        let _ = self
+            .frame
+            .lock()
+            .draw_iter([Pixel(to_point(x, y), to_color(color))]);
+    }
+
+    fn line(&mut self, x1: INT, y1: INT, x2: INT, y2: INT, color: INT) {
+        let mut frame = self.frame.lock();
+
+        // Only the part of the line in the frame is drawn, the rest would be walked for nothing
+        let Some((start, end)) = clip_line((x1, y1), (x2, y2), frame.size()) else {
+            return;
+        };
+
+        let _ = Line::new(start, end)
+            .into_styled(PrimitiveStyle::with_stroke(to_color(color), 1))
+            .draw(&mut *frame);
+    }
+
+    fn rect(&mut self, x: INT, y: INT, width: INT, height: INT, style: PrimitiveStyle<Rgb888>) {
+        let mut frame = self.frame.lock();
+        let Size {
+            width: frame_width,
+            height: frame_height,
+        } = frame.size();
+
+        // Clipped to a pixel around the frame, so the edges of a stroked rectangle that are out
+        // of the frame stay out of it
+        let clip_x = |x: INT| x.clamp(-1, frame_width as INT + 1);
+        let clip_y = |y: INT| y.clamp(-1, frame_height as INT + 1);
+        let (left, right) = (clip_x(x), clip_x(x.saturating_add(width.max(0))));
+        let (top, bottom) = (clip_y(y), clip_y(y.saturating_add(height.max(0))));
+
+        let _ = Rectangle::new(
+            Point::new(left as i32, top as i32),
+            Size::new((right - left) as u32, (bottom - top) as u32),
+        )
+        .into_styled(style)
+        .draw(&mut *frame);
+    }
+
+    fn circle(&mut self, x: INT, y: INT, diameter: INT, style: PrimitiveStyle<Rgb888>) {
+        let mut frame = self.frame.lock();
+        let size = frame.size();
+        let diameter = diameter.max(0);
+
+        let outside = x >= size.width as INT
+            || y >= size.height as INT
+            || x.saturating_add(diameter) <= 0
+            || y.saturating_add(diameter) <= 0;
+        if outside {
+            return;
+        }
+
+        // Larger circles are drawn smaller around the same center
+        let max_diameter = MAX_CIRCLE_FRAMES * size.width.max(size.height) as INT;
+        let (x, y, diameter) = if diameter > max_diameter {
+            let shift = (diameter - max_diameter) / 2;
+            (x + shift, y + shift, max_diameter)
+        } else {
+            (x, y, diameter)
+        };
+
+        let _ = Circle::new(to_point(x, y), diameter as u32)
+            .into_styled(style)
+            .draw(&mut *frame);
+    }
+
+    /// Draws `text` with its top left corner at `(x, y)`, returns the position right after the
+    /// text so calls can be chained
+    fn text(&mut self, x: INT, y: INT, text: &str, color: INT, font: FontName) -> INT {
+        Text::with_baseline(
+            text,
+            to_point(x, y),
+            font.style(to_color(color)),
+            Baseline::Top,
+        )
+        .draw(&mut *self.frame.lock())
+        .map(|next| next.x as INT)
+        .unwrap_or(x)
+    }
+
+    fn image(&mut self, x: INT, y: INT, name: &str) -> ScriptResult<()> {
+        let image = self
+            .images
+            .get(name)
+            .ok_or_else(|| format!("Unknown image \"{name}\""))?;
+
+        let _ = Image::new(&image, to_point(x, y)).draw(&mut *self.frame.lock());
+
+        Ok(())
+    }
+}
+
+/// Registers the drawing API with the engine:
+///
+/// - `rgb(r, g, b)`: builds a color, colors can also be written as `0xRRGGBB`
+/// - `text_width(text)` and `text_width(text, font)`: width of `text` in pixels
+/// - `ctx.width` and `ctx.height`: size of the canvas in pixels
+/// - `ctx.time`: seconds since the Unix epoch
+/// - `ctx.format_time(format)`: formats the local time (i.e. `"%H:%M"`)
+/// - `ctx.data`: map of the JSON documents fetched from the data sources
+/// - `ctx.clear(color)`, `ctx.pixel(x, y, color)`, `ctx.line(x1, y1, x2, y2, color)`
+/// - `ctx.rect(x, y, width, height, color)` and `ctx.stroke_rect(...)`
+/// - `ctx.circle(x, y, diameter, color)` and `ctx.stroke_circle(...)`
+/// - `ctx.text(x, y, text, color)` and `ctx.text(x, y, text, color, font)`: `(x, y)` is the top
+///   left corner of the text, returns the x coordinate right after the text
+/// - `ctx.image(x, y, name)`: draws one of the images of the configuration
+pub(super) fn register(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<ScriptContext>("Context")
+        .register_fn("rgb", |r: INT, g: INT, b: INT| {
+            (r.clamp(0, 255) << 16) | (g.clamp(0, 255) << 8) | b.clamp(0, 255)
+        })
+        .register_fn("text_width", |text: &str| {
+            text_width(text, FontName::default()) as INT
+        })
+        .register_fn(
+            "text_width",
+            |text: &str, font: &str| -> ScriptResult<INT> {
+                Ok(text_width(text, to_font(font)?) as INT)
+            },
+        )
+        .register_get("width", ScriptContext::width)
+        .register_get("height", ScriptContext::height)
+        .register_get("data", ScriptContext::data)
+        .register_get("time", |_: &mut ScriptContext| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs() as INT)
+                .unwrap_or_default()
+        })
+        .register_fn(
+            "format_time",
+            |_: &mut ScriptContext, format: &str| -> ScriptResult<ImmutableString> {
+                // chrono only reports an invalid format once the time is written
+                let mut time = String::new();
+                write!(time, "{}", Local::now().format(format))
+                    .map_err(|_| format!("Invalid time format \"{format}\""))?;
+
+                Ok(time.into())
+            },
+        )
+        .register_fn("clear", ScriptContext::clear)
+        .register_fn("pixel", ScriptContext::pixel)
+        .register_fn("line", ScriptContext::line)
+        .register_fn(
+            "rect",
+            |ctx: &mut ScriptContext, x: INT, y: INT, width: INT, height: INT, color: INT| {
+                ctx.rect(
+                    x,
+                    y,
+                    width,
+                    height,
+                    PrimitiveStyle::with_fill(to_color(color)),
+                )
+            },
+        )
+        .register_fn(
+            "stroke_rect",
+            |ctx: &mut ScriptContext, x: INT, y: INT, width: INT, height: INT, color: INT| {
+                ctx.rect(
+                    x,
+                    y,
+                    width,
+                    height,
+                    PrimitiveStyle::with_stroke(to_color(color), 1),
+                )
+            },
+        )
+        .register_fn(
+            "circle",
+            |ctx: &mut ScriptContext, x: INT, y: INT, diameter: INT, color: INT| {
+                ctx.circle(x, y, diameter, PrimitiveStyle::with_fill(to_color(color)))
+            },
+        )
+        .register_fn(
+            "stroke_circle",
+            |ctx: &mut ScriptContext, x: INT, y: INT, diameter: INT, color: INT| {
+                ctx.circle(
+                    x,
+                    y,
+                    diameter,
+                    PrimitiveStyle::with_stroke(to_color(color), 1),
+                )
+            },
+        )
+        .register_fn(
+            "text",
+            |ctx: &mut ScriptContext, x: INT, y: INT, text: &str, color: INT| {
+                ctx.text(x, y, text, color, FontName::default())
+            },
+        )
+        .register_fn(
+            "text",
+            |ctx: &mut ScriptContext,
+             x: INT,
+             y: INT,
+             text: &str,
+             color: INT,
+             font: &str|
+             -> ScriptResult<INT> { Ok(ctx.text(x, y, text, color, to_font(font)?)) },
+        )
+        .register_fn("image", ScriptContext::image);
+}
+
+fn text_width(text: &str, font: FontName) -> u32 {
+    font.style(Rgb888::default())
+        .measure_string(text, Point::zero(), Baseline::Top)
+        .next_position
+        .x as u32
+}
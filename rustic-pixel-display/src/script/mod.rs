@@ -0,0 +1,257 @@
+use crate::{
+    data_source::{self, DataSource},
+    health::TaskHealth,
+    images::Images,
+    provider::Poller,
+    render::{FrameBuffer, Render, RenderFactory},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use context::ScriptContext;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, RgbColor},
+    text::{Baseline, Text},
+    Drawable,
+};
+use log::{info, warn};
+use parking_lot::Mutex;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    io::Read,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+mod context;
+
+fn default_max_operations() -> u64 {
+    100_000
+}
+
+/// Frames that take longer than this are logged, so slow scripts can be spotted
+const SLOW_FRAME: Duration = Duration::from_millis(20);
+
+#[derive(Deserialize)]
+struct ScriptConfig {
+    /// Source of the script, see [`ScriptRenderFactory`]
+    script: String,
+
+    /// JSON documents fetched on an interval, keyed by the name the script uses to access them
+    #[serde(default)]
+    data_sources: HashMap<String, DataSource>,
+
+    /// BMP images the script can draw, encoded in base64 and keyed by name
+    #[serde(default)]
+    images: HashMap<String, String>,
+
+    /// Upper bound on the number of operations the script can perform to draw a single frame
+    #[serde(default = "default_max_operations")]
+    max_operations: u64,
+}
+
+/// Constructs renders from [Rhai](https://rhai.rs) scripts.
+///
+/// The script must define a `render(ctx)` function which is called for every frame, `ctx` giving
+/// access to the drawing API described in [`context::register`]. The top level statements of the
+/// script are run once when the render is loaded. State that needs to survive between frames can
+/// be stored in `this`, which is an object map kept for the lifetime of the render.
+///
+/// ```rhai
+/// fn render(ctx) {
+///     this.frames = (this.frames ?? 0) + 1;
+///     ctx.text(0, 0, ctx.format_time("%H:%M"), rgb(255, 255, 255), "9x18 Bold");
+///     ctx.text(0, 20, `${ctx.data.weather?.temp ?? "-"} F`, 0x00AAFF);
+/// }
+/// ```
+///
+/// Errors raised by the script (including running over the operation limit) are shown on the
+/// display instead of the frame, the script is tried again on the next frame.
+#[derive(Default)]
+pub struct ScriptRenderFactory;
+
+impl<D> RenderFactory<D> for ScriptRenderFactory
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        "Script"
+    }
+
+    fn render_description(&self) -> &'static str {
+        "Draws whatever a Rhai script tells it to"
+    }
+
+    fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
+        let config: ScriptConfig = serde_json::from_reader(reader)?;
+        Ok(Box::new(ScriptRender::new(config)?))
+    }
+}
+
+struct ScriptState {
+    scope: Scope<'static>,
+
+    /// Bound to `this` when calling `render`
+    this: Dynamic,
+
+    /// Error raised while drawing the last frame
+    error: Option<String>,
+}
+
+struct ScriptRender {
+    engine: Engine,
+    ast: AST,
+    context: ScriptContext,
+    state: Mutex<ScriptState>,
+    data_sources: Vec<Poller>,
+}
+
+impl ScriptRender {
+    fn new(config: ScriptConfig) -> Result<Self> {
+        let ScriptConfig {
+            script,
+            data_sources,
+            images,
+            max_operations,
+        } = config;
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(max_operations);
+        engine.on_print(|text| info!("Script: {text}"));
+        engine.on_debug(|text, _, position| info!("Script ({position}): {text}"));
+        context::register(&mut engine);
+
+        let ast = engine.compile(script).context("Script failed to compile")?;
+
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == "render" && function.params.len() == 1)
+        {
+            bail!("Script doesn't define a render(ctx) function");
+        }
+
+        let images = Images::decode(images)?;
+
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow!("Script failed to run: {e}"))?;
+
+        let context = ScriptContext {
+            frame: Arc::new(Mutex::new(FrameBuffer::new(Default::default()))),
+            images: Arc::new(images),
+            data: Arc::new(Mutex::new(Map::new())),
+        };
+
+        let mut pollers = Vec::new();
+        for (name, source) in data_sources {
+            let data = context.data.clone();
+            let poller =
+                data_source::spawn(
+                    name,
+                    source,
+                    move |name, value| match rhai::serde::to_dynamic(value) {
+                        Ok(value) => {
+                            data.lock().insert(name.into(), value);
+                        }
+                        Err(e) => warn!("Data source \"{name}\" can't be used by the script: {e}"),
+                    },
+                )?;
+            pollers.push(poller);
+        }
+
+        Ok(Self {
+            engine,
+            ast,
+            context,
+            state: Mutex::new(ScriptState {
+                scope,
+                this: Map::new().into(),
+                error: None,
+            }),
+            data_sources: pollers,
+        })
+    }
+}
+
+impl<D> Render<D> for ScriptRender
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        let Self {
+            engine,
+            ast,
+            context,
+            state,
+            ..
+        } = self;
+
+        let mut state = state.lock();
+        let ScriptState { scope, this, error } = &mut *state;
+
+        {
+            let mut frame = context.frame.lock();
+            frame.resize(canvas.bounding_box().size);
+            frame.clear(Rgb888::BLACK)?;
+        }
+
+        let start = Instant::now();
+        let result = engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false).bind_this_ptr(this),
+            scope,
+            ast,
+            "render",
+            (context.clone(),),
+        );
+
+        match result {
+            Ok(_) => {
+                *error = None;
+
+                let elapsed = start.elapsed();
+                if elapsed > SLOW_FRAME {
+                    warn!("Script took {elapsed:?} to draw a frame");
+                }
+
+                context.frame.lock().draw_onto(canvas)
+            }
+            Err(e) => {
+                let message = e.to_string();
+
+                // Only log the error once, the script is run again for every frame
+                if error.as_ref() != Some(&message) {
+                    warn!("Script failed to draw a frame: {message}");
+                }
+
+                let columns = (canvas.bounding_box().size.width / FONT_6X10.character_size.width)
+                    .max(1) as usize;
+                let wrapped = message
+                    .chars()
+                    .collect::<Vec<_>>()
+                    .chunks(columns)
+                    .map(|line| line.iter().collect::<String>())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                Text::with_baseline(
+                    &wrapped,
+                    Point::zero(),
+                    MonoTextStyle::new(&FONT_6X10, Rgb888::RED),
+                    Baseline::Top,
+                )
+                .draw(canvas)?;
+
+                *error = Some(message);
+                Ok(())
+            }
+        }
+    }
+
+    fn health(&self) -> Vec<TaskHealth> {
+        self.data_sources.iter().map(Poller::health).collect()
+    }
+}
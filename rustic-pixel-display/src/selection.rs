@@ -0,0 +1,204 @@
+use crate::{
+    registry::{EventSender, RegistryEvent},
+    render::{panic_message, Render, UsefulnessVal},
+};
+use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
+use log::{debug, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    convert::Infallible,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// How often the renders are asked how useful they are
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(1);
+
+fn default_min_display_secs() -> u64 {
+    30
+}
+
+fn default_hysteresis() -> u8 {
+    1
+}
+
+/// Settings of the automatic selection of renders.
+///
+/// When enabled, the display shows the loaded render that reports the highest
+/// [`usefulness`](crate::render::Render::usefulness) instead of the render picked through
+/// [`Registry::select`](crate::registry::Registry::select).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmartSelection {
+    /// Shortest time a render stays on the display before it can be replaced
+    #[serde(default = "default_min_display_secs")]
+    pub min_display_secs: u64,
+
+    /// Levels of usefulness a render needs over the render on the display to replace it, `1`
+    /// switches to any render that is more useful
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: u8,
+}
+
+impl Default for SmartSelection {
+    fn default() -> Self {
+        Self {
+            min_display_secs: default_min_display_secs(),
+            hysteresis: default_hysteresis(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SelectorState {
+    /// The render on the display and since when
+    shown: Option<(Uuid, Instant)>,
+
+    /// The manual selection seen on the previous frame, a new manual selection is shown right away
+    manual: Option<Uuid>,
+
+    evaluated_at: Option<Instant>,
+    scores: HashMap<Uuid, UsefulnessVal>,
+}
+
+/// Picks the render shown by the [`RenderSet`](crate::registry::RenderSet)s of a registry in
+/// smart selection mode.
+///
+/// The selection is made while drawing, so it follows the renders' usefulness without a task of
+/// its own, and is remembered here between frames.
+pub(crate) struct SmartSelector {
+    state: Mutex<SelectorState>,
+    events: EventSender,
+}
+
+impl SmartSelector {
+    pub(crate) fn new(events: EventSender) -> Self {
+        Self {
+            state: Mutex::new(SelectorState::default()),
+            events,
+        }
+    }
+
+    /// Forgets the previous selection, the next frame starts from the manual selection
+    pub(crate) fn reset(&self) {
+        *self.state.lock() = SelectorState::default();
+    }
+
+    /// Returns the id of the render on the display
+    pub(crate) fn shown(&self) -> Option<Uuid> {
+        self.state.lock().shown.map(|(uuid, _)| uuid)
+    }
+
+    /// Returns the usefulness the render reported the last time it was asked
+    pub(crate) fn usefulness(&self, uuid: Uuid) -> Option<UsefulnessVal> {
+        self.state.lock().scores.get(&uuid).copied()
+    }
+
+    /// Picks the render to show among the `candidates`, `manual` being the render selected
+    /// through the registry
+    pub(crate) fn select<D>(
+        &self,
+        settings: &SmartSelection,
+        candidates: &[(Uuid, Arc<dyn Render<D>>)],
+        manual: Option<Uuid>,
+    ) -> Option<Uuid>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        let previous = state.shown.map(|(uuid, _)| uuid);
+        let is_candidate = |uuid: Uuid| candidates.iter().any(|(id, _)| *id == uuid);
+
+        // The shown render was unloaded
+        if previous.is_some_and(|uuid| !is_candidate(uuid)) {
+            state.shown = None;
+        }
+
+        if state.manual != manual {
+            state.manual = manual;
+
+            if let Some(manual) = manual.filter(|uuid| is_candidate(*uuid)) {
+                state.shown = Some((manual, now));
+            }
+        }
+
+        let evaluated_recently = state
+            .evaluated_at
+            .is_some_and(|evaluated_at| now - evaluated_at < EVALUATION_INTERVAL);
+        let evaluate = state.shown.is_none() || !evaluated_recently;
+
+        if evaluate {
+            state.scores = candidates
+                .iter()
+                .map(|(uuid, render)| (*uuid, usefulness(*uuid, render.as_ref())))
+                .collect();
+            state.evaluated_at = Some(now);
+
+            // Ties go to the manual selection, then to the first candidate
+            let best = candidates
+                .iter()
+                .enumerate()
+                .max_by_key(|(index, (uuid, _))| {
+                    (state.scores[uuid], Some(*uuid) == manual, Reverse(*index))
+                })
+                .map(|(_, (uuid, _))| *uuid);
+
+            state.shown = match (state.shown, best) {
+                (Some((shown, since)), Some(best)) if shown != best => {
+                    let shown_score = state.scores[&shown];
+                    let best_score = state.scores[&best];
+
+                    // A render with nothing to show is replaced by anything more useful
+                    let margin = if shown_score == UsefulnessVal::NotUseful {
+                        1
+                    } else {
+                        settings.hysteresis.max(1)
+                    };
+
+                    let displayed_long_enough =
+                        now - since >= Duration::from_secs(settings.min_display_secs);
+                    let more_useful =
+                        best_score as u8 >= (shown_score as u8).saturating_add(margin);
+
+                    if displayed_long_enough && more_useful {
+                        debug!("Switching to render {best}, {best_score:?} over {shown_score:?}");
+                        Some((best, now))
+                    } else {
+                        Some((shown, since))
+                    }
+                }
+                (None, Some(best)) => Some((best, now)),
+                (shown, _) => shown,
+            };
+        }
+
+        let shown = state.shown.map(|(uuid, _)| uuid);
+        drop(state);
+
+        if shown != previous {
+            self.events
+                .publish(RegistryEvent::SelectionChanged { id: shown });
+        }
+
+        shown
+    }
+}
+
+/// Asks a render how useful it is, a render that panics has nothing useful to show
+fn usefulness<D>(uuid: Uuid, render: &dyn Render<D>) -> UsefulnessVal
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| render.usefulness())).unwrap_or_else(|payload| {
+        warn!(
+            "Render {uuid} panicked reporting its usefulness: {}",
+            panic_message(payload.as_ref())
+        );
+        UsefulnessVal::NotUseful
+    })
+}
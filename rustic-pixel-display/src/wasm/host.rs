@@ -0,0 +1,163 @@
+use crate::render::FrameBuffer;
+use anyhow::Result;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{Dimensions, DrawTarget, Point, Size},
+    primitives::Rectangle,
+    text::{Baseline, Text},
+    Drawable, Pixel,
+};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use wasmi::{core::Trap, Caller, Extern, Linker, StoreLimits};
+
+/// Module name the drawing functions are imported from
+const HOST_MODULE: &str = "rustic_pixel";
+
+/// Longest string a module can draw with a single call to `draw_text`
+const MAX_TEXT_LEN: usize = 1024;
+
+/// Data owned by the store of every module instance
+pub(super) struct HostState {
+    /// The frame the module is currently drawing onto
+    pub(super) frame: FrameBuffer,
+    pub(super) limits: StoreLimits,
+
+    /// When the frame being drawn has to be done by, host functions called after that trap
+    pub(super) deadline: Option<Instant>,
+}
+
+/// Stops the module if it is past the frame's deadline
+fn check_deadline(caller: &Caller<'_, HostState>) -> Result<(), Trap> {
+    match caller.data().deadline {
+        Some(deadline) if Instant::now() > deadline => {
+            Err(Trap::new("module took too long to draw a frame"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Colors are passed as `0x00RRGGBB`
+fn to_color(color: i32) -> Rgb888 {
+    Rgb888::new((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+/// Defines the functions modules can import from the `rustic_pixel` module:
+///
+/// - `width() -> i32` and `height() -> i32`: size of the frame in pixels
+/// - `time_ms() -> i64`: milliseconds since the Unix epoch
+/// - `set_pixel(x: i32, y: i32, color: i32)`
+/// - `fill_rect(x: i32, y: i32, width: i32, height: i32, color: i32)`
+/// - `draw_text(ptr: i32, len: i32, x: i32, y: i32, color: i32)`: draws the UTF-8 string found
+///   in the module's memory using a 6x10 font, `(x, y)` being the top left corner of the text
+///
+/// Every function traps once the frame's deadline has passed. Modules that don't call any of them
+/// are only limited by their fuel.
+pub(super) fn define(linker: &mut Linker<HostState>) -> Result<()> {
+    linker
+        .func_wrap(
+            HOST_MODULE,
+            "width",
+            |caller: Caller<'_, HostState>| -> Result<i32, Trap> {
+                check_deadline(&caller)?;
+                Ok(caller.data().frame.bounding_box().size.width as i32)
+            },
+        )?
+        .func_wrap(
+            HOST_MODULE,
+            "height",
+            |caller: Caller<'_, HostState>| -> Result<i32, Trap> {
+                check_deadline(&caller)?;
+                Ok(caller.data().frame.bounding_box().size.height as i32)
+            },
+        )?
+        .func_wrap(
+            HOST_MODULE,
+            "time_ms",
+            |caller: Caller<'_, HostState>| -> Result<i64, Trap> {
+                check_deadline(&caller)?;
+                Ok(SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_millis() as i64)
+                    .unwrap_or_default())
+            },
+        )?
+        .func_wrap(
+            HOST_MODULE,
+            "set_pixel",
+            |mut caller: Caller<'_, HostState>, x: i32, y: i32, color: i32| -> Result<(), Trap> {
+                check_deadline(&caller)?;
+                let _ = caller
+                    .data_mut()
+                    .frame
+                    .draw_iter([Pixel(Point::new(x, y), to_color(color))]);
+
+                Ok(())
+            },
+        )?
+        .func_wrap(
+            HOST_MODULE,
+            "fill_rect",
+            |mut caller: Caller<'_, HostState>,
+             x: i32,
+             y: i32,
+             width: i32,
+             height: i32,
+             color: i32|
+             -> Result<(), Trap> {
+                check_deadline(&caller)?;
+
+                let area = Rectangle::new(
+                    Point::new(x, y),
+                    Size::new(width.max(0) as u32, height.max(0) as u32),
+                );
+
+                // Only draw the part of the rectangle that is visible
+                let frame = &mut caller.data_mut().frame;
+                let area = area.intersection(&frame.bounding_box());
+                let _ = frame.fill_solid(&area, to_color(color));
+
+                Ok(())
+            },
+        )?
+        .func_wrap(
+            HOST_MODULE,
+            "draw_text",
+            |mut caller: Caller<'_, HostState>,
+             ptr: i32,
+             len: i32,
+             x: i32,
+             y: i32,
+             color: i32|
+             -> Result<(), Trap> {
+                check_deadline(&caller)?;
+
+                if ptr < 0 || len < 0 || len as usize > MAX_TEXT_LEN {
+                    return Err(Trap::new("draw_text called with an invalid string"));
+                }
+
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(Extern::into_memory)
+                    .ok_or_else(|| Trap::new("module doesn't export its memory"))?;
+
+                let mut text = vec![0; len as usize];
+                memory
+                    .read(&caller, ptr as usize, &mut text)
+                    .map_err(|_| Trap::new("draw_text called with an out of bounds string"))?;
+
+                let text = String::from_utf8_lossy(&text);
+                let _ = Text::with_baseline(
+                    &text,
+                    Point::new(x, y),
+                    MonoTextStyle::new(&FONT_6X10, to_color(color)),
+                    Baseline::Top,
+                )
+                .draw(&mut caller.data_mut().frame);
+
+                Ok(())
+            },
+        )?;
+
+    Ok(())
+}
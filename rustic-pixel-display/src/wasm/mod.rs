@@ -0,0 +1,249 @@
+use crate::render::{FrameBuffer, Render, RenderFactory};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, RgbColor},
+    text::{Baseline, Text},
+    Drawable,
+};
+use host::HostState;
+use log::warn;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{
+    convert::Infallible,
+    io::Read,
+    time::{Duration, Instant},
+};
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimitsBuilder, TypedFunc};
+
+mod host;
+
+fn default_fuel_per_frame() -> u64 {
+    5_000_000
+}
+
+fn default_max_frame_time_ms() -> u64 {
+    100
+}
+
+fn default_max_memory() -> usize {
+    16 * 1024 * 1024
+}
+
+/// Frames that take longer than this are logged, so slow modules can be spotted
+const SLOW_FRAME: Duration = Duration::from_millis(20);
+
+#[derive(Deserialize)]
+struct WasmConfig {
+    /// The WebAssembly module, encoded in base64
+    module: String,
+
+    /// Upper bound on the amount of work the module can do to draw a single frame, roughly the
+    /// number of instructions executed
+    #[serde(default = "default_fuel_per_frame")]
+    fuel_per_frame: u64,
+
+    /// Upper bound on the time (in milliseconds) the module can spend drawing a single frame,
+    /// checked whenever the module calls one of the drawing functions
+    #[serde(default = "default_max_frame_time_ms")]
+    max_frame_time_ms: u64,
+
+    /// The most memory (in bytes) the module can allocate
+    #[serde(default = "default_max_memory")]
+    max_memory: usize,
+
+    /// Configuration handed to the module's `init` function as a JSON document
+    #[serde(default)]
+    config: Option<serde_json::Value>,
+}
+
+/// Constructs renders from WebAssembly modules.
+///
+/// Modules run in a sandbox: they can only draw through the functions described in
+/// [`host::define`] and each frame is limited in the amount of work it can do and in the time it
+/// takes. A module that traps (i.e. runs out of fuel or time, accesses memory out of bounds, etc.)
+/// is stopped and the error is shown on the display instead.
+///
+/// A module must export its `memory` and a `render()` function that is called for every frame.
+/// Modules that accept a configuration also export `alloc(len: i32) -> i32`, which returns a
+/// buffer the configuration is copied into, and `init(ptr: i32, len: i32) -> i32`, which returns
+/// `0` if the configuration was accepted.
+#[derive(Default)]
+pub struct WasmRenderFactory;
+
+impl<D> RenderFactory<D> for WasmRenderFactory
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        "WebAssembly"
+    }
+
+    fn render_description(&self) -> &'static str {
+        "Runs a render compiled to WebAssembly in a sandbox"
+    }
+
+    fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
+        let config: WasmConfig = serde_json::from_reader(reader)?;
+        Ok(Box::new(WasmRender::new(config)?))
+    }
+}
+
+struct WasmInstance {
+    store: Store<HostState>,
+    render: TypedFunc<(), ()>,
+    fuel_per_frame: u64,
+    max_frame_time: Duration,
+
+    /// Set once the module trapped, the module isn't run anymore
+    error: Option<String>,
+}
+
+impl WasmInstance {
+    /// Tops the fuel back up to the per frame budget
+    fn refuel(&mut self) -> Result<()> {
+        let remaining = self.store.consume_fuel(0).map_err(|e| anyhow!("{e}"))?;
+        self.store
+            .add_fuel(self.fuel_per_frame.saturating_sub(remaining))
+            .map_err(|e| anyhow!("{e}"))
+    }
+}
+
+struct WasmRender {
+    instance: Mutex<WasmInstance>,
+}
+
+impl WasmRender {
+    fn new(config: WasmConfig) -> Result<Self> {
+        let WasmConfig {
+            module,
+            fuel_per_frame,
+            max_frame_time_ms,
+            max_memory,
+            config,
+        } = config;
+
+        let module = STANDARD
+            .decode(module)
+            .context("Module is not valid base64")?;
+
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+
+        let module = Module::new(&engine, module.as_slice()).context("Invalid module")?;
+
+        let mut store = Store::new(
+            &engine,
+            HostState {
+                frame: FrameBuffer::new(Default::default()),
+                limits: StoreLimitsBuilder::new().memory_size(max_memory).build(),
+                deadline: None,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(fuel_per_frame).map_err(|e| anyhow!("{e}"))?;
+
+        let mut linker = Linker::new(&engine);
+        host::define(&mut linker)?;
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .context("Could not instantiate the module")?
+            .start(&mut store)
+            .context("Module failed to start")?;
+
+        let render = instance
+            .get_typed_func::<(), ()>(&store, "render")
+            .context("Module doesn't export a render() function")?;
+
+        if let Some(config) = config {
+            let config = serde_json::to_vec(&config)?;
+
+            let alloc = instance
+                .get_typed_func::<i32, i32>(&store, "alloc")
+                .context("Module doesn't accept a configuration, alloc() is missing")?;
+            let init = instance
+                .get_typed_func::<(i32, i32), i32>(&store, "init")
+                .context("Module doesn't accept a configuration, init() is missing")?;
+            let memory = instance
+                .get_memory(&store, "memory")
+                .context("Module doesn't export its memory")?;
+
+            let ptr = alloc.call(&mut store, config.len() as i32)?;
+            memory
+                .write(&mut store, ptr as usize, &config)
+                .map_err(|e| anyhow!("Could not copy the configuration: {e}"))?;
+
+            let status = init.call(&mut store, (ptr, config.len() as i32))?;
+            if status != 0 {
+                bail!("Module rejected the configuration (status {status})");
+            }
+        }
+
+        Ok(Self {
+            instance: Mutex::new(WasmInstance {
+                store,
+                render,
+                fuel_per_frame,
+                max_frame_time: Duration::from_millis(max_frame_time_ms),
+                error: None,
+            }),
+        })
+    }
+}
+
+impl<D> Render<D> for WasmRender
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        let mut instance = self.instance.lock();
+
+        if instance.error.is_none() {
+            let start = Instant::now();
+
+            let max_frame_time = instance.max_frame_time;
+            let state = instance.store.data_mut();
+            state.deadline = Some(start + max_frame_time);
+            state.frame.resize(canvas.bounding_box().size);
+            state.frame.clear(Rgb888::BLACK)?;
+
+            let render = instance.render;
+            let result = instance
+                .refuel()
+                .and_then(|_| Ok(render.call(&mut instance.store, ())?));
+
+            match result {
+                Ok(_) => {
+                    let elapsed = start.elapsed();
+                    if elapsed > SLOW_FRAME {
+                        warn!("WebAssembly render took {elapsed:?} to draw a frame");
+                    }
+                }
+                Err(e) => {
+                    warn!("WebAssembly render stopped: {e:#}");
+                    instance.error = Some(format!("{e:#}"));
+                }
+            }
+        }
+
+        match &instance.error {
+            None => instance.store.data().frame.draw_onto(canvas),
+            Some(error) => {
+                Text::with_baseline(
+                    error,
+                    Point::zero(),
+                    MonoTextStyle::new(&FONT_6X10, Rgb888::RED),
+                    Baseline::Top,
+                )
+                .draw(canvas)?;
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    data_source::{self, DataSource},
+    health::TaskHealth,
+    images::Images,
+    provider::Poller,
+    render::{Render, RenderFactory},
+};
+use anyhow::Result;
+use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, convert::Infallible, io::Read, sync::Arc};
+use tree::{Widget, WidgetContext};
+
+mod template;
+mod tree;
+
+#[derive(Deserialize)]
+struct WidgetConfig {
+    /// The widget drawn onto the whole canvas, usually a `row` or a `column`
+    root: Widget,
+
+    /// JSON documents fetched on an interval, keyed by the name templates use to access them
+    #[serde(default)]
+    data_sources: HashMap<String, DataSource>,
+
+    /// BMP images used by `image` widgets, encoded in base64 and keyed by name
+    #[serde(default)]
+    images: HashMap<String, String>,
+}
+
+/// Constructs renders from a tree of widgets described in JSON.
+///
+/// The tree is made of `text`, `image`, `rectangle` and `progress_bar` widgets arranged by `row`
+/// and `column` layouts. Text and progress bar values can be bound to data sources using
+/// templates (i.e. `"{{stock.price:.2}}"`), so boards showing live data can be built without
+/// writing any Rust code.
+///
+/// ```json
+/// {
+///     "data_sources": { "stock": { "url": "https://example.com/quote/AAPL", "interval_secs": 60 } },
+///     "root": {
+///         "type": "column",
+///         "spacing": 2,
+///         "children": [
+///             { "type": "text", "text": "AAPL", "font": "6x13 Bold" },
+///             { "type": "text", "text": "${{stock.price:.2 | --}}", "color": "#00FF00" },
+///             { "type": "progress_bar", "value": "{{stock.day_range_percent}}" }
+///         ]
+///     }
+/// }
+/// ```
+#[derive(Default)]
+pub struct WidgetRenderFactory;
+
+impl<D> RenderFactory<D> for WidgetRenderFactory
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        "Widget"
+    }
+
+    fn render_description(&self) -> &'static str {
+        "Draws a tree of widgets described in JSON"
+    }
+
+    fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
+        let config: WidgetConfig = serde_json::from_reader(reader)?;
+        Ok(Box::new(WidgetRender::new(config)?))
+    }
+}
+
+struct WidgetRender {
+    root: Widget,
+    images: Images,
+
+    /// Latest document of each data source, keyed by the name of the source
+    data: Arc<Mutex<Value>>,
+    data_sources: Vec<Poller>,
+}
+
+impl WidgetRender {
+    fn new(config: WidgetConfig) -> Result<Self> {
+        let WidgetConfig {
+            root,
+            data_sources,
+            images,
+        } = config;
+
+        let images = Images::decode(images)?;
+        root.validate(&images)?;
+
+        let data = Arc::new(Mutex::new(Value::Object(Default::default())));
+
+        let mut pollers = Vec::new();
+        for (name, source) in data_sources {
+            let data = data.clone();
+            let poller = data_source::spawn(name, source, move |name, value| {
+                if let Value::Object(data) = &mut *data.lock() {
+                    data.insert(name.to_owned(), value);
+                }
+            })?;
+            pollers.push(poller);
+        }
+
+        Ok(Self {
+            root,
+            images,
+            data,
+            data_sources: pollers,
+        })
+    }
+}
+
+impl<D> Render<D> for WidgetRender
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        let Self {
+            root, images, data, ..
+        } = self;
+
+        let data = data.lock();
+        let area = canvas.bounding_box();
+        root.draw(
+            canvas,
+            area,
+            &WidgetContext {
+                data: &data,
+                images,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn health(&self) -> Vec<TaskHealth> {
+        self.data_sources.iter().map(Poller::health).collect()
+    }
+}
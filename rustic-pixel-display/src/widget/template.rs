@@ -0,0 +1,179 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A step taken to reach a value inside a JSON document
+#[derive(Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug)]
+struct Binding {
+    /// The first segment is the name of the data source
+    path: Vec<Segment>,
+
+    /// Number of decimals numbers are shown with, numbers are shown as is if not set
+    precision: Option<usize>,
+
+    /// Shown when the value is missing (i.e. the data source wasn't fetched yet)
+    fallback: String,
+}
+
+#[derive(Debug)]
+enum Part {
+    Literal(String),
+    Binding(Binding),
+}
+
+/// Text that can contain values taken from the data sources.
+///
+/// Values are written as `{{source.path.to[0].value}}`, the first element of the path being the
+/// name of the data source. A number of decimals can be given with `{{source.price:.2}}` and the
+/// text shown while the value is missing with `{{source.price | --}}`.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub(super) struct Template(Vec<Part>);
+
+impl Template {
+    /// Produces the text using the latest documents of the data sources, keyed by name
+    pub(super) fn render(&self, data: &Value) -> String {
+        let Self(parts) = self;
+
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                Part::Literal(literal) => text.push_str(literal),
+                Part::Binding(binding) => match binding.lookup(data) {
+                    Some(value) => text.push_str(&value),
+                    None => text.push_str(&binding.fallback),
+                },
+            }
+        }
+
+        text
+    }
+}
+
+impl Binding {
+    fn lookup(&self, data: &Value) -> Option<String> {
+        let Self {
+            path, precision, ..
+        } = self;
+
+        let value = path.iter().try_fold(data, |value, segment| match segment {
+            Segment::Key(key) => value.get(key),
+            Segment::Index(index) => value.get(index),
+        })?;
+
+        match (value, precision) {
+            (Value::Null, _) => None,
+            (Value::String(value), _) => Some(value.clone()),
+            (Value::Number(value), Some(precision)) => {
+                value.as_f64().map(|value| format!("{value:.precision$}"))
+            }
+            (value, _) => Some(value.to_string()),
+        }
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+
+    for element in path.split('.') {
+        let (key, indexes) = element.split_at(element.find('[').unwrap_or(element.len()));
+        if key.is_empty() {
+            bail!("\"{path}\" contains an empty key");
+        }
+        segments.push(Segment::Key(key.to_owned()));
+
+        let mut indexes = indexes;
+        while !indexes.is_empty() {
+            let (index, rest) = indexes
+                .strip_prefix('[')
+                .and_then(|indexes| indexes.split_once(']'))
+                .ok_or_else(|| anyhow!("\"{path}\" contains an unterminated index"))?;
+            let index = index
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("\"{path}\" contains an invalid index \"{index}\""))?;
+
+            segments.push(Segment::Index(index));
+            indexes = rest;
+        }
+    }
+
+    Ok(segments)
+}
+
+fn parse_binding(binding: &str) -> Result<Binding> {
+    let (expression, fallback) = match binding.split_once('|') {
+        Some((expression, fallback)) => (expression, fallback.trim()),
+        None => (binding, ""),
+    };
+
+    let (path, precision) = match expression.split_once(":.") {
+        Some((path, precision)) => {
+            let precision = precision
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid precision in \"{{{{{binding}}}}}\""))?;
+            (path, Some(precision))
+        }
+        None => (expression, None),
+    };
+
+    Ok(Binding {
+        path: parse_path(path.trim())?,
+        precision,
+        fallback: fallback.to_owned(),
+    })
+}
+
+impl TryFrom<String> for Template {
+    type Error = anyhow::Error;
+
+    fn try_from(template: String) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_owned()));
+            }
+
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| anyhow!("Unterminated binding in \"{template}\""))?;
+            parts.push(Part::Binding(parse_binding(&rest[start + 2..start + end])?));
+
+            rest = &rest[start + end + 2..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_owned()));
+        }
+
+        Ok(Self(parts))
+    }
+}
+
+/// A number that is either given directly or taken from the data sources (i.e.
+/// `"{{stock.change_percent}}"`)
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum Number {
+    Value(f64),
+    Template(Template),
+}
+
+impl Number {
+    /// Returns `None` if the value is missing or isn't a number
+    pub(super) fn resolve(&self, data: &Value) -> Option<f64> {
+        match self {
+            Number::Value(value) => Some(*value),
+            Number::Template(template) => template.render(data).trim().parse().ok(),
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use super::template::{Number, Template};
+use crate::{color::Color, font::FontName, images::Images};
+use anyhow::{bail, Result};
+use embedded_graphics::{
+    image::Image,
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, DrawTargetExt, OriginDimensions, Point, Primitive, RgbColor, Size},
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::{renderer::TextRenderer, Baseline, Text},
+    Drawable,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::convert::Infallible;
+
+fn white() -> Color {
+    Color(Rgb888::WHITE)
+}
+
+fn dark_gray() -> Color {
+    Color(Rgb888::new(40, 40, 40))
+}
+
+fn default_max() -> f64 {
+    100.0
+}
+
+fn default_bar_height() -> u32 {
+    4
+}
+
+fn default_stroke_width() -> u32 {
+    1
+}
+
+/// Where children are placed across the direction of a layout (i.e. vertically in a row)
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Align {
+    #[default]
+    Start,
+    Center,
+    End,
+}
+
+/// A node of the widget tree.
+///
+/// Widgets are laid out using their natural size. Rectangles and progress bars that don't set a
+/// `width` or `height` stretch to fill the space left in their layout instead.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum Widget {
+    Text {
+        text: Template,
+        #[serde(default)]
+        font: FontName,
+        #[serde(default = "white")]
+        color: Color,
+    },
+    Image {
+        image: String,
+    },
+    Rectangle {
+        width: Option<u32>,
+        height: Option<u32>,
+        fill: Option<Color>,
+        stroke: Option<Color>,
+        #[serde(default = "default_stroke_width")]
+        stroke_width: u32,
+    },
+    ProgressBar {
+        value: Number,
+        #[serde(default)]
+        min: f64,
+        #[serde(default = "default_max")]
+        max: f64,
+        width: Option<u32>,
+        #[serde(default = "default_bar_height")]
+        height: u32,
+        #[serde(default = "white")]
+        color: Color,
+        #[serde(default = "dark_gray")]
+        background: Color,
+    },
+    Row {
+        children: Vec<Widget>,
+        #[serde(default)]
+        spacing: u32,
+        #[serde(default)]
+        padding: u32,
+        #[serde(default)]
+        align: Align,
+    },
+    Column {
+        children: Vec<Widget>,
+        #[serde(default)]
+        spacing: u32,
+        #[serde(default)]
+        padding: u32,
+        #[serde(default)]
+        align: Align,
+    },
+}
+
+/// What widgets are drawn with
+pub(super) struct WidgetContext<'a> {
+    /// Latest documents of the data sources, keyed by name
+    pub(super) data: &'a Value,
+    pub(super) images: &'a Images,
+}
+
+/// Lengths along the direction of a layout and across it, so rows and columns share their code
+fn split(size: Size, horizontal: bool) -> (u32, u32) {
+    if horizontal {
+        (size.width, size.height)
+    } else {
+        (size.height, size.width)
+    }
+}
+
+fn join(along: u32, across: u32, horizontal: bool) -> Size {
+    if horizontal {
+        Size::new(along, across)
+    } else {
+        Size::new(across, along)
+    }
+}
+
+impl Widget {
+    /// Makes sure every image the tree uses is part of the configuration
+    pub(super) fn validate(&self, images: &Images) -> Result<()> {
+        match self {
+            Widget::Image { image } if !images.contains(image) => {
+                bail!("Unknown image \"{image}\"")
+            }
+            Widget::Row { children, .. } | Widget::Column { children, .. } => {
+                children.iter().try_for_each(|child| child.validate(images))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the widget stretches horizontally and vertically
+    fn stretches(&self) -> (bool, bool) {
+        match self {
+            Widget::Rectangle { width, height, .. } => (width.is_none(), height.is_none()),
+            Widget::ProgressBar { width, .. } => (width.is_none(), false),
+            _ => (false, false),
+        }
+    }
+
+    /// The size the widget needs, stretching dimensions are reported as 0
+    fn measure(&self, context: &WidgetContext) -> Size {
+        match self {
+            Widget::Text { text, font, color } => {
+                let text = text.render(context.data);
+                font.style(color.0)
+                    .measure_string(&text, Point::zero(), Baseline::Top)
+                    .bounding_box
+                    .size
+            }
+            Widget::Image { image } => context
+                .images
+                .get(image)
+                .map(|image| image.size())
+                .unwrap_or_default(),
+            Widget::Rectangle { width, height, .. } => {
+                Size::new(width.unwrap_or(0), height.unwrap_or(0))
+            }
+            Widget::ProgressBar { width, height, .. } => Size::new(width.unwrap_or(0), *height),
+            Widget::Row {
+                children,
+                spacing,
+                padding,
+                ..
+            } => Self::measure_layout(children, *spacing, *padding, true, context),
+            Widget::Column {
+                children,
+                spacing,
+                padding,
+                ..
+            } => Self::measure_layout(children, *spacing, *padding, false, context),
+        }
+    }
+
+    fn measure_layout(
+        children: &[Widget],
+        spacing: u32,
+        padding: u32,
+        horizontal: bool,
+        context: &WidgetContext,
+    ) -> Size {
+        let (along, across) = children
+            .iter()
+            .map(|child| split(child.measure(context), horizontal))
+            .fold((0u32, 0), |(along, across), (child_along, child_across)| {
+                (along.saturating_add(child_along), across.max(child_across))
+            });
+        let gaps = spacing.saturating_mul(children.len().max(1) as u32 - 1);
+
+        // Sizes come from the configuration, they saturate instead of overflowing
+        join(
+            along
+                .saturating_add(gaps)
+                .saturating_add(padding.saturating_mul(2)),
+            across.saturating_add(padding.saturating_mul(2)),
+            horizontal,
+        )
+    }
+
+    /// Draws the widget into `area`, anything outside of the area is clipped
+    pub(super) fn draw<D>(&self, canvas: &mut D, area: Rectangle, context: &WidgetContext)
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        // Layouts don't clip themselves, their children do. Wrapping the canvas at every level
+        // of the tree would nest the canvas type once per level.
+        match self {
+            Widget::Row {
+                children,
+                spacing,
+                padding,
+                align,
+            } => Self::draw_layout(
+                canvas, area, children, *spacing, *padding, *align, true, context,
+            ),
+            Widget::Column {
+                children,
+                spacing,
+                padding,
+                align,
+            } => Self::draw_layout(
+                canvas, area, children, *spacing, *padding, *align, false, context,
+            ),
+            _ => {
+                // Drawing onto the canvas can't fail
+                let _ = self.draw_leaf(&mut canvas.clipped(&area), area, context);
+            }
+        }
+    }
+
+    fn draw_leaf<D>(
+        &self,
+        canvas: &mut D,
+        area: Rectangle,
+        context: &WidgetContext,
+    ) -> Result<(), Infallible>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let origin = area.top_left;
+
+        match self {
+            Widget::Text { text, font, color } => {
+                Text::with_baseline(
+                    &text.render(context.data),
+                    origin,
+                    font.style(color.0),
+                    Baseline::Top,
+                )
+                .draw(canvas)?;
+                Ok(())
+            }
+            Widget::Image { image } => match context.images.get(image) {
+                Some(image) => Image::new(&image, origin).draw(canvas),
+                None => Ok(()),
+            },
+            Widget::Rectangle {
+                fill,
+                stroke,
+                stroke_width,
+                ..
+            } => {
+                let mut style = PrimitiveStyleBuilder::new();
+                if let Some(Color(fill)) = fill {
+                    style = style.fill_color(*fill);
+                }
+                if let Some(Color(stroke)) = stroke {
+                    style = style.stroke_color(*stroke).stroke_width(*stroke_width);
+                }
+
+                area.into_styled(style.build()).draw(canvas)
+            }
+            Widget::ProgressBar {
+                value,
+                min,
+                max,
+                color,
+                background,
+                ..
+            } => {
+                let fraction = value
+                    .resolve(context.data)
+                    .filter(|_| max > min)
+                    .map(|value| ((value - min) / (max - min)).clamp(0.0, 1.0))
+                    .unwrap_or(0.0);
+                let filled = Size::new(
+                    (area.size.width as f64 * fraction).round() as u32,
+                    area.size.height,
+                );
+
+                canvas.fill_solid(&area, background.0)?;
+                canvas.fill_solid(&Rectangle::new(origin, filled), color.0)
+            }
+            Widget::Row { .. } | Widget::Column { .. } => Ok(()),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_layout<D>(
+        canvas: &mut D,
+        area: Rectangle,
+        children: &[Widget],
+        spacing: u32,
+        padding: u32,
+        align: Align,
+        horizontal: bool,
+        context: &WidgetContext,
+    ) where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let inner = area.offset(-i32::try_from(padding).unwrap_or(i32::MAX));
+        let (available, across) = split(inner.size, horizontal);
+
+        let sizes: Vec<_> = children
+            .iter()
+            .map(|child| split(child.measure(context), horizontal))
+            .collect();
+        let stretching = children
+            .iter()
+            .filter(|child| split_flags(child.stretches(), horizontal).0)
+            .count() as u32;
+
+        // The space left once every other child got what it needs is shared between the children
+        // that stretch
+        let used = sizes
+            .iter()
+            .fold(0u32, |used, (along, _)| used.saturating_add(*along))
+            .saturating_add(spacing.saturating_mul(children.len().max(1) as u32 - 1));
+        let stretched = available.saturating_sub(used) / stretching.max(1);
+
+        let mut offset = 0u32;
+        for (child, (child_along, child_across)) in children.iter().zip(sizes) {
+            // The remaining children start past the end of the area, they wouldn't be seen
+            if offset >= available {
+                break;
+            }
+
+            let (stretches_along, stretches_across) = split_flags(child.stretches(), horizontal);
+
+            // Nested layouts always get the whole width of a column (or height of a row), so
+            // their own children can stretch
+            let stretches_across =
+                stretches_across || matches!(child, Widget::Row { .. } | Widget::Column { .. });
+
+            let child_along = if stretches_along {
+                stretched
+            } else {
+                child_along
+            };
+            let child_across = if stretches_across {
+                across
+            } else {
+                child_across.min(across)
+            };
+
+            let cross_offset = match align {
+                Align::Start => 0,
+                Align::Center => (across - child_across) / 2,
+                Align::End => across - child_across,
+            };
+
+            let along_offset = i32::try_from(offset).unwrap_or(i32::MAX);
+            let cross_offset = i32::try_from(cross_offset).unwrap_or(i32::MAX);
+            let (x, y) = if horizontal {
+                (along_offset, cross_offset)
+            } else {
+                (cross_offset, along_offset)
+            };
+            let top_left = Point::new(
+                inner.top_left.x.saturating_add(x),
+                inner.top_left.y.saturating_add(y),
+            );
+
+            child.draw(
+                canvas,
+                Rectangle::new(top_left, join(child_along, child_across, horizontal)),
+                context,
+            );
+
+            offset = offset.saturating_add(child_along).saturating_add(spacing);
+        }
+    }
+}
+
+fn split_flags((horizontal_flag, vertical_flag): (bool, bool), horizontal: bool) -> (bool, bool) {
+    if horizontal {
+        (horizontal_flag, vertical_flag)
+    } else {
+        (vertical_flag, horizontal_flag)
+    }
+}
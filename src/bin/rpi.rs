@@ -2,20 +2,31 @@ use anyhow::Result;
 use rustic_pixel_display::{
     config::{HardwareConfig, HardwareMapping, LedSequence, RowAddressSetterType},
     driver::{self, RustHardwareDriver},
+    provider,
 };
 
-use rustic_pixel_examples::renders::upcoming_arrivals::{UpcomingArrivals, UpcomingArrivalsConfig};
+use rustic_pixel_examples::renders::upcoming_arrivals::{
+    ArrivalSourceConfig, UpcomingArrivals, UpcomingArrivalsConfig,
+};
 use septa_api::types::RegionalRailStop;
+use std::env::var;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
+    // Providers restore their last payloads from the cache when they are created, so the cache
+    // needs to be set up before any render is loaded
+    provider::set_cache_dir(var("CACHE_DIR").unwrap_or_else(|_| "cache".to_owned()))?;
+
     let _led_driver = driver::MatrixDriver::with_single_render::<RustHardwareDriver, _>(
         UpcomingArrivals::new(UpcomingArrivalsConfig {
             septa_station: Some(RegionalRailStop::SuburbanStation),
             amtrak_station: None,
             results: Some(20),
+            septa_source: ArrivalSourceConfig::Live,
+            amtrak_source: ArrivalSourceConfig::Live,
+            stale_after_secs: 5 * 60,
         })?,
         HardwareConfig {
             hardware_mapping: HardwareMapping::Regular,
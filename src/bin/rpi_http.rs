@@ -1,43 +1,110 @@
 use anyhow::Result;
 use embedded_graphics::{pixelcolor::Rgb888, prelude::DrawTarget};
-use parking_lot::Mutex;
 use rustic_pixel_display::{
     config::{HardwareConfig, HardwareMapping, LedSequence, RowAddressSetterType},
     driver::{self, HardwareDriver, RustHardwareDriver},
+    font,
+    http_server::ApiTokens,
+    mqtt::{serve_mqtt, MqttConfig},
+    plugin, provider,
+};
+use rustic_pixel_display::{
+    image_render::ImageRenderFactory,
+    registry::Registry,
+    render::{DimmedCanvas, Render},
+    script::ScriptRenderFactory,
+    wasm::WasmRenderFactory,
+    widget::WidgetRenderFactory,
 };
-use rustic_pixel_display::{registry::Registry, render::Render};
 use rustic_pixel_display_macros::RenderFactories;
-use rustic_pixel_examples::renders::{
-    person_tracker::TransitTrackerFactory, upcoming_arrivals::UpcomingArrivalsFactory,
-    weather::WeatherFactory,
+use rustic_pixel_examples::{
+    recordings,
+    renders::{
+        clock::ClockFactory, person_tracker::TransitTrackerFactory,
+        upcoming_arrivals::UpcomingArrivalsFactory, weather::WeatherFactory,
+    },
 };
-use std::{convert::Infallible, sync::Arc, vec};
+use std::{convert::Infallible, env::var, path::Path, vec};
+use tokio_util::sync::CancellationToken;
 
 #[derive(RenderFactories)]
-enum RenderFactoryEntries<D: DrawTarget<Color = Rgb888, Error = Infallible>> {
+enum RenderFactoryEntries<D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static> {
     TransitTracker(TransitTrackerFactory<D>),
     UpcomingArrivals(UpcomingArrivalsFactory<D>),
     Weather(WeatherFactory<D>),
+    Clock(ClockFactory<D>),
+    Image(ImageRenderFactory),
+    Script(ScriptRenderFactory),
+    WebAssembly(WasmRenderFactory),
+    Widget(WidgetRenderFactory),
+}
+
+/// Loads the render factory plugins found in `PLUGIN_DIR` (`plugins` by default), if it exists
+fn load_plugins() -> Result<Vec<plugin::PluginFactory>> {
+    let plugin_dir = var("PLUGIN_DIR").unwrap_or_else(|_| "plugins".to_owned());
+
+    if Path::new(&plugin_dir).is_dir() {
+        plugin::load_plugins(plugin_dir)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Registers the fonts found in `FONT_DIR` (`fonts` by default), if it exists, so render
+/// configurations can select them by name
+fn load_fonts() -> Result<()> {
+    let font_dir = var("FONT_DIR").unwrap_or_else(|_| "fonts".to_owned());
+
+    if Path::new(&font_dir).is_dir() {
+        font::load_fonts(font_dir)?;
+    }
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
+    // Fonts need to be registered before any render configuration is loaded
+    load_fonts()?;
+
+    // Providers restore their last payloads from the cache when they are created, so the cache
+    // needs to be set up before any render is loaded
+    provider::set_cache_dir(var("CACHE_DIR").unwrap_or_else(|_| "cache".to_owned()))?;
+
+    // Recordings named in the render configurations are resolved inside of this directory
+    recordings::set_recordings_dir(var("RECORDINGS_DIR").unwrap_or_else(|_| "fixtures".to_owned()));
+
     // Use the Rust Driver
     type DriverType = RustHardwareDriver;
-    type CanvasType = <RustHardwareDriver as HardwareDriver>::Canvas;
+    type CanvasType = DimmedCanvas<<RustHardwareDriver as HardwareDriver>::Canvas>;
 
     // Create the factory registry. This will house all the registered
     // RenderFactories that can be used to construct renders.
-    let factory_registry = {
-        let factory_registry: Registry<RenderFactoryEntries<CanvasType>, _> =
-            Registry::new(RenderFactoryEntries::factories());
-        Arc::new(Mutex::new(factory_registry))
+    let factory_registry: Registry<RenderFactoryEntries<CanvasType>, _> =
+        Registry::new(RenderFactoryEntries::factories()).with_plugins(load_plugins()?);
+
+    // The API is reachable by anyone on the network, so require the tokens to be provided
+    let api_tokens = ApiTokens::from_file(
+        var("API_TOKENS_FILE").unwrap_or_else(|_| "api_tokens.json".to_owned()),
+    )?;
+
+    // Optionally expose the display to Home Assistant, the registry is a handle so the MQTT task
+    // controls the same renders as the HTTP API
+    let mqtt_cancel_token = CancellationToken::new();
+    let mqtt_task = match MqttConfig::from_env()? {
+        Some(mqtt_config) => Some(tokio::task::spawn(serve_mqtt(
+            mqtt_config,
+            factory_registry.clone(),
+            mqtt_cancel_token.clone(),
+        ))),
+        None => None,
     };
 
     let _led_driver = driver::MatrixDriver::with_register::<DriverType, _, _>(
         "0.0.0.0:8080",
+        Some(api_tokens),
         factory_registry,
         HardwareConfig {
             hardware_mapping: HardwareMapping::Regular,
@@ -65,5 +132,10 @@ async fn main() -> Result<()> {
         }
     }
 
+    mqtt_cancel_token.cancel();
+    if let Some(mqtt_task) = mqtt_task {
+        mqtt_task.await??;
+    }
+
     Ok(())
 }
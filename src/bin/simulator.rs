@@ -8,14 +8,18 @@ use embedded_graphics::{
 use embedded_graphics_simulator::{
     OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
 };
-use rustic_pixel_display::render::Render;
-use rustic_pixel_examples::renders::{
-    person_tracker::{
-        HomeAssistantTracker, HomeTrackerConfig, PersonTracker, StateProvider, TransitTracker,
-        TransitTrackerConfig,
+use rustic_pixel_display::{provider, render::Render};
+use rustic_pixel_examples::{
+    recordings,
+    renders::{
+        clock::{Clock, ClockConfig},
+        person_tracker::{
+            HomeAssistantTracker, HomeTrackerConfig, PersonTracker, StateProvider, TransitTracker,
+            TransitTrackerConfig,
+        },
+        upcoming_arrivals::{ArrivalSourceConfig, UpcomingArrivals, UpcomingArrivalsConfig},
+        weather::{Configuration, Weather},
     },
-    upcoming_arrivals::{UpcomingArrivals, UpcomingArrivalsConfig},
-    weather::{Configuration, Weather},
 };
 use std::{collections::HashMap, env::var, vec};
 
@@ -35,14 +39,26 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Weather,
-    UpcomingArrivals,
+    UpcomingArrivals {
+        /// Replays the SEPTA arrivals recorded in the fixtures instead of calling the API
+        #[arg(long)]
+        offline: bool,
+    },
     PersonTracker,
+    Clock,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
+    // Providers restore their last payloads from the cache when they are created, so the cache
+    // needs to be set up before any render is loaded
+    provider::set_cache_dir(var("CACHE_DIR").unwrap_or_else(|_| "cache".to_owned()))?;
+
+    // Recordings named in the render configurations are resolved inside of this directory
+    recordings::set_recordings_dir(var("RECORDINGS_DIR").unwrap_or_else(|_| "fixtures".to_owned()));
+
     let output_settings = OutputSettingsBuilder::new().scale(4).max_fps(60).build();
     let mut window = Window::new("Simulator", &output_settings);
     let mut canvas = SimulatorDisplay::<Rgb888>::new(DISPLAY_SIZE);
@@ -55,12 +71,24 @@ async fn main() -> Result<()> {
             location: rustic_pixel_examples::renders::weather::Location::City(
                 "Philadelphia".to_owned(),
             ),
-        })),
-        Commands::UpcomingArrivals => Box::new(UpcomingArrivals::new(UpcomingArrivalsConfig {
-            septa_station: Some(septa_api::types::RegionalRailStop::SuburbanStation),
-            amtrak_station: None,
-            results: Some(20),
+            stale_after_secs: 2 * 60 * 60,
         })?),
+        Commands::UpcomingArrivals { offline } => {
+            let septa_source = if offline {
+                ArrivalSourceConfig::Replay("upcoming_arrivals/septa_suburban_station.jsonl".into())
+            } else {
+                ArrivalSourceConfig::Live
+            };
+
+            Box::new(UpcomingArrivals::new(UpcomingArrivalsConfig {
+                septa_station: Some(septa_api::types::RegionalRailStop::SuburbanStation),
+                amtrak_station: None,
+                results: Some(20),
+                septa_source,
+                amtrak_source: ArrivalSourceConfig::Live,
+                stale_after_secs: 5 * 60,
+            })?)
+        }
         Commands::PersonTracker => {
             let hass_url: String = var("HASS_URL")
                 .expect("Pleases set HASS_URL to the url of the home assistant instance");
@@ -77,11 +105,14 @@ async fn main() -> Result<()> {
                         home_assistant_url: hass_url.clone(),
                         home_assistant_bearer_token: bearer_token.clone(),
                         person_entity_id: "person.stefan".to_string(),
+                        stale_after_secs: 2 * 60,
+                        record_trace: None,
                     })?),
                     Box::new(HomeAssistantTracker::new(HomeTrackerConfig {
                         home_assistant_url: hass_url.clone(),
                         home_assistant_bearer_token: bearer_token.clone(),
                         person_entity_id: "person.stefan".to_string(),
+                        stale_after_secs: 10 * 60,
                     })?),
                 ],
             );
@@ -93,17 +124,21 @@ async fn main() -> Result<()> {
                         home_assistant_url: hass_url.clone(),
                         home_assistant_bearer_token: bearer_token.clone(),
                         person_entity_id: "person.abby".to_string(),
+                        stale_after_secs: 2 * 60,
+                        record_trace: None,
                     })?),
                     Box::new(HomeAssistantTracker::new(HomeTrackerConfig {
                         home_assistant_url: hass_url.clone(),
                         home_assistant_bearer_token: bearer_token.clone(),
                         person_entity_id: "person.abby".to_string(),
+                        stale_after_secs: 10 * 60,
                     })?),
                 ],
             );
 
             Box::new(PersonTracker::new(person_map))
         }
+        Commands::Clock => Box::new(Clock::new(ClockConfig::default())?),
     };
 
     'render_loop: loop {
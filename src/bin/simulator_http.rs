@@ -7,22 +7,38 @@ use embedded_graphics::{
 use embedded_graphics_simulator::{
     OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
 };
-use parking_lot::Mutex;
-use rustic_pixel_display::{http_server::build_api_server, registry::Registry, render::Render};
+use rustic_pixel_display::{
+    font,
+    http_server::{serve_api, ApiTokens},
+    image_render::ImageRenderFactory,
+    mqtt::{serve_mqtt, MqttConfig},
+    plugin, provider,
+    registry::Registry,
+    render::{DimmedCanvas, Render},
+    script::ScriptRenderFactory,
+    wasm::WasmRenderFactory,
+    widget::WidgetRenderFactory,
+};
 use rustic_pixel_display_macros::RenderFactories;
-use rustic_pixel_examples::renders::{
-    person_tracker::TransitTrackerFactory, upcoming_arrivals::UpcomingArrivalsFactory,
-    weather::WeatherFactory,
+use rustic_pixel_examples::{
+    recordings,
+    renders::{
+        clock::ClockFactory, person_tracker::TransitTrackerFactory,
+        upcoming_arrivals::UpcomingArrivalsFactory, weather::WeatherFactory,
+    },
 };
 use std::{
     convert::Infallible,
+    env::var,
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     vec,
 };
-use tokio::{runtime::Handle, task};
+use tokio::{net::TcpListener, task};
+use tokio_util::sync::CancellationToken;
 
 const DISPLAY_SIZE: Size = Size {
     width: 256,
@@ -30,57 +46,111 @@ const DISPLAY_SIZE: Size = Size {
 };
 
 #[derive(RenderFactories)]
-enum RenderFactoryEntries<D: DrawTarget<Color = Rgb888, Error = Infallible>> {
+enum RenderFactoryEntries<D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static> {
     TransitTracker(TransitTrackerFactory<D>),
     UpcomingArrivals(UpcomingArrivalsFactory<D>),
     Weather(WeatherFactory<D>),
+    Clock(ClockFactory<D>),
+    Image(ImageRenderFactory),
+    Script(ScriptRenderFactory),
+    WebAssembly(WasmRenderFactory),
+    Widget(WidgetRenderFactory),
+}
+
+/// Loads the render factory plugins found in `PLUGIN_DIR` (`plugins` by default), if it exists
+fn load_plugins() -> Result<Vec<plugin::PluginFactory>> {
+    let plugin_dir = var("PLUGIN_DIR").unwrap_or_else(|_| "plugins".to_owned());
+
+    if Path::new(&plugin_dir).is_dir() {
+        plugin::load_plugins(plugin_dir)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Registers the fonts found in `FONT_DIR` (`fonts` by default), if it exists, so render
+/// configurations can select them by name
+fn load_fonts() -> Result<()> {
+    let font_dir = var("FONT_DIR").unwrap_or_else(|_| "fonts".to_owned());
+
+    if Path::new(&font_dir).is_dir() {
+        font::load_fonts(font_dir)?;
+    }
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
-    // Get the handle to the created Tokio Runtime
-    let handle = Handle::current();
+    // Fonts need to be registered before any render configuration is loaded
+    load_fonts()?;
+
+    // Providers restore their last payloads from the cache when they are created, so the cache
+    // needs to be set up before any render is loaded
+    provider::set_cache_dir(var("CACHE_DIR").unwrap_or_else(|_| "cache".to_owned()))?;
+
+    // Recordings named in the render configurations are resolved inside of this directory
+    recordings::set_recordings_dir(var("RECORDINGS_DIR").unwrap_or_else(|_| "fixtures".to_owned()));
 
     // Create the factory registry. This will house all the registered RenderFactories that can
     // be used to construct renders.
-    let factory_registry = {
-        let factory_registry: Registry<RenderFactoryEntries<SimulatorDisplay<_>>, _> =
-            Registry::new(RenderFactoryEntries::factories());
-        Arc::new(Mutex::new(factory_registry))
-    };
+    let factory_registry: Registry<RenderFactoryEntries<DimmedCanvas<SimulatorDisplay<_>>>, _> =
+        Registry::new(RenderFactoryEntries::factories()).with_plugins(load_plugins()?);
 
-    // Since we will be sharing the registry between the HTTP thread and the render thread, we
+    // The registry is a handle that can be shared between the HTTP task and the render thread, we
     // need to clone since they will be moved into the lambda expression.
     let http_registry = factory_registry.clone();
+    let mqtt_registry = factory_registry.clone();
     let render_registry = factory_registry;
 
-    // Same with the alive variable
+    // Flag used to stop the render thread
     let alive = Arc::new(AtomicBool::new(true));
-    let http_alive = alive.clone();
-    let render_alive = alive;
+    let render_alive = alive.clone();
 
-    let http_task = task::spawn(async move {
-        let server = build_api_server("localhost:8080", handle, http_registry);
+    // The simulator only listens on localhost, so authentication is opt-in
+    let api_tokens = match var("API_TOKENS_FILE") {
+        Ok(path) => Some(ApiTokens::from_file(path)?),
+        Err(_) => None,
+    };
 
-        while http_alive.load(Ordering::SeqCst) {
-            server.poll();
-        }
-    });
+    // The HTTP server runs on the Tokio runtime until it is told to shutdown
+    let http_cancel_token = CancellationToken::new();
+    let http_task = task::spawn(serve_api(
+        TcpListener::bind("localhost:8080").await?,
+        http_registry,
+        api_tokens,
+        http_cancel_token.clone(),
+    ));
+
+    // MQTT is only enabled when a broker is configured, see the README on how to run one locally
+    let mqtt_task = match MqttConfig::from_env()? {
+        Some(mqtt_config) => Some(task::spawn(serve_mqtt(
+            mqtt_config,
+            mqtt_registry,
+            http_cancel_token.clone(),
+        ))),
+        None => None,
+    };
 
-    let render_task = task::spawn(async move {
+    // The render loop never yields, so run it on a blocking thread to keep the runtime's worker
+    // threads free to serve HTTP requests
+    let render_task = task::spawn_blocking(move || {
         let output_settings = OutputSettingsBuilder::new().scale(4).max_fps(60).build();
         let mut window = Window::new("Simulator", &output_settings);
-        let mut canvas: SimulatorDisplay<Rgb888> = SimulatorDisplay::<Rgb888>::new(DISPLAY_SIZE);
+        let mut canvas = DimmedCanvas::new(Box::new(SimulatorDisplay::<Rgb888>::new(DISPLAY_SIZE)));
 
         while render_alive.load(Ordering::SeqCst) {
+            let render_set = render_registry.render_set();
+            canvas.set_brightness(render_set.display_settings().brightness);
+
             canvas
                 .fill_solid(&Rectangle::new(Point::zero(), DISPLAY_SIZE), Rgb888::BLACK)
                 .unwrap();
 
-            render_registry.lock().render(&mut canvas).unwrap();
-            window.update(&canvas);
+            render_set.render(&mut canvas).unwrap();
+            window.update(canvas.inner());
 
             for event in window.events() {
                 if event == SimulatorEvent::Quit {
@@ -96,9 +166,16 @@ async fn main() -> Result<()> {
         }
     }
 
-    //
-    http_task.abort();
-    render_task.abort();
+    // Let the HTTP server finish any in-flight requests before exiting
+    http_cancel_token.cancel();
+    http_task.await??;
+
+    if let Some(mqtt_task) = mqtt_task {
+        mqtt_task.await??;
+    }
+
+    alive.store(false, Ordering::SeqCst);
+    render_task.await?;
 
     Ok(())
 }
@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use rustic_pixel_examples::renders::person_tracker::trace::{
+    check_transitions, load_trace, replay_trace, TransitPhase,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Lists the phases each trace of a directory is expected to go through
+const EXPECTATIONS_FILE: &str = "expected.json";
+
+/// Replays GPS traces recorded by the TransitTracker's `record_trace` through its state machine
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// A trace, or a directory of traces along with an `expected.json` mapping the file name of
+    /// each trace to the phases it should go through
+    path: PathBuf,
+
+    /// How many times faster than recorded to replay, as fast as possible when not set
+    #[arg(long, value_parser = parse_speed)]
+    speed: Option<f64>,
+
+    /// Phases a single trace should go through, i.e. `no_status,at_station,on_train`
+    #[arg(long, value_delimiter = ',')]
+    expect: Option<Vec<TransitPhase>>,
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    match speed.parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(value),
+        _ => Err(format!("{speed} is not a number greater than 0")),
+    }
+}
+
+/// Replays the trace in `path` and prints its transitions, failing if they are not the
+/// `expected` ones
+async fn replay(path: &Path, speed: Option<f64>, expected: Option<&[TransitPhase]>) -> Result<()> {
+    println!("{}", path.display());
+
+    let transitions = replay_trace(load_trace(path)?, speed).await?;
+    for transition in &transitions {
+        println!("  {transition}");
+    }
+
+    match expected {
+        Some(expected) => check_transitions(&transitions, expected),
+        None => Ok(()),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let Args {
+        path,
+        speed,
+        expect,
+    } = Args::parse();
+
+    if !path.is_dir() {
+        return replay(&path, speed, expect.as_deref()).await;
+    }
+
+    let expectations: BTreeMap<String, Vec<TransitPhase>> =
+        serde_json::from_str(&fs::read_to_string(path.join(EXPECTATIONS_FILE))?)?;
+
+    let mut failures = 0;
+    for (file_name, expected) in &expectations {
+        if let Err(e) = replay(&path.join(file_name), speed, Some(expected)).await {
+            println!("  FAILED: {e:#}");
+            failures += 1;
+        }
+    }
+
+    match failures {
+        0 => {
+            println!("All {} traces passed", expectations.len());
+            Ok(())
+        }
+        _ => Err(anyhow!(
+            "{failures} of {} traces failed",
+            expectations.len()
+        )),
+    }
+}
@@ -0,0 +1,218 @@
+use embedded_graphics::{
+    prelude::{Dimensions, DrawTarget, DrawTargetExt, Point, Size},
+    primitives::Rectangle,
+    text::{renderer::TextRenderer, Baseline, Text},
+    transform::Transform,
+    Drawable,
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// What happens once the end of the text has scrolled into view
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarqueeMode {
+    /// The text keeps scrolling and starts over, followed by a gap
+    #[default]
+    Loop,
+
+    /// The text scrolls back and forth between its start and its end
+    Bounce,
+}
+
+/// Single line of text that scrolls horizontally when it is wider than its box.
+///
+/// Renders are redrawn from scratch for every frame, so the marquee doesn't keep any state: the
+/// scroll position is derived from the time elapsed since the text started showing, given with
+/// [`Marquee::at`]. Text that fits in the box is drawn as is.
+///
+/// The marquee implements [`Dimensions`], [`Transform`] and [`Drawable`], so it can be used as a
+/// view in embedded-layout `LinearLayout` chains like [`Text`].
+#[derive(Clone, Copy, Debug)]
+pub struct Marquee<'a, S> {
+    text: &'a str,
+    character_style: S,
+    bounds: Rectangle,
+
+    /// Scrolling speed in pixels per second
+    speed: u32,
+
+    /// Time spent at each end of the text before scrolling
+    pause: Duration,
+    mode: MarqueeMode,
+
+    /// Pixels between the end of the text and its next repetition in [`MarqueeMode::Loop`]
+    gap: u32,
+    elapsed: Duration,
+}
+
+impl<'a, S> Marquee<'a, S>
+where
+    S: TextRenderer + Clone,
+{
+    /// Creates a marquee `width` pixels wide with its top left corner at `position`, the height
+    /// of the marquee is the line height of the font
+    pub fn new(text: &'a str, position: Point, width: u32, character_style: S) -> Self {
+        let height = character_style.line_height();
+
+        Self {
+            text,
+            character_style,
+            bounds: Rectangle::new(position, Size::new(width, height)),
+            speed: 20,
+            pause: Duration::from_millis(1500),
+            mode: MarqueeMode::default(),
+            gap: 16,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn with_speed(mut self, pixels_per_second: u32) -> Self {
+        self.speed = pixels_per_second;
+        self
+    }
+
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: MarqueeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_gap(mut self, gap: u32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Scrolls the text to where it should be `elapsed` after it started showing
+    pub fn at(mut self, elapsed: Duration) -> Self {
+        self.elapsed = elapsed;
+        self
+    }
+
+    fn text_width(&self) -> u32 {
+        self.character_style
+            .measure_string(self.text, Point::zero(), Baseline::Top)
+            .bounding_box
+            .size
+            .width
+    }
+
+    /// Number of pixels the text is scrolled to the left by
+    fn scroll_offset(&self, text_width: u32) -> u32 {
+        let Self {
+            bounds,
+            speed,
+            pause,
+            mode,
+            gap,
+            elapsed,
+            ..
+        } = self;
+
+        let overflow = text_width.saturating_sub(bounds.size.width);
+        if overflow == 0 || *speed == 0 {
+            return 0;
+        }
+
+        let speed = *speed as u128;
+        let pause = pause.as_millis();
+        let elapsed = elapsed.as_millis();
+
+        // Pixels travelled while scrolling for `millis`
+        let travelled = |millis: u128| (millis * speed / 1000) as u32;
+
+        match mode {
+            MarqueeMode::Loop => {
+                let distance = (text_width + gap) as u128;
+                let cycle = pause + distance * 1000 / speed;
+                let time = elapsed % cycle.max(1);
+
+                time.checked_sub(pause).map(travelled).unwrap_or(0)
+            }
+            MarqueeMode::Bounce => {
+                let travel = overflow as u128 * 1000 / speed;
+                let cycle = 2 * (pause + travel);
+                let time = elapsed % cycle.max(1);
+
+                if time < pause {
+                    0
+                } else if time < pause + travel {
+                    travelled(time - pause)
+                } else if time < 2 * pause + travel {
+                    overflow
+                } else {
+                    overflow - travelled(time - 2 * pause - travel).min(overflow)
+                }
+            }
+        }
+    }
+}
+
+impl<S> Dimensions for Marquee<'_, S> {
+    fn bounding_box(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl<S> Transform for Marquee<'_, S>
+where
+    S: Clone,
+{
+    fn translate(&self, by: Point) -> Self {
+        let mut marquee = self.clone();
+        marquee.translate_mut(by);
+        marquee
+    }
+
+    fn translate_mut(&mut self, by: Point) -> &mut Self {
+        self.bounds.top_left += by;
+        self
+    }
+}
+
+impl<S> Drawable for Marquee<'_, S>
+where
+    S: TextRenderer + Clone,
+{
+    type Color = S::Color;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Self {
+            text,
+            character_style,
+            bounds,
+            mode,
+            gap,
+            ..
+        } = self;
+
+        let text_width = self.text_width();
+        let offset = self.scroll_offset(text_width);
+        let mut target = target.clipped(bounds);
+
+        let position = bounds.top_left - Point::new(offset as i32, 0);
+        Text::with_baseline(text, position, character_style.clone(), Baseline::Top)
+            .draw(&mut target)?;
+
+        // The start of the next repetition follows the end of the text while looping
+        if *mode == MarqueeMode::Loop && offset > 0 {
+            Text::with_baseline(
+                text,
+                position + Point::new((text_width + gap) as i32, 0),
+                character_style.clone(),
+                Baseline::Top,
+            )
+            .draw(&mut target)?;
+        }
+
+        Ok(())
+    }
+}
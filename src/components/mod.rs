@@ -0,0 +1,2 @@
+pub mod marquee;
+pub mod text_box;
@@ -0,0 +1,340 @@
+use embedded_graphics::{
+    prelude::{Dimensions, DrawTarget, DrawTargetExt, Point},
+    primitives::Rectangle,
+    text::{renderer::TextRenderer, Alignment, Baseline, Text},
+    transform::Transform,
+    Drawable,
+};
+use serde::Deserialize;
+
+/// Appended to the last line when the text doesn't fit, the mono fonts don't have `…`
+const ELLIPSIS: &str = "...";
+
+/// Shortened forms of the words commonly found in station and stop names
+pub const TRANSIT_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("Station", "Stn"),
+    ("Street", "St"),
+    ("Avenue", "Ave"),
+    ("Boulevard", "Blvd"),
+    ("Road", "Rd"),
+    ("Square", "Sq"),
+    ("Center", "Ctr"),
+    ("Centre", "Ctr"),
+    ("Junction", "Jct"),
+    ("Terminal", "Term"),
+    ("Transportation", "Trans"),
+    ("International", "Intl"),
+    ("Airport", "Arpt"),
+    ("University", "Univ"),
+];
+
+/// Where the lines of text are placed vertically when they don't fill the box
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerticalAlignment {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// The lines a [`TextBox`] draws and the style they are drawn with
+#[derive(Clone, Debug)]
+pub struct FittedText<S> {
+    pub character_style: S,
+    pub lines: Vec<String>,
+}
+
+/// Text that is fitted into a rectangle.
+///
+/// The text is word wrapped to the width of the box, trying each character style in turn (i.e.
+/// from the largest font to the smallest) until all of it fits. Words are replaced by their
+/// abbreviation (see [`TextBox::with_abbreviations`]) before moving on to a smaller style. When
+/// the text doesn't fit even with the last style, the lines that do fit are kept and the last one
+/// ends with an ellipsis.
+///
+/// Like the [`Marquee`](super::marquee::Marquee), a text box can be laid out next to other views
+/// by embedded-layout.
+#[derive(Clone, Debug)]
+pub struct TextBox<'a, S> {
+    text: &'a str,
+    character_styles: Vec<S>,
+    bounds: Rectangle,
+    alignment: Alignment,
+    vertical_alignment: VerticalAlignment,
+
+    /// Lines the text can use on top of the limit set by the height of the box
+    max_lines: Option<usize>,
+
+    /// Whole words and their replacement
+    abbreviations: &'a [(&'a str, &'a str)],
+}
+
+impl<'a, S> TextBox<'a, S>
+where
+    S: TextRenderer + Clone,
+{
+    /// Creates a text box filling `bounds`, `character_styles` are tried in order until the text
+    /// fits. Nothing is drawn if no style is given.
+    pub fn new(
+        text: &'a str,
+        bounds: Rectangle,
+        character_styles: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self {
+            text,
+            character_styles: character_styles.into_iter().collect(),
+            bounds,
+            alignment: Alignment::Left,
+            vertical_alignment: VerticalAlignment::default(),
+            max_lines: None,
+            abbreviations: &[],
+        }
+    }
+
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn with_vertical_alignment(mut self, vertical_alignment: VerticalAlignment) -> Self {
+        self.vertical_alignment = vertical_alignment;
+        self
+    }
+
+    /// Limits the number of lines, `1` keeps the text on a single line
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Replaces whole words by their abbreviation (i.e. `("Station", "Stn")`) when the text
+    /// doesn't fit using a style, before trying the next one
+    pub fn with_abbreviations(mut self, abbreviations: &'a [(&'a str, &'a str)]) -> Self {
+        self.abbreviations = abbreviations;
+        self
+    }
+
+    /// Number of lines of `character_style` that fit in the box
+    fn line_capacity(&self, character_style: &S) -> usize {
+        let capacity = (self.bounds.size.height / character_style.line_height().max(1)) as usize;
+        self.max_lines.map_or(capacity, |max| capacity.min(max))
+    }
+
+    /// Picks the style and breaks the text into the lines that are drawn
+    pub fn fit(&self) -> Option<FittedText<S>> {
+        let Self {
+            text,
+            character_styles,
+            bounds,
+            abbreviations,
+            ..
+        } = self;
+
+        let abbreviated = abbreviate(text, abbreviations);
+        let mut candidates = vec![*text];
+        if abbreviated != *text {
+            candidates.push(&abbreviated);
+        }
+
+        for character_style in character_styles {
+            for candidate in &candidates {
+                let lines = wrap(candidate, character_style, bounds.size.width);
+                if lines.len() <= self.line_capacity(character_style) {
+                    return Some(FittedText {
+                        character_style: character_style.clone(),
+                        lines,
+                    });
+                }
+            }
+        }
+
+        // Nothing fits, keep as much as possible of the shortest text using the smallest style
+        let character_style = character_styles.last()?;
+        let mut lines = wrap(
+            candidates.last().copied().unwrap_or_default(),
+            character_style,
+            bounds.size.width,
+        );
+        lines.truncate(self.line_capacity(character_style).max(1));
+
+        if let Some(line) = lines.last_mut() {
+            *line = ellipsize(line, character_style, bounds.size.width);
+        }
+
+        Some(FittedText {
+            character_style: character_style.clone(),
+            lines,
+        })
+    }
+}
+
+fn text_width<S: TextRenderer>(text: &str, character_style: &S) -> u32 {
+    character_style
+        .measure_string(text, Point::zero(), Baseline::Top)
+        .bounding_box
+        .size
+        .width
+}
+
+/// Replaces the words of `text` that have an abbreviation, punctuation around the words is kept
+fn abbreviate(text: &str, abbreviations: &[(&str, &str)]) -> String {
+    if abbreviations.is_empty() {
+        return text.to_owned();
+    }
+
+    // Every piece is a word followed by the character that ended it
+    text.split_inclusive(|c: char| !c.is_alphanumeric())
+        .map(|piece| {
+            let word = piece.trim_end_matches(|c: char| !c.is_alphanumeric());
+
+            match abbreviations.iter().find(|(long, _)| *long == word) {
+                Some((_, short)) => format!("{short}{}", &piece[word.len()..]),
+                None => piece.to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Breaks `text` into lines no wider than `width`. Lines are broken between words, words that
+/// are wider than a whole line are broken between characters.
+fn wrap<S: TextRenderer>(text: &str, character_style: &S, width: u32) -> Vec<String> {
+    let fits = |line: &str| text_width(line, character_style) <= width;
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_owned()
+            } else {
+                format!("{line} {word}")
+            };
+
+            if fits(&candidate) {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            for character in word.chars() {
+                line.push(character);
+
+                if !fits(&line) && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, character.to_string()));
+                }
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Shortens `line` until it fits in `width` followed by an ellipsis
+fn ellipsize<S: TextRenderer>(line: &str, character_style: &S, width: u32) -> String {
+    let mut line = line.trim_end().to_owned();
+
+    loop {
+        let ellipsized = format!("{line}{ELLIPSIS}");
+        if line.is_empty() || text_width(&ellipsized, character_style) <= width {
+            return ellipsized;
+        }
+
+        line.pop();
+        line.truncate(line.trim_end().len());
+    }
+}
+
+impl<S> Dimensions for TextBox<'_, S> {
+    fn bounding_box(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl<S> Transform for TextBox<'_, S>
+where
+    S: Clone,
+{
+    fn translate(&self, by: Point) -> Self {
+        let mut text_box = self.clone();
+        text_box.translate_mut(by);
+        text_box
+    }
+
+    fn translate_mut(&mut self, by: Point) -> &mut Self {
+        self.bounds.top_left += by;
+        self
+    }
+}
+
+impl<S> Drawable for TextBox<'_, S>
+where
+    S: TextRenderer + Clone,
+{
+    type Color = S::Color;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let Self {
+            bounds,
+            alignment,
+            vertical_alignment,
+            ..
+        } = self;
+
+        let Some(FittedText {
+            character_style,
+            lines,
+        }) = self.fit()
+        else {
+            return Ok(());
+        };
+
+        let line_height = character_style.line_height();
+        let free_height = bounds
+            .size
+            .height
+            .saturating_sub(line_height * lines.len() as u32);
+        let top = bounds.top_left.y
+            + match vertical_alignment {
+                VerticalAlignment::Top => 0,
+                VerticalAlignment::Middle => free_height / 2,
+                VerticalAlignment::Bottom => free_height,
+            } as i32;
+
+        let mut target = target.clipped(bounds);
+
+        for (index, line) in lines.iter().enumerate() {
+            let free_width = bounds
+                .size
+                .width
+                .saturating_sub(text_width(line, &character_style));
+            let x = bounds.top_left.x
+                + match alignment {
+                    Alignment::Left => 0,
+                    Alignment::Center => free_width / 2,
+                    Alignment::Right => free_width,
+                } as i32;
+
+            Text::with_baseline(
+                line,
+                Point::new(x, top + (line_height * index as u32) as i32),
+                character_style.clone(),
+                Baseline::Top,
+            )
+            .draw(&mut target)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod components;
+pub mod recordings;
 pub mod renders;
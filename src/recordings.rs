@@ -0,0 +1,40 @@
+//! Directory the recorded provider responses and GPS traces are read from and written to.
+//!
+//! Renders name their recordings in their configuration, which can come from the HTTP API, so the
+//! names are only allowed to point inside of this directory.
+
+use anyhow::{bail, Result};
+use parking_lot::RwLock;
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+fn recordings_dir() -> &'static RwLock<Option<PathBuf>> {
+    static RECORDINGS_DIR: OnceLock<RwLock<Option<PathBuf>>> = OnceLock::new();
+    RECORDINGS_DIR.get_or_init(Default::default)
+}
+
+/// Keeps the recordings in `dir`. Renders can't record or replay anything until this is called.
+pub fn set_recordings_dir<P: AsRef<Path>>(dir: P) {
+    *recordings_dir().write() = Some(dir.as_ref().to_owned());
+}
+
+/// Resolves the name of a recording to its path in the recordings directory. Absolute names and
+/// names going up a directory (i.e. `../secrets`) are rejected.
+pub(crate) fn recording_path(name: &Path) -> Result<PathBuf> {
+    let inside = name
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside || name.as_os_str().is_empty() {
+        bail!(
+            "Recording {} has to be a relative path inside the recordings directory",
+            name.display()
+        );
+    }
+
+    match recordings_dir().read().as_ref() {
+        Some(dir) => Ok(dir.join(name)),
+        None => bail!("Recordings are disabled, no recordings directory was set"),
+    }
+}
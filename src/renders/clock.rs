@@ -0,0 +1,494 @@
+use crate::components::text_box::{TextBox, VerticalAlignment};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use embedded_graphics::{
+    mono_font::{self, MonoFont, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::{Dimensions, DrawTarget, Point, Primitive, RgbColor, Size, WebColors},
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use rustic_pixel_display::render::{Render, RenderFactory, UsefulnessVal};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    convert::Infallible,
+    f32::consts::PI,
+    fmt::{Display, Write},
+    io::Read,
+    marker::PhantomData,
+    str::FromStr,
+};
+
+/// Tried in order until the time fits, the largest font that fits is used
+const TIME_FONTS: [&MonoFont<'static>; 7] = [
+    &mono_font::ascii::FONT_10X20,
+    &mono_font::ascii::FONT_9X18_BOLD,
+    &mono_font::ascii::FONT_9X15,
+    &mono_font::ascii::FONT_7X13,
+    &mono_font::ascii::FONT_6X10,
+    &mono_font::ascii::FONT_5X7,
+    &mono_font::ascii::FONT_4X6,
+];
+
+const DATE_FONTS: [&MonoFont<'static>; 3] = [
+    &mono_font::ascii::FONT_6X10,
+    &mono_font::ascii::FONT_5X7,
+    &mono_font::ascii::FONT_4X6,
+];
+
+const WORLD_CLOCK_FONT: &MonoFont<'static> = &mono_font::ascii::FONT_6X10;
+
+/// A timezone given by its IANA name (i.e. `"America/New_York"`)
+#[derive(Clone, Debug)]
+pub struct Zone {
+    name: String,
+    timezone: Tz,
+}
+
+impl Zone {
+    fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.timezone)
+    }
+
+    /// The city part of the name (i.e. `"New York"` for `"America/New_York"`)
+    fn city(&self) -> String {
+        self.name
+            .rsplit('/')
+            .next()
+            .unwrap_or(&self.name)
+            .replace('_', " ")
+    }
+}
+
+impl FromStr for Zone {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.parse() {
+            Ok(timezone) => Ok(Self {
+                name: name.to_owned(),
+                timezone,
+            }),
+            Err(_) => bail!("Unknown timezone \"{name}\""),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Zone {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockFace {
+    #[default]
+    Digital,
+    Analog,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum HourFormat {
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFourHour,
+    #[serde(rename = "12h")]
+    TwelveHour,
+}
+
+impl HourFormat {
+    /// Formats `time`, the hour is padded so the text keeps the same width through the day
+    fn format(&self, time: &NaiveDateTime, seconds: bool, colon: bool) -> String {
+        let separator = if colon { ':' } else { ' ' };
+
+        let mut text = match self {
+            HourFormat::TwentyFourHour => {
+                format!("{:02}{separator}{:02}", time.hour(), time.minute())
+            }
+            HourFormat::TwelveHour => {
+                format!("{:>2}{separator}{:02}", time.hour12().1, time.minute())
+            }
+        };
+
+        if seconds {
+            text.push_str(&format!("{separator}{:02}", time.second()));
+        }
+
+        if *self == HourFormat::TwelveHour {
+            text.push_str(if time.hour12().0 { " PM" } else { " AM" });
+        }
+
+        text
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WorldClock {
+    pub timezone: Zone,
+
+    /// Shown next to the time, the city of the timezone by default
+    pub label: Option<String>,
+}
+
+fn default_date_format() -> Option<String> {
+    Some("%a %b %-d".to_owned())
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClockConfig {
+    #[serde(default)]
+    pub face: ClockFace,
+
+    /// Timezone of the main clock, the timezone of the system when not set
+    #[serde(default)]
+    pub timezone: Option<Zone>,
+
+    #[serde(default)]
+    pub hour_format: HourFormat,
+
+    #[serde(default)]
+    pub show_seconds: bool,
+
+    /// Hides the colons of the digital face every other half second
+    #[serde(default)]
+    pub blink_colon: bool,
+
+    /// `strftime` format of the date shown under the time, `None` hides the date
+    #[serde(default = "default_date_format")]
+    pub date_format: Option<String>,
+
+    /// Other timezones listed under the main clock
+    #[serde(default)]
+    pub world_clocks: Vec<WorldClock>,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            face: ClockFace::default(),
+            timezone: None,
+            hour_format: HourFormat::default(),
+            show_seconds: false,
+            blink_colon: false,
+            date_format: default_date_format(),
+            world_clocks: Vec::new(),
+        }
+    }
+}
+
+/// Shows the time and date, along with the time in other timezones.
+///
+/// Nothing is cached between frames: the time is read every time the clock is drawn, so the
+/// digits change on the first frame after a second boundary.
+pub struct Clock {
+    config: ClockConfig,
+}
+
+/// Formats `time` with the `strftime` `format`, `None` if the format is invalid. chrono only
+/// reports some invalid formats (e.g. `%z` for a time without an offset) once the time is written.
+fn format_date<Z>(time: &DateTime<Z>, format: &str) -> Option<String>
+where
+    Z: TimeZone,
+    Z::Offset: Display,
+{
+    let mut date = String::new();
+    write!(date, "{}", time.format(format)).ok()?;
+    Some(date)
+}
+
+impl Clock {
+    pub fn new(config: ClockConfig) -> Result<Self> {
+        if let Some(date_format) = &config.date_format {
+            if format_date(&Local::now(), date_format).is_none() {
+                bail!("Invalid date format \"{date_format}\"");
+            }
+        }
+
+        Ok(Self { config })
+    }
+
+    fn draw_digital<D>(
+        &self,
+        canvas: &mut D,
+        area: Rectangle,
+        now: &NaiveDateTime,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let ClockConfig {
+            hour_format,
+            show_seconds,
+            blink_colon,
+            ..
+        } = &self.config;
+
+        let colon = !blink_colon || now.nanosecond() < 500_000_000;
+        let time = hour_format.format(now, *show_seconds, colon);
+
+        TextBox::new(
+            &time,
+            area,
+            TIME_FONTS.map(|font| MonoTextStyle::new(font, Rgb888::WHITE)),
+        )
+        .with_max_lines(1)
+        .with_alignment(Alignment::Center)
+        .with_vertical_alignment(VerticalAlignment::Middle)
+        .draw(canvas)
+    }
+
+    fn draw_analog<D>(
+        &self,
+        canvas: &mut D,
+        area: Rectangle,
+        now: &NaiveDateTime,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let diameter = area.size.width.min(area.size.height);
+        if diameter < 8 {
+            return Ok(());
+        }
+
+        let face = Rectangle::with_center(area.center(), Size::new_equal(diameter));
+        let radius = (diameter - 1) as f32 / 2.0;
+        let center = (
+            face.top_left.x as f32 + radius,
+            face.top_left.y as f32 + radius,
+        );
+
+        // Point `length` (as a fraction of the radius) away from the center, `turns` being the
+        // fraction of a full turn clockwise from 12 o'clock
+        let point = |turns: f32, length: f32| {
+            let angle = turns * 2.0 * PI;
+            Point::new(
+                (center.0 + angle.sin() * radius * length).round() as i32,
+                (center.1 - angle.cos() * radius * length).round() as i32,
+            )
+        };
+        let hand_width = if diameter >= 48 { 2 } else { 1 };
+
+        Circle::new(face.top_left, diameter)
+            .into_styled(PrimitiveStyle::with_stroke(Rgb888::CSS_DIM_GRAY, 1))
+            .draw(canvas)?;
+
+        for hour in 0..12 {
+            let turns = hour as f32 / 12.0;
+            let start = if hour % 3 == 0 { 0.75 } else { 0.85 };
+
+            Line::new(point(turns, start), point(turns, 0.95))
+                .into_styled(PrimitiveStyle::with_stroke(Rgb888::CSS_LIGHT_GRAY, 1))
+                .draw(canvas)?;
+        }
+
+        let minutes = now.minute() as f32 + now.second() as f32 / 60.0;
+        let hours = (now.hour() % 12) as f32 + minutes / 60.0;
+
+        let mut hands = vec![
+            (hours / 12.0, 0.5, hand_width, Rgb888::WHITE),
+            (minutes / 60.0, 0.8, hand_width, Rgb888::WHITE),
+        ];
+        if self.config.show_seconds {
+            hands.push((now.second() as f32 / 60.0, 0.85, 1, Rgb888::RED));
+        }
+
+        for (turns, length, width, color) in hands {
+            Line::new(point(turns, 0.0), point(turns, length))
+                .into_styled(PrimitiveStyle::with_stroke(color, width))
+                .draw(canvas)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_world_clock<D>(
+        &self,
+        canvas: &mut D,
+        area: Rectangle,
+        world_clock: &WorldClock,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
+        let WorldClock { timezone, label } = world_clock;
+
+        let time = self
+            .config
+            .hour_format
+            .format(&timezone.now().naive_local(), false, true);
+        let time_style = MonoTextStyle::new(WORLD_CLOCK_FONT, Rgb888::WHITE);
+
+        let time = Text::with_text_style(
+            &time,
+            Point::new(
+                area.top_left.x + area.size.width as i32 - 1,
+                area.top_left.y,
+            ),
+            time_style,
+            TextStyleBuilder::new()
+                .alignment(Alignment::Right)
+                .baseline(Baseline::Top)
+                .build(),
+        );
+        let time_width = time.bounding_box().size.width;
+        time.draw(canvas)?;
+
+        // The label gets the rest of the row
+        let label = label.clone().unwrap_or_else(|| timezone.city());
+        let label_area = Rectangle::new(
+            area.top_left,
+            Size::new(
+                area.size.width.saturating_sub(time_width + 2),
+                area.size.height,
+            ),
+        );
+
+        TextBox::new(
+            &label,
+            label_area,
+            [WORLD_CLOCK_FONT, &mono_font::ascii::FONT_5X7]
+                .map(|font| MonoTextStyle::new(font, Rgb888::CSS_LIGHT_GRAY)),
+        )
+        .with_max_lines(1)
+        .with_vertical_alignment(VerticalAlignment::Middle)
+        .draw(canvas)
+    }
+}
+
+impl<D> Render<D> for Clock
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        let ClockConfig {
+            face,
+            timezone,
+            date_format,
+            world_clocks,
+            ..
+        } = &self.config;
+
+        let bounds = canvas.bounding_box();
+        let (now, date) = match timezone {
+            Some(timezone) => {
+                let now = timezone.now();
+                let date = date_format
+                    .as_deref()
+                    .and_then(|date_format| format_date(&now, date_format));
+                (now.naive_local(), date)
+            }
+            None => {
+                let now = Local::now();
+                let date = date_format
+                    .as_deref()
+                    .and_then(|date_format| format_date(&now, date_format));
+                (now.naive_local(), date)
+            }
+        };
+
+        // The world clocks are listed at the bottom, using at most half of the canvas
+        let row_height = WORLD_CLOCK_FONT.character_size.height + 1;
+        let rows = world_clocks
+            .len()
+            .min((bounds.size.height / 2 / row_height) as usize);
+        let world_height = row_height * rows as u32;
+
+        let date_height = if date_format.is_some() {
+            DATE_FONTS[0].character_size.height
+        } else {
+            0
+        };
+
+        let time_height = bounds
+            .size
+            .height
+            .saturating_sub(world_height + date_height);
+        let time_area = Rectangle::new(bounds.top_left, Size::new(bounds.size.width, time_height));
+
+        match face {
+            ClockFace::Digital => self.draw_digital(canvas, time_area, &now)?,
+            ClockFace::Analog => self.draw_analog(canvas, time_area, &now)?,
+        }
+
+        if let Some(date) = date {
+            TextBox::new(
+                &date,
+                Rectangle::new(
+                    bounds.top_left + Point::new(0, time_height as i32),
+                    Size::new(bounds.size.width, date_height),
+                ),
+                DATE_FONTS.map(|font| MonoTextStyle::new(font, Rgb888::CSS_LIGHT_GRAY)),
+            )
+            .with_max_lines(1)
+            .with_alignment(Alignment::Center)
+            .with_vertical_alignment(VerticalAlignment::Middle)
+            .draw(canvas)?;
+        }
+
+        for (index, world_clock) in world_clocks.iter().take(rows).enumerate() {
+            let top = time_height + date_height + row_height * index as u32 + 1;
+
+            self.draw_world_clock(
+                canvas,
+                Rectangle::new(
+                    bounds.top_left + Point::new(0, top as i32),
+                    Size::new(bounds.size.width, row_height - 1),
+                ),
+                world_clock,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The time is always correct but rarely urgent, so the clock fills in when nothing else has
+    /// anything to show
+    fn usefulness(&self) -> UsefulnessVal {
+        UsefulnessVal::BarelyUseful
+    }
+}
+
+pub struct ClockFactory<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    _phantom: PhantomData<D>,
+}
+
+impl<D> Default for ClockFactory<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn default() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<D> RenderFactory<D> for ClockFactory<D>
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render_name(&self) -> &'static str {
+        "Clock"
+    }
+
+    fn render_description(&self) -> &'static str {
+        "Shows the time and date, along with world clocks"
+    }
+
+    fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
+        let config: ClockConfig = serde_json::from_reader(reader)?;
+        Ok(Box::new(Clock::new(config)?))
+    }
+}
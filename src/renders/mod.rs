@@ -1,3 +1,4 @@
+pub mod clock;
 pub mod person_tracker;
 pub mod upcoming_arrivals;
 pub mod weather;
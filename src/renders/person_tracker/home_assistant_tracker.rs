@@ -1,5 +1,5 @@
-use super::{State, StateProvider, SubRender, Usefulness, UsefulnessVal};
-use anyhow::Result;
+use super::{State, StateProvider, SubRender};
+use anyhow::{anyhow, Result};
 use embedded_graphics::{
     image::Image,
     mono_font::{self, MonoTextStyle},
@@ -15,12 +15,14 @@ use embedded_layout::{
 use home_assistant_rest::get::StateEnum;
 use log::warn;
 use parking_lot::Mutex;
-use rustic_pixel_display::render::SubCanvas;
-use serde::Deserialize;
+use rustic_pixel_display::{
+    health::TaskHealth,
+    provider::{PollSettings, Poller, Provider},
+    render::{SubCanvas, Usefulness, UsefulnessVal},
+};
+use serde::{Deserialize, Serialize};
 use std::{convert::Infallible, sync::Arc, time::Duration};
 use tinybmp::Bmp;
-use tokio::{select, task::JoinHandle};
-use tokio_util::sync::CancellationToken;
 
 const HOME_BYTES: &[u8] = include_bytes!("icons/home_48.bmp");
 const LOCATION_AWAY_BYTES: &[u8] = include_bytes!("icons/location_away_48.bmp");
@@ -36,14 +38,22 @@ lazy_static! {
     static ref WORK_BMP: Bmp::<'static, Rgb888> = Bmp::<Rgb888>::from_slice(WORK_BYTES).unwrap();
 }
 
+fn default_stale_after_secs() -> u64 {
+    10 * 60
+}
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct HomeTrackerConfig {
     pub home_assistant_url: String,
     pub home_assistant_bearer_token: String,
     pub person_entity_id: String,
+
+    /// The person's state is shown as unknown once it hasn't been updated for this long
+    #[serde(default = "default_stale_after_secs")]
+    pub stale_after_secs: u64,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum PersonState {
     Home,
     Away,
@@ -83,90 +93,95 @@ where
         .with_alignment(vertical::Center)
         .with_spacing(spacing::FixedMargin(4))
         .arrange()
-        .draw(sub_canvas)
-        .unwrap();
+        .draw(sub_canvas)?;
 
         Ok(())
     }
 }
 
+/// Fetches the state of the person's entity
+struct PersonStateProvider {
+    client: home_assistant_rest::Client,
+    person_entity_id: String,
+}
+
+impl Provider for PersonStateProvider {
+    type Data = PersonState;
+    type State = PersonState;
+
+    fn name(&self) -> &'static str {
+        "home_assistant"
+    }
+
+    async fn fetch(&mut self) -> Result<Self::Data> {
+        let entity_state = self
+            .client
+            .get_states_of_entity(&self.person_entity_id)
+            .await
+            .map_err(|e| anyhow!("{e}"))?;
+
+        // Attempt to get the person's state
+        let person_state_str = if let Some(state_value) = entity_state.state {
+            if let StateEnum::String(value) = state_value {
+                Some(value)
+            } else {
+                warn!("Could not parse 'state' as str");
+                None
+            }
+        } else {
+            warn!("{}'s 'state' was not provided", self.person_entity_id);
+            None
+        };
+
+        Ok(match person_state_str {
+            Some(state) => match state.to_ascii_lowercase().as_str() {
+                "home" => PersonState::Home,
+                "work" => PersonState::Work,
+                "away" | "not_home" => PersonState::Away,
+                _ => PersonState::Unknown,
+            },
+            None => PersonState::Unknown,
+        })
+    }
+
+    fn apply(&mut self, state: &mut Self::State, data: Self::Data) -> Result<()> {
+        *state = data;
+        Ok(())
+    }
+}
+
 pub struct HomeAssistantTracker {
     state: Arc<Mutex<PersonState>>,
-    cancel_token: CancellationToken,
-    update_task_handle: Option<JoinHandle<Result<()>>>,
+
+    /// Keeps the person's state up to date
+    poller: Poller,
+
+    stale_after: Duration,
 }
 
 impl HomeAssistantTracker {
     pub fn new(config: HomeTrackerConfig) -> Result<Self> {
-        let home_assistant_client = home_assistant_rest::Client::new(
+        let client = home_assistant_rest::Client::new(
             &config.home_assistant_url,
             &config.home_assistant_bearer_token,
         )?;
 
-        let state_holder = Arc::new(Mutex::new(PersonState::Unknown));
-        let cancel_token = CancellationToken::new();
-
-        // Clone the shared data since it will be moved onto the task
-        let task_state_holder = state_holder.clone();
-        let task_cancel_token = cancel_token.clone();
-
-        let update_task_handle: JoinHandle<Result<()>> = tokio::task::spawn(async move {
-            'update_loop: loop {
-                let refresh_time = tokio::time::Instant::now() + Duration::from_secs(60);
-
-                let person_state = match home_assistant_client
-                    .get_states_of_entity(&config.person_entity_id)
-                    .await
-                {
-                    Ok(entity_state) => {
-                        // Attempt to get the person's state
-                        let person_state_str = if let Some(state_value) = entity_state.state {
-                            if let StateEnum::String(value) = state_value {
-                                Some(value)
-                            } else {
-                                warn!("Could not parse 'state' as str");
-                                None
-                            }
-                        } else {
-                            warn!("{}'s 'state' was not provided", config.person_entity_id);
-                            None
-                        };
-
-                        match person_state_str {
-                            Some(state) => match state.to_ascii_lowercase().as_str() {
-                                "home" => PersonState::Home,
-                                "work" => PersonState::Work,
-                                "away" | "not_home" => PersonState::Away,
-                                _ => PersonState::Unknown,
-                            },
-                            None => PersonState::Unknown,
-                        }
-                    }
-                    Err(e) => {
-                        warn!(
-                            "Could not acquire home assistant status for '{}' because of {}",
-                            config.person_entity_id, e
-                        );
-
-                        PersonState::Unknown
-                    }
-                };
-
-                *task_state_holder.lock() = person_state;
-
-                select! {
-                    _ = tokio::time::sleep_until(refresh_time) => {},
-                    _ = task_cancel_token.cancelled() => break 'update_loop,
-                }
-            }
-
-            Ok(())
-        });
+        let state = Arc::new(Mutex::new(PersonState::Unknown));
+        let cache_key = format!("home_assistant_{}", config.person_entity_id);
+        let poller = Poller::spawn_cached(
+            PersonStateProvider {
+                client,
+                person_entity_id: config.person_entity_id,
+            },
+            PollSettings::every(Duration::from_secs(60)),
+            state.clone(),
+            cache_key,
+        )?;
 
         Ok(Self {
-            state: state_holder,
-            cancel_token,
-            update_task_handle: Some(update_task_handle),
+            state,
+            poller,
+            stale_after: Duration::from_secs(config.stale_after_secs),
         })
     }
 }
@@ -176,17 +191,17 @@ where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
 {
     fn provide_state(&self) -> Box<dyn super::State<D>> {
-        let state: Box<dyn State<_>> = Box::new(*self.state.lock());
+        let person_state = if self.poller.stale(self.stale_after).is_some() {
+            PersonState::Unknown
+        } else {
+            *self.state.lock()
+        };
+
+        let state: Box<dyn State<_>> = Box::new(person_state);
         state
     }
-}
-
-impl Drop for HomeAssistantTracker {
-    fn drop(&mut self) {
-        self.cancel_token.cancel();
 
-        if let Some(task_handle) = self.update_task_handle.take() {
-            task_handle.abort();
-        }
+    fn health(&self) -> Vec<TaskHealth> {
+        vec![self.poller.health()]
     }
 }
@@ -12,29 +12,19 @@ use embedded_layout::{
     View,
 };
 use log::warn;
-use rustic_pixel_display::render::{Render, SubCanvas};
+use rustic_pixel_display::{
+    health::TaskHealth,
+    render::{Render, SubCanvas, Usefulness, UsefulnessVal},
+};
 use std::{collections::HashMap, convert::Infallible};
 
 mod home_assistant_tracker;
 mod septa_tracker;
+pub mod trace;
 
 pub use home_assistant_tracker::{HomeAssistantTracker, HomeTrackerConfig};
 pub use septa_tracker::{TransitTracker, TransitTrackerConfig, TransitTrackerFactory};
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
-pub enum UsefulnessVal {
-    NotUseful,
-    BarelyUseful,
-    SomewhatUseful,
-    Useful,
-    VeryUseful,
-    Essential,
-}
-
-pub trait Usefulness {
-    fn usefulness(&self) -> UsefulnessVal;
-}
-
 pub trait SubRender<D>
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
@@ -48,11 +38,17 @@ where
 {
 }
 
-pub trait StateProvider<D>
+/// Held by the [`PersonTracker`] render, so it has to be `Send` and `Sync` like renders are
+pub trait StateProvider<D>: Send + Sync
 where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
 {
     fn provide_state(&self) -> Box<dyn State<D>>;
+
+    /// Reports how the background tasks keeping the state up to date are doing
+    fn health(&self) -> Vec<TaskHealth> {
+        Vec::new()
+    }
 }
 
 // Create a blanket impl for State<D> if struct implements both Usefulness + SubRender<D>
@@ -131,13 +127,14 @@ where
                         height: 50,
                     };
 
-                    most_useful
-                        .sub_render(&mut SubCanvas::new(
-                            sub_canvas_offset,
-                            sub_canvas_size,
-                            canvas,
-                        ))
-                        .unwrap();
+                    // A tracker that can't draw its state only leaves its slot empty
+                    if let Err(e) = most_useful.sub_render(&mut SubCanvas::new(
+                        sub_canvas_offset,
+                        sub_canvas_size,
+                        canvas,
+                    )) {
+                        warn!("Could not draw the state of {person_name}: {e:#}");
+                    }
 
                     offset = sub_canvas_offset
                         + Size {
@@ -151,4 +148,30 @@ where
 
         Ok(())
     }
+
+    /// As useful as the most useful state of any person, i.e. someone being on a train
+    fn usefulness(&self) -> UsefulnessVal {
+        self.person_to_trackers
+            .values()
+            .flatten()
+            .map(|tracker| tracker.provide_state().usefulness())
+            .max()
+            .unwrap_or(UsefulnessVal::NotUseful)
+    }
+
+    /// The tasks of every tracker, named after the person they track
+    fn health(&self) -> Vec<TaskHealth> {
+        self.person_to_trackers
+            .iter()
+            .flat_map(|(person_name, trackers)| {
+                trackers
+                    .iter()
+                    .flat_map(|tracker| tracker.health())
+                    .map(move |mut task_health| {
+                        task_health.task = format!("{person_name}: {}", task_health.task);
+                        task_health
+                    })
+            })
+            .collect()
+    }
 }
@@ -1,8 +1,9 @@
+use crate::{components::marquee::Marquee, recordings::recording_path};
 use anyhow::{anyhow, Result};
 use embedded_graphics::{
     mono_font::{self, MonoTextStyle},
     pixelcolor::{Rgb555, Rgb565, Rgb888},
-    prelude::{DrawTarget, PixelColor, Point, RgbColor},
+    prelude::{Dimensions, DrawTarget, PixelColor, Point, RgbColor},
     text::Text,
     Drawable,
 };
@@ -14,25 +15,29 @@ use embedded_layout::{
 };
 use embedded_layout_macros::ViewGroup;
 use geoutils::{Distance, Location};
-use log::{debug, error};
+use log::{debug, warn};
 use parking_lot::Mutex;
-use rustic_pixel_display::render::{Render, RenderFactory, SubCanvas};
+use rustic_pixel_display::{
+    health::TaskHealth,
+    provider::{PollSettings, Poller, Provider},
+    render::{Render, RenderFactory, SubCanvas, Usefulness, UsefulnessVal},
+};
 use septa_api::{responses::Train, types::RegionalRailStop};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     convert::Infallible,
     io::Read,
     marker::PhantomData,
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
 use strum::IntoEnumIterator;
 use tinybmp::Bmp;
-use tokio::{join, select, task::JoinHandle};
-use tokio_util::sync::CancellationToken;
+use tokio::join;
 
-use super::{State, StateProvider, SubRender, Usefulness};
+use super::{trace::TraceSample, PersonTracker, State, StateProvider, SubRender};
 
 /// The amount of time the user has to be within the radius of a station to be considered at the station.
 const NO_STATUS_TO_AT_STATION: Duration = Duration::from_secs(30);
@@ -66,8 +71,39 @@ lazy_static! {
     static ref SEPTA_BMP: Bmp::<'static, Rgb888> = Bmp::<Rgb888>::from_slice(SEPTA_IMAGE).unwrap();
 }
 
+// States are recreated for every frame, so scrolling text is driven by a shared clock instead.
+lazy_static! {
+    static ref MARQUEE_START: Instant = Instant::now();
+}
+
+/// The parts of a SEPTA train the transit state needs, kept separately from the API's response so
+/// that traces can record them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct TrackedTrain {
+    train_number: String,
+    lat: f64,
+    lon: f64,
+
+    /// Minutes the train is running late, negative when it is early
+    late: i32,
+
+    dest: String,
+}
+
+impl From<Train> for TrackedTrain {
+    fn from(value: Train) -> Self {
+        Self {
+            train_number: value.train_number,
+            lat: value.lat,
+            lon: value.lon,
+            late: value.late,
+            dest: value.dest.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
-struct TrainEncounter {
+pub(super) struct TrainEncounter {
     /// The first time the user encountered the train inside the radius of the current station.
     first_encounter_inside_station: Option<Instant>,
 
@@ -76,7 +112,7 @@ struct TrainEncounter {
 }
 
 #[derive(Debug, Clone)]
-enum TransitState {
+pub(super) enum TransitState {
     NoStatus {
         /// A map of the regional rail stop to the time the user entered into the radius of the station.
         /// We use time::Instance since we need a monotonic clock and do not care about the system time.
@@ -95,7 +131,7 @@ enum TransitState {
     },
     OnTrain {
         /// The train (wrap in Box to get rid of the clippy::large_enum_variant lint warning)
-        train: Box<Train>,
+        train: Box<TrackedTrain>,
 
         /// The time the user has been on the train.
         last_train_encounter: Instant,
@@ -110,21 +146,41 @@ impl Default for TransitState {
     }
 }
 
+fn default_stale_after_secs() -> u64 {
+    2 * 60
+}
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct TransitTrackerConfig {
     pub home_assistant_url: String,
     pub home_assistant_bearer_token: String,
     pub person_entity_id: String,
+
+    /// No status is shown once the trains or the person's location haven't been updated for
+    /// this long
+    #[serde(default = "default_stale_after_secs")]
+    pub stale_after_secs: u64,
+
+    /// Appends every location and train list the tracker receives to this JSONL file, which the
+    /// `transit_trace` binary can replay. Named relative to the
+    /// [recordings directory](crate::recordings).
+    #[serde(default)]
+    pub record_trace: Option<PathBuf>,
 }
 
 impl TransitState {
-    fn new() -> Self {
+    pub(super) fn new() -> Self {
         Self::default()
     }
 
-    fn update_state(self, lat_lon: (f64, f64), trains: Vec<Train>) -> Result<Self> {
-        // Get the monotonic time
-        let now = Instant::now();
+    /// Moves the state machine on with the person's location and the trains at `now`, which is
+    /// passed in so that traces can be replayed faster than they were recorded
+    pub(super) fn update_state(
+        self,
+        now: Instant,
+        lat_lon: (f64, f64),
+        trains: Vec<TrackedTrain>,
+    ) -> Result<Self> {
         let person_location = Location::new(lat_lon.0, lat_lon.1);
 
         Ok(match self {
@@ -359,10 +415,12 @@ impl TransitState {
                                 train: Box::new(train),
                                 last_train_encounter,
                             }
-                        } else if last_train_encounter - now > ON_TRAIN_TO_NO_STATUS_TIMEOUT {
+                        } else if now - last_train_encounter > ON_TRAIN_TO_NO_STATUS_TIMEOUT {
                             let station: Option<RegionalRailStop> = {
                                 let mut regional_rail_stop = None;
-                                for station in RegionalRailStop::iter() {
+                                for station in RegionalRailStop::iter()
+                                    .filter(|p| !matches!(p, RegionalRailStop::Unknown(_)))
+                                {
                                     let station_location = {
                                         let (lat, lon) = station.lat_lon()?;
                                         Location::new(lat, lon)
@@ -435,11 +493,11 @@ pub enum DisplayTransitState {
 }
 
 impl Usefulness for DisplayTransitState {
-    fn usefulness(&self) -> super::UsefulnessVal {
+    fn usefulness(&self) -> UsefulnessVal {
         match self {
-            DisplayTransitState::NoStatus => super::UsefulnessVal::NotUseful,
-            DisplayTransitState::AtStation { .. } => super::UsefulnessVal::VeryUseful,
-            DisplayTransitState::OnTrain { .. } => super::UsefulnessVal::VeryUseful,
+            DisplayTransitState::NoStatus => UsefulnessVal::NotUseful,
+            DisplayTransitState::AtStation { .. } => UsefulnessVal::VeryUseful,
+            DisplayTransitState::OnTrain { .. } => UsefulnessVal::VeryUseful,
         }
     }
 }
@@ -466,11 +524,15 @@ where
                 )
             }
             DisplayTransitState::AtStation { station_name } => {
-                let chain = Chain::new(Text::new(
-                    station_name,
-                    Point::zero(),
-                    MonoTextStyle::new(&mono_font::ascii::FONT_6X10, Rgb888::WHITE),
-                ));
+                let chain = Chain::new(
+                    Marquee::new(
+                        station_name,
+                        Point::zero(),
+                        sub_canvas.bounding_box().size.width,
+                        MonoTextStyle::new(&mono_font::ascii::FONT_6X10, Rgb888::WHITE),
+                    )
+                    .at(MARQUEE_START.elapsed()),
+                );
 
                 PersonStatusView::AtStation(
                     LinearLayout::horizontal(chain)
@@ -490,21 +552,33 @@ where
                     TrainStatus::Late(_) => Rgb888::RED,
                 };
 
-                let chain = Chain::new(Text::new(
+                let train_number_view = Text::new(
                     train_number,
                     Point::zero(),
                     MonoTextStyle::new(&mono_font::ascii::FONT_6X10, Rgb888::WHITE),
-                ))
-                .append(Text::new(
+                );
+                let status_view = Text::new(
                     status_text,
                     Point::zero(),
                     MonoTextStyle::new(&mono_font::ascii::FONT_6X10, status_color),
-                ))
-                .append(Text::new(
-                    destination,
-                    Point::zero(),
-                    MonoTextStyle::new(&mono_font::ascii::FONT_6X10, Rgb888::WHITE),
-                ));
+                );
+
+                // The destination scrolls in whatever is left of the line
+                let destination_width = sub_canvas.bounding_box().size.width.saturating_sub(
+                    train_number_view.bounding_box().size.width
+                        + status_view.bounding_box().size.width
+                        + 2 * 6,
+                );
+
+                let chain = Chain::new(train_number_view).append(status_view).append(
+                    Marquee::new(
+                        destination,
+                        Point::zero(),
+                        destination_width,
+                        MonoTextStyle::new(&mono_font::ascii::FONT_6X10, Rgb888::WHITE),
+                    )
+                    .at(MARQUEE_START.elapsed()),
+                );
 
                 PersonStatusView::OnTrain(
                     LinearLayout::horizontal(chain)
@@ -519,8 +593,7 @@ where
             .with_alignment(horizontal::Left)
             .with_spacing(spacing::FixedMargin(4))
             .arrange()
-            .draw(sub_canvas)
-            .unwrap();
+            .draw(sub_canvas)?;
 
         Ok(())
     }
@@ -551,23 +624,21 @@ impl From<&TransitState> for DisplayTransitState {
     }
 }
 
-pub struct TransitTracker {
-    state: Arc<Mutex<TransitState>>,
-
-    /// Used to signal that all async tasks should be cancelled immediately
-    cancel_token: CancellationToken,
+/// Fetches the SEPTA trains and the person's location, the transit state needs both to move on
+struct TransitProvider {
+    septa_client: septa_api::Client,
+    home_assistant_client: home_assistant_rest::Client,
+    config: TransitTrackerConfig,
 
-    /// Handle to the task used to update the SEPTA and User location
-    update_task_handle: Option<JoinHandle<Result<()>>>,
+    /// Where `record_trace` resolved to in the recordings directory
+    trace_path: Option<PathBuf>,
 }
 
-impl TransitTracker {
-    async fn get_location(
-        home_assistant_client: &home_assistant_rest::Client,
-        config: &TransitTrackerConfig,
-    ) -> Result<(f64, f64)> {
-        let entity_state = home_assistant_client
-            .get_states_of_entity(&config.person_entity_id)
+impl TransitProvider {
+    async fn get_location(&self) -> Result<(f64, f64)> {
+        let entity_state = self
+            .home_assistant_client
+            .get_states_of_entity(&self.config.person_entity_id)
             .await?;
 
         if let (Some(lat), Some(lon)) = (
@@ -583,67 +654,93 @@ impl TransitTracker {
             Err(anyhow!("Could not match lat lng"))
         }
     }
+}
 
-    pub fn new(config: TransitTrackerConfig) -> Result<Self> {
-        let septa_client = septa_api::Client::new();
-        let home_assistant_client = home_assistant_rest::Client::new(
-            &config.home_assistant_url,
-            &config.home_assistant_bearer_token,
-        )?;
+impl Provider for TransitProvider {
+    type Data = TraceSample;
+    type State = TransitState;
 
-        let state_holder = Arc::new(Mutex::new(TransitState::new()));
-        let cancel_token = CancellationToken::new();
+    fn name(&self) -> &'static str {
+        "septa_transit"
+    }
 
-        // Clone the shared data since it will be moved onto the task
-        let task_state_holder = state_holder.clone();
-        let task_cancel_token = cancel_token.clone();
+    async fn fetch(&mut self) -> Result<Self::Data> {
+        let (trains_result, user_location_result) =
+            join!(self.septa_client.train_view(), self.get_location());
 
-        let update_task_handle: JoinHandle<Result<()>> = tokio::task::spawn(async move {
-            'update_loop: loop {
-                let refresh_time = tokio::time::Instant::now() + Duration::from_secs(15);
+        let sample = match (user_location_result, trains_result) {
+            (Ok(user_location), Ok(trains)) => {
+                TraceSample::new(user_location, trains.into_iter().map(Into::into).collect())
+            }
+            (Err(location_error), Err(train_error)) => return Err(anyhow!("Error in both location and SEPTA calls (location_error: {location_error}, train_error: {train_error})")),
+            (Ok(_), Err(train_error)) => return Err(anyhow!("Error in SEPTA call ({train_error})")),
+            (Err(location_error), Ok(_)) => return Err(anyhow!("Error in location call ({location_error})")),
+        };
 
-                let trains_request = septa_client.train_view();
-                let user_location_request = Self::get_location(&home_assistant_client, &config);
+        // A trace that can't be written shouldn't stop the tracker
+        if let Some(path) = &self.trace_path {
+            if let Err(e) = sample.append_to(path).await {
+                warn!(
+                    "Could not record the transit trace {}: {e:#}",
+                    path.display()
+                );
+            }
+        }
 
-                let (trains_result, user_location_result) =
-                    join!(trains_request, user_location_request);
+        Ok(sample)
+    }
 
-                match (user_location_result, trains_result) {
-                    (Ok((user_loc_lat, user_loc_lon)), Ok(trains)) => {
-                        let mut holder_unlocked = task_state_holder.lock();
+    fn apply(&mut self, state: &mut Self::State, data: Self::Data) -> Result<()> {
+        let (user_location, trains) = data.into_parts();
 
-                        let transit_state = std::mem::take(&mut *holder_unlocked);
-                        let new_state =
-                            transit_state.update_state((user_loc_lat, user_loc_lon), trains)?;
+        let transit_state = std::mem::take(state);
+        *state = transit_state.update_state(Instant::now(), user_location, trains)?;
 
-                        debug!("Updated state: {:?}", new_state);
+        debug!("Updated state: {:?}", state);
+        Ok(())
+    }
+}
 
-                        let _ = std::mem::replace(&mut *holder_unlocked, new_state);
-                    }
-                    (Err(location_error), Err(train_error)) => {
-                        error!("Error in both location and SEPTA calls (location_error: {location_error}, train_error: {train_error})");
-                    }
-                    (Ok(_), Err(train_error)) => {
-                        error!("Error in SEPTA call ({train_error})");
-                    }
-                    (Err(location_error), Ok(_)) => {
-                        error!("Error in location call ({location_error})");
-                    }
-                }
+pub struct TransitTracker {
+    state: Arc<Mutex<TransitState>>,
 
-                select! {
-                    _ = tokio::time::sleep_until(refresh_time) => {},
-                    _ = task_cancel_token.cancelled() => break 'update_loop,
-                }
-            }
+    /// Keeps the SEPTA trains and the person's location up to date
+    poller: Poller,
+
+    stale_after: Duration,
+}
+
+impl TransitTracker {
+    pub fn new(config: TransitTrackerConfig) -> Result<Self> {
+        let septa_client = septa_api::Client::new();
+        let home_assistant_client = home_assistant_rest::Client::new(
+            &config.home_assistant_url,
+            &config.home_assistant_bearer_token,
+        )?;
 
-            Ok(())
-        });
+        let trace_path = config
+            .record_trace
+            .as_deref()
+            .map(recording_path)
+            .transpose()?;
+
+        let stale_after = Duration::from_secs(config.stale_after_secs);
+        let state = Arc::new(Mutex::new(TransitState::new()));
+        let poller = Poller::spawn(
+            TransitProvider {
+                septa_client,
+                home_assistant_client,
+                config,
+                trace_path,
+            },
+            PollSettings::every(Duration::from_secs(15)),
+            state.clone(),
+        )?;
 
         Ok(Self {
-            state: state_holder,
-            cancel_token,
-            update_task_handle: Some(update_task_handle),
+            state,
+            poller,
+            stale_after,
         })
     }
 }
@@ -653,10 +750,18 @@ where
     D: DrawTarget<Color = Rgb888, Error = Infallible>,
 {
     fn provide_state(&self) -> Box<dyn super::State<D>> {
-        let display_state: DisplayTransitState = (&*self.state.lock()).into();
+        let display_state = if self.poller.stale(self.stale_after).is_some() {
+            DisplayTransitState::NoStatus
+        } else {
+            (&*self.state.lock()).into()
+        };
         let state: Box<dyn State<D>> = Box::new(display_state);
         state
     }
+
+    fn health(&self) -> Vec<TaskHealth> {
+        vec![self.poller.health()]
+    }
 }
 
 type NoStatusViews<'a, C> = chain! {
@@ -664,13 +769,13 @@ type NoStatusViews<'a, C> = chain! {
 };
 
 type AtStationViews<'a, C> = chain! {
-    Text<'a, MonoTextStyle<'static, C>>
+    Marquee<'a, MonoTextStyle<'static, C>>
 };
 
 type OnTrainViews<'a, C> = chain! {
     Text<'a, MonoTextStyle<'static, C>>,
     Text<'a, MonoTextStyle<'static, C>>,
-    Text<'a, MonoTextStyle<'static, C>>
+    Marquee<'a, MonoTextStyle<'static, C>>
 };
 
 #[derive(ViewGroup)]
@@ -704,7 +809,7 @@ where
 
 impl<D> RenderFactory<D> for TransitTrackerFactory<D>
 where
-    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    D: DrawTarget<Color = Rgb888, Error = Infallible> + 'static,
 {
     fn render_name(&self) -> &'static str {
         "TransitTracker"
@@ -714,17 +819,64 @@ where
         "Tracks a person based on the SEPTA transit information"
     }
 
-    fn load_from_config<R: Read>(&self, _reader: R) -> Result<Box<dyn Render<D>>> {
-        todo!()
+    /// The configuration maps the name shown for each person to how they are tracked
+    fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
+        let config: HashMap<String, TransitTrackerConfig> = serde_json::from_reader(reader)?;
+
+        let mut person_to_trackers: HashMap<String, Vec<Box<dyn StateProvider<D>>>> =
+            HashMap::new();
+        for (person_name, tracker_config) in config {
+            person_to_trackers.insert(
+                person_name,
+                vec![Box::new(TransitTracker::new(tracker_config)?)],
+            );
+        }
+
+        Ok(Box::new(PersonTracker::new(person_to_trackers)))
     }
 }
 
-impl Drop for TransitTracker {
-    fn drop(&mut self) {
-        self.cancel_token.cancel();
+#[cfg(test)]
+mod tests {
+    use super::{TrackedTrain, TransitState, ON_TRAIN_TO_NO_STATUS_TIMEOUT};
+    use std::time::{Duration, Instant};
+
+    /// In the Pine Barrens, nowhere near a station
+    const AWAY_FROM_STATIONS: (f64, f64) = (39.8, -74.5);
+
+    #[test]
+    fn leaves_a_train_once_it_is_out_of_reach() {
+        let boarded = Instant::now();
+        let train = TrackedTrain {
+            train_number: "9553".to_owned(),
+            lat: 39.9539,
+            lon: -75.1678,
+            late: 0,
+            dest: "Thorndale".to_owned(),
+        };
+        let state = TransitState::OnTrain {
+            train: Box::new(train.clone()),
+            last_train_encounter: boarded,
+        };
 
-        if let Some(task_handle) = self.update_task_handle.take() {
-            task_handle.abort();
-        }
+        // Losing the train for a little while (i.e. in a tunnel) keeps the person on it
+        let state = state
+            .update_state(
+                boarded + Duration::from_secs(60),
+                AWAY_FROM_STATIONS,
+                vec![train.clone()],
+            )
+            .unwrap();
+        assert!(matches!(state, TransitState::OnTrain { .. }));
+
+        // The person isn't near any station, so they go back to having no status
+        let state = state
+            .update_state(
+                boarded + ON_TRAIN_TO_NO_STATUS_TIMEOUT + Duration::from_secs(1),
+                AWAY_FROM_STATIONS,
+                vec![train],
+            )
+            .unwrap();
+        assert!(matches!(state, TransitState::NoStatus { .. }));
     }
 }
@@ -0,0 +1,254 @@
+//! Recording and replaying of what the transit tracker receives, so that changes to its state
+//! machine can be checked against real commutes without being on a train.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncWriteExt;
+
+use super::septa_tracker::{DisplayTransitState, TrackedTrain, TransitState};
+
+/// The person's location and the SEPTA trains at one point in time, one line of a trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceSample {
+    timestamp: DateTime<Utc>,
+    lat_lon: (f64, f64),
+    trains: Vec<TrackedTrain>,
+}
+
+impl TraceSample {
+    pub(super) fn new(lat_lon: (f64, f64), trains: Vec<TrackedTrain>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            lat_lon,
+            trains,
+        }
+    }
+
+    pub(super) fn into_parts(self) -> ((f64, f64), Vec<TrackedTrain>) {
+        (self.lat_lon, self.trains)
+    }
+
+    /// Appends the sample to the trace in `path`, which is created if needed
+    pub(super) async fn append_to(&self, path: &Path) -> Result<()> {
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+
+        tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?
+            .write_all(line.as_bytes())
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Reads a trace recorded by the tracker's `record_trace`
+pub fn load_trace(path: &Path) -> Result<Vec<TraceSample>> {
+    fs::read_to_string(path)
+        .with_context(|| format!("Could not read the trace {}", path.display()))?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid sample on line {}", index + 1))
+        })
+        .collect()
+}
+
+/// The states of the transit state machine, without what they are about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitPhase {
+    NoStatus,
+    AtStation,
+    OnTrain,
+}
+
+impl Display for TransitPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitPhase::NoStatus => write!(f, "no_status"),
+            TransitPhase::AtStation => write!(f, "at_station"),
+            TransitPhase::OnTrain => write!(f, "on_train"),
+        }
+    }
+}
+
+impl FromStr for TransitPhase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "no_status" => Ok(TransitPhase::NoStatus),
+            "at_station" => Ok(TransitPhase::AtStation),
+            "on_train" => Ok(TransitPhase::OnTrain),
+            _ => bail!("Unknown transit phase {s}, expected no_status, at_station or on_train"),
+        }
+    }
+}
+
+/// A state the state machine moved into while replaying a trace
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// When the sample causing the transition was recorded
+    pub at: DateTime<Utc>,
+    pub phase: TransitPhase,
+
+    /// The station or train number the person is at or on
+    pub detail: Option<String>,
+}
+
+/// The phase `state` is in, along with the station or train it is about
+fn phase_of(state: &TransitState) -> (TransitPhase, Option<String>) {
+    match DisplayTransitState::from(state) {
+        DisplayTransitState::NoStatus => (TransitPhase::NoStatus, None),
+        DisplayTransitState::AtStation { station_name } => {
+            (TransitPhase::AtStation, Some(station_name))
+        }
+        DisplayTransitState::OnTrain { train_number, .. } => {
+            (TransitPhase::OnTrain, Some(train_number))
+        }
+    }
+}
+
+impl Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { at, phase, detail } = self;
+
+        match detail {
+            Some(detail) => write!(f, "{} {phase} ({detail})", at.format("%H:%M:%S")),
+            None => write!(f, "{} {phase}", at.format("%H:%M:%S")),
+        }
+    }
+}
+
+/// Feeds a trace through the transit state machine and returns the states it went through,
+/// starting with the one it is in after the first sample.
+///
+/// The state machine is given the recorded times rather than the wall clock, so the timeouts
+/// behave as they did live however fast the trace is replayed. `speed` is how many times faster
+/// than recorded the samples are fed, `None` feeds them as fast as possible.
+pub async fn replay_trace(
+    samples: Vec<TraceSample>,
+    speed: Option<f64>,
+) -> Result<Vec<Transition>> {
+    if speed.is_some_and(|speed| speed.is_nan() || speed <= 0.0) {
+        bail!("The replay speed has to be greater than 0");
+    }
+
+    let Some(first_timestamp) = samples.first().map(|sample| sample.timestamp) else {
+        bail!("The trace has no samples");
+    };
+
+    let start = Instant::now();
+    let mut state = TransitState::new();
+    let mut transitions: Vec<Transition> = Vec::new();
+
+    for sample in samples {
+        let offset = (sample.timestamp - first_timestamp)
+            .to_std()
+            .context("The samples of the trace are out of order")?;
+
+        if let Some(speed) = speed {
+            let wake_at = Duration::try_from_secs_f64(offset.as_secs_f64() / speed)
+                .ok()
+                .and_then(|delay| start.checked_add(delay))
+                .with_context(|| {
+                    format!("The trace is too long to replay at a speed of {speed}")
+                })?;
+            tokio::time::sleep_until(wake_at.into()).await;
+        }
+
+        let at = sample.timestamp;
+        let now = start
+            .checked_add(offset)
+            .context("The samples of the trace are too far apart")?;
+        let (lat_lon, trains) = sample.into_parts();
+        state = state.update_state(now, lat_lon, trains)?;
+
+        let (phase, detail) = phase_of(&state);
+        let changed = transitions
+            .last()
+            .is_none_or(|last| last.phase != phase || last.detail != detail);
+        if changed {
+            transitions.push(Transition { at, phase, detail });
+        }
+    }
+
+    Ok(transitions)
+}
+
+/// Fails unless the transitions went through the `expected` phases, in order
+pub fn check_transitions(transitions: &[Transition], expected: &[TransitPhase]) -> Result<()> {
+    let phases: Vec<TransitPhase> = transitions
+        .iter()
+        .map(|transition| transition.phase)
+        .collect();
+
+    if phases != expected {
+        let format = |phases: &[TransitPhase]| {
+            phases
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        };
+
+        bail!(
+            "Expected {} but went through {}",
+            format(expected),
+            format(&phases)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_transitions, load_trace, replay_trace, TransitPhase};
+    use std::{collections::BTreeMap, fs, path::Path};
+
+    #[tokio::test]
+    async fn checked_in_traces_go_through_the_expected_phases() {
+        let dir = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/transit_traces"
+        ));
+        let expectations: BTreeMap<String, Vec<TransitPhase>> =
+            serde_json::from_str(&fs::read_to_string(dir.join("expected.json")).unwrap()).unwrap();
+
+        for (file_name, expected) in &expectations {
+            let transitions = replay_trace(load_trace(&dir.join(file_name)).unwrap(), None)
+                .await
+                .unwrap();
+
+            if let Err(e) = check_transitions(&transitions, expected) {
+                panic!("{file_name}: {e:#}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn speeds_too_slow_to_schedule_are_an_error() {
+        let trace = load_trace(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/transit_traces/wait_at_temple.jsonl"
+        )))
+        .unwrap();
+
+        assert!(replay_trace(trace, Some(1e-300)).await.is_err());
+    }
+}
@@ -1,18 +1,96 @@
-use std::{cmp::Ordering, error::Error};
+use std::{cmp::Ordering, sync::Arc};
 
 use amtrak_api::{
     responses::{TrainState, TrainStatus},
     Client,
 };
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, FixedOffset};
+use parking_lot::Mutex;
+use rustic_pixel_display::provider::{PollSettings, Poller, Provider};
+use serde::{Deserialize, Serialize};
 
-use super::{UpcomingTrain, UpcomingTrainDirection, UpcomingTrainStatus};
+use super::{
+    source::{
+        ArrivalSource, ArrivalSourceConfig, FixtureSource, RecordingSource, ReplaySource,
+        Reschedule,
+    },
+    UpcomingTrain, UpcomingTrainDirection, UpcomingTrainStatus, UpcomingTrainsState,
+    REFRESH_INTERVAL,
+};
+
+/// An Amtrak train that is still on its way to the station, with the parts of its route the
+/// render uses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct AmtrakArrival {
+    train_id: String,
+    origin_name: String,
+    destination_name: String,
+    destination_code: String,
+
+    /// When the train is scheduled in the station
+    schedule_arrival: DateTime<FixedOffset>,
+
+    /// When the train is expected in the station, if Amtrak has an estimate
+    estimated_arrival: Option<DateTime<FixedOffset>>,
+}
+
+impl Reschedule for AmtrakArrival {
+    fn reschedule(&mut self, by: Duration) {
+        self.schedule_arrival += by;
+        self.estimated_arrival = self.estimated_arrival.map(|arrival| arrival + by);
+    }
+}
+
+impl AmtrakArrival {
+    fn into_upcoming_train(self, station_code: &str) -> UpcomingTrain {
+        let is_arrival = self.destination_code == station_code;
+
+        UpcomingTrain {
+            schedule_arrival: self.schedule_arrival,
+            destination_name: if is_arrival {
+                self.origin_name
+            } else {
+                self.destination_name
+            },
+            direction: if is_arrival {
+                UpcomingTrainDirection::Arrival
+            } else {
+                UpcomingTrainDirection::Departure
+            },
+            train_id: self.train_id,
+            status: match self.estimated_arrival {
+                None => UpcomingTrainStatus::Unknown,
+                Some(est_arrival) => {
+                    let mins_early = self
+                        .schedule_arrival
+                        .signed_duration_since(est_arrival)
+                        .num_minutes();
+
+                    match mins_early.cmp(&0) {
+                        Ordering::Equal => UpcomingTrainStatus::OnTime,
+                        Ordering::Less => match mins_early.abs().try_into() {
+                            Ok(num) => UpcomingTrainStatus::Late(num),
+                            Err(_) => UpcomingTrainStatus::Unknown,
+                        },
+                        Ordering::Greater => match mins_early.abs().try_into() {
+                            Ok(num) => UpcomingTrainStatus::Early(num),
+                            Err(_) => UpcomingTrainStatus::Unknown,
+                        },
+                    }
+                }
+            },
+        }
+    }
+}
 
-pub(super) struct AmtrakProvider {
+/// Requests every train from the Amtrak API and keeps the ones still due in a station
+pub(super) struct LiveAmtrakSource {
     station_code: String,
     client: Client,
 }
 
-impl AmtrakProvider {
+impl LiveAmtrakSource {
     pub(super) fn new(station_code: String) -> Self {
         let client = Client::new();
 
@@ -21,17 +99,19 @@ impl AmtrakProvider {
             station_code,
         }
     }
+}
 
-    pub(super) async fn arrivals(&self) -> Result<Vec<UpcomingTrain>, Box<dyn Error>> {
+impl ArrivalSource<AmtrakArrival> for LiveAmtrakSource {
+    async fn arrivals(&mut self) -> Result<Vec<AmtrakArrival>> {
         let Self {
             station_code,
             client,
-            ..
         } = self;
 
         let arrivals = client
             .trains()
-            .await?
+            .await
+            .map_err(|e| anyhow!("{e}"))?
             .0
             .into_iter()
             .flat_map(|(_, trains)| {
@@ -45,50 +125,109 @@ impl AmtrakProvider {
                     .stations
                     .into_iter()
                     .find(|station| &station.code == station_code)
-                    .filter(|station| (station.status == TrainStatus::Enroute));
-
-                if let Some(station) = station {
-                    Some(UpcomingTrain {
-                        schedule_arrival: station.schedule_arrival,
-                        destination_name: if train.destination_code == self.station_code {
-                            train.origin_name
-                        } else {
-                            train.destination_name
-                        },
-                        direction: if train.destination_code == self.station_code {
-                            UpcomingTrainDirection::Arrival
-                        } else {
-                            UpcomingTrainDirection::Departure
-                        },
-                        train_id: train.train_id,
-                        status: match station.arrival {
-                            None => super::UpcomingTrainStatus::Unknown,
-                            Some(est_arrival) => {
-                                let mins_early = station
-                                    .schedule_arrival
-                                    .signed_duration_since(est_arrival)
-                                    .num_minutes();
-
-                                match mins_early.cmp(&0) {
-                                    Ordering::Equal => super::UpcomingTrainStatus::OnTime,
-                                    Ordering::Less => match mins_early.abs().try_into() {
-                                        Ok(num) => UpcomingTrainStatus::Late(num),
-                                        Err(_) => UpcomingTrainStatus::Unknown,
-                                    },
-                                    Ordering::Greater => match mins_early.abs().try_into() {
-                                        Ok(num) => UpcomingTrainStatus::Early(num),
-                                        Err(_) => UpcomingTrainStatus::Unknown,
-                                    },
-                                }
-                            }
-                        },
-                    })
-                } else {
-                    None
-                }
+                    .filter(|station| (station.status == TrainStatus::Enroute))?;
+
+                Some(AmtrakArrival {
+                    train_id: train.train_id,
+                    origin_name: train.origin_name,
+                    destination_name: train.destination_name,
+                    destination_code: train.destination_code,
+                    schedule_arrival: station.schedule_arrival,
+                    estimated_arrival: station.arrival,
+                })
             })
             .collect::<Vec<_>>();
 
         Ok(arrivals)
     }
 }
+
+pub(super) struct AmtrakProvider<S> {
+    /// Trains ending their trip in this station are arrivals, the others departures
+    station_code: String,
+    source: S,
+}
+
+impl<S> AmtrakProvider<S>
+where
+    S: ArrivalSource<AmtrakArrival>,
+{
+    pub(super) fn new(station_code: String, source: S) -> Self {
+        Self {
+            station_code,
+            source,
+        }
+    }
+}
+
+impl<S> Provider for AmtrakProvider<S>
+where
+    S: ArrivalSource<AmtrakArrival>,
+{
+    type Data = Vec<UpcomingTrain>;
+    type State = UpcomingTrainsState;
+
+    fn name(&self) -> &'static str {
+        "amtrak"
+    }
+
+    async fn fetch(&mut self) -> Result<Self::Data> {
+        let station_code = &self.station_code;
+
+        Ok(self
+            .source
+            .arrivals()
+            .await?
+            .into_iter()
+            .map(|arrival| arrival.into_upcoming_train(station_code))
+            .collect())
+    }
+
+    fn apply(&mut self, state: &mut Self::State, data: Self::Data) -> Result<()> {
+        state.amtrak_arrivals = data;
+        state.combine();
+        Ok(())
+    }
+}
+
+/// Starts polling the trains due in `station_code` from the configured source. Only the live
+/// arrivals are cached, recorded ones would otherwise replace them on the next start.
+pub(super) fn spawn_poller(
+    station_code: String,
+    source: &ArrivalSourceConfig,
+    state: Arc<Mutex<UpcomingTrainsState>>,
+) -> Result<Poller> {
+    let settings = PollSettings::every(REFRESH_INTERVAL);
+    let cache_key = format!("amtrak_arrivals_{station_code}");
+
+    match source {
+        ArrivalSourceConfig::Live => {
+            let source = LiveAmtrakSource::new(station_code.clone());
+            Poller::spawn_cached(
+                AmtrakProvider::new(station_code, source),
+                settings,
+                state,
+                cache_key,
+            )
+        }
+        ArrivalSourceConfig::Record(path) => {
+            let source = RecordingSource::new(LiveAmtrakSource::new(station_code.clone()), path)?;
+            Poller::spawn_cached(
+                AmtrakProvider::new(station_code, source),
+                settings,
+                state,
+                cache_key,
+            )
+        }
+        ArrivalSourceConfig::Fixture(path) => Poller::spawn(
+            AmtrakProvider::new(station_code, FixtureSource::load(path)?),
+            settings,
+            state,
+        ),
+        ArrivalSourceConfig::Replay(path) => Poller::spawn(
+            AmtrakProvider::new(station_code, ReplaySource::load(path)?),
+            settings,
+            state,
+        ),
+    }
+}
@@ -1,10 +1,15 @@
+use crate::components::{
+    marquee::Marquee,
+    text_box::{TextBox, VerticalAlignment, TRANSIT_ABBREVIATIONS},
+};
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Utc};
 use embedded_graphics::{
     image::Image,
-    mono_font::{self, MonoTextStyle},
+    mono_font::{self, MonoFont, MonoTextStyle},
     pixelcolor::Rgb888,
-    prelude::{DrawTarget, ImageDrawable, PixelColor, Point, RgbColor},
+    prelude::{DrawTarget, ImageDrawable, PixelColor, Point, RgbColor, Size},
+    primitives::Rectangle,
     text::Text,
     Drawable,
 };
@@ -17,22 +22,32 @@ use embedded_layout::{
 };
 use embedded_layout::{layout::linear::spacing, prelude::Link};
 use embedded_layout_macros::ViewGroup;
-use log::error;
 use parking_lot::Mutex;
-use rustic_pixel_display::render::{Render, RenderFactory};
+use rustic_pixel_display::{
+    health::TaskHealth,
+    provider::Poller,
+    render::{
+        draw_stale_badge, FadedCanvas, Render, RenderFactory, UsefulnessVal, STALE_BRIGHTNESS,
+    },
+};
 use septa_api::types::RegionalRailStop;
-use serde::Deserialize;
-use std::{convert::Infallible, io::Read, marker::PhantomData, sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    io::Read,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tinybmp::Bmp;
-use tokio::{select, task::JoinHandle};
-use tokio_util::sync::CancellationToken;
 
-use self::{amtrak_provider::AmtrakProvider, septa_provider::SeptaProvider};
+pub use self::source::ArrivalSourceConfig;
 
 mod amtrak_provider;
 mod septa_provider;
+mod source;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 enum UpcomingTrainStatus {
     OnTime,
     Early(u32),
@@ -40,13 +55,22 @@ enum UpcomingTrainStatus {
     Unknown,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 enum UpcomingTrainDirection {
     Arrival,
     Departure,
 }
 
-#[derive(Debug, Clone)]
+/// How often the arrivals are fetched from SEPTA and Amtrak
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A train due within this many minutes makes the render very useful
+const DUE_SOON_MINS: i64 = 10;
+
+/// A train due within this many minutes makes the render useful
+const DUE_LATER_MINS: i64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct UpcomingTrain {
     /// The time the train is scheduled to arrive in the station
     schedule_arrival: DateTime<FixedOffset>,
@@ -64,6 +88,19 @@ struct UpcomingTrain {
     status: UpcomingTrainStatus,
 }
 
+impl UpcomingTrain {
+    /// Minutes until the train is expected in the station, taking its delay into account
+    fn minutes_until_due(&self) -> i64 {
+        let delay = match self.status {
+            UpcomingTrainStatus::Late(mins) => mins as i64,
+            UpcomingTrainStatus::Early(mins) => -(mins as i64),
+            UpcomingTrainStatus::OnTime | UpcomingTrainStatus::Unknown => 0,
+        };
+
+        (self.schedule_arrival.with_timezone(&Utc) - Utc::now()).num_minutes() + delay
+    }
+}
+
 #[derive(Debug, Default)]
 struct UpcomingTrainsState {
     septa_arrivals: Vec<UpcomingTrain>,
@@ -73,11 +110,43 @@ struct UpcomingTrainsState {
     combined_arrivals: Vec<UpcomingTrain>,
 }
 
+impl UpcomingTrainsState {
+    /// Merges the SEPTA and Amtrak arrivals, called whenever either of them is updated
+    fn combine(&mut self) {
+        let mut arrivals = self
+            .septa_arrivals
+            .iter()
+            .cloned()
+            .chain(self.amtrak_arrivals.iter().cloned())
+            .collect::<Vec<_>>();
+        arrivals.sort_by(|a, b| a.schedule_arrival.cmp(&b.schedule_arrival));
+
+        self.combined_arrivals = arrivals;
+    }
+}
+
+fn default_stale_after_secs() -> u64 {
+    5 * 60
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct UpcomingArrivalsConfig {
     pub septa_station: Option<RegionalRailStop>,
     pub amtrak_station: Option<String>,
     pub results: Option<u8>,
+
+    /// Where the SEPTA arrivals come from, recorded arrivals let the render run offline
+    #[serde(default)]
+    pub septa_source: ArrivalSourceConfig,
+
+    /// Where the Amtrak arrivals come from, recorded arrivals let the render run offline
+    #[serde(default)]
+    pub amtrak_source: ArrivalSourceConfig,
+
+    /// Once the arrivals haven't been updated for this long they are greyed out and the trains
+    /// that should have left are no longer shown
+    #[serde(default = "default_stale_after_secs")]
+    pub stale_after_secs: u64,
 }
 
 pub struct UpcomingArrivals {
@@ -90,14 +159,16 @@ pub struct UpcomingArrivals {
     /// If the station has Amtrak transit information
     is_amtrak_stop: bool,
 
-    /// Flag used to gracefully terminate the render and driver threads
-    cancel_token: CancellationToken,
-
-    /// Shared state between the render and the async task
+    /// Shared state between the render and the pollers
     state: Arc<Mutex<UpcomingTrainsState>>,
 
-    /// Handle to the task used to update the SEPTA information
-    update_task_handle: Option<JoinHandle<Result<()>>>,
+    /// Keep the SEPTA and Amtrak arrivals up to date, only for the providers the station uses
+    pollers: Vec<Poller>,
+
+    stale_after: Duration,
+
+    /// When the render was created, used to scroll destinations that are too long
+    created: Instant,
 }
 
 impl UpcomingArrivals {
@@ -113,85 +184,44 @@ impl UpcomingArrivals {
         };
 
         let state = Arc::new(Mutex::new(UpcomingTrainsState::default()));
-        let cancel_token = CancellationToken::new();
 
         let is_septa_stop = config.septa_station.is_some();
         let is_amtrak_stop = config.amtrak_station.is_some();
 
-        let task_cancel_token = cancel_token.clone();
-        let task_state = state.clone();
-
-        let update_task_handle: JoinHandle<Result<()>> = tokio::task::spawn(async move {
-            let septa_client = config.septa_station.map(SeptaProvider::new);
-            let amtrak_client = config.amtrak_station.map(AmtrakProvider::new);
-
-            loop {
-                let refresh_time = tokio::time::Instant::now() + Duration::from_secs(60);
-
-                let septa_arrivals = if let Some(septa_client) = &septa_client {
-                    match septa_client.arrivals().await {
-                        Ok(response) => Some(response),
-                        Err(e) => {
-                            error!("Could not get updated SEPTA arrivals {e}");
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-
-                let amtrak_arrivals = if let Some(amtrak_client) = &amtrak_client {
-                    match amtrak_client.arrivals().await {
-                        Ok(response) => Some(response),
-                        Err(e) => {
-                            error!("Could not get updated Amtrak arrivals {e}");
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-
-                {
-                    let mut state_unlocked = task_state.lock();
-
-                    if let Some(septa_arrivals) = septa_arrivals {
-                        state_unlocked.septa_arrivals = septa_arrivals;
-                    }
-
-                    if let Some(amtrak_arrivals) = amtrak_arrivals {
-                        state_unlocked.amtrak_arrivals = amtrak_arrivals;
-                    }
-
-                    let mut arrivals = state_unlocked
-                        .septa_arrivals
-                        .iter()
-                        .cloned()
-                        .chain(state_unlocked.amtrak_arrivals.iter().cloned())
-                        .collect::<Vec<_>>();
-                    arrivals.sort_by(|a, b| a.schedule_arrival.cmp(&b.schedule_arrival));
-
-                    state_unlocked.combined_arrivals = arrivals;
-                } // drop(state_unlocked)
-
-                select! {
-                    _ = tokio::time::sleep_until(refresh_time) => {},
-                    _ = task_cancel_token.cancelled() => break,
-                }
-            }
-
-            Ok(())
-        });
+        let mut pollers = Vec::new();
+        if let Some(septa_station) = config.septa_station {
+            pollers.push(septa_provider::spawn_poller(
+                septa_station,
+                &config.septa_source,
+                state.clone(),
+            )?);
+        }
+        if let Some(amtrak_station) = config.amtrak_station {
+            pollers.push(amtrak_provider::spawn_poller(
+                amtrak_station,
+                &config.amtrak_source,
+                state.clone(),
+            )?);
+        }
 
         Ok(Self {
             state,
             station_name,
             is_septa_stop,
             is_amtrak_stop,
-            cancel_token,
-            update_task_handle: Some(update_task_handle),
+            pollers,
+            stale_after: Duration::from_secs(config.stale_after_secs),
+            created: Instant::now(),
         })
     }
+
+    /// Age of the oldest arrivals once they are stale
+    fn stale(&self) -> Option<Duration> {
+        self.pollers
+            .iter()
+            .filter_map(|poller| poller.stale(self.stale_after))
+            .max()
+    }
 }
 
 const SEPTA_IMAGE: &[u8] = include_bytes!("../../../assets/SEPTA_16.bmp");
@@ -203,18 +233,32 @@ lazy_static! {
         Bmp::<Rgb888>::from_slice(AMTRAK_IMAGE).unwrap();
 }
 
+/// Tried in order until the station name fits next to the logos
+const TITLE_FONTS: [&MonoFont<'static>; 4] = [
+    &mono_font::ascii::FONT_9X15,
+    &mono_font::ascii::FONT_7X13,
+    &mono_font::ascii::FONT_6X10,
+    &mono_font::ascii::FONT_5X7,
+];
+
+/// Pixels between the logos and the station name
+const TITLE_SPACING: i32 = 2;
+
+/// Room for 20 characters, longer destinations scroll
+const DESTINATION_WIDTH: u32 = mono_font::ascii::FONT_5X7.character_size.width * 20;
+
 type UpcomingArrivalViews<'a, C> = chain! {
     Text<'a, MonoTextStyle<'static, C>>,
     Text<'a, MonoTextStyle<'static, C>>,
     Text<'a, MonoTextStyle<'static, C>>,
-    Text<'a, MonoTextStyle<'static, C>>,
+    Marquee<'a, MonoTextStyle<'static, C>>,
     Text<'a, MonoTextStyle<'static, C>>
 };
 
 #[derive(ViewGroup)]
 enum TitleView<'a, C: PixelColor, T: ImageDrawable<Color = C>> {
     LogoView(Image<'a, T>),
-    TextView(Text<'a, MonoTextStyle<'static, C>>),
+    TextView(TextBox<'a, MonoTextStyle<'static, C>>),
 }
 
 #[derive(ViewGroup)]
@@ -233,11 +277,13 @@ enum LayoutView<'a, C: PixelColor> {
     ),
 }
 
-impl<D> Render<D> for UpcomingArrivals
-where
-    D: DrawTarget<Color = Rgb888, Error = Infallible>,
-{
-    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+impl UpcomingArrivals {
+    /// Draws the station and its arrivals, `drop_departed` hides the trains that should have left
+    /// already
+    fn draw<D>(&self, canvas: &mut D, drop_departed: bool) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888, Error = Infallible>,
+    {
         let canvas_bounding_box = canvas.bounding_box();
         let mut remaining_height = canvas_bounding_box.size.height;
 
@@ -250,27 +296,45 @@ where
             title_views.push(TitleView::LogoView(Image::new(&*AMTRAK_BMP, Point::zero())));
         }
 
-        title_views.push(TitleView::TextView(Text::new(
-            &self.station_name,
-            Point::zero(),
-            MonoTextStyle::new(&mono_font::ascii::FONT_9X15, Rgb888::WHITE),
-        )));
+        // The station name gets the rest of the line, long names are abbreviated and use smaller
+        // fonts on narrow panels
+        let logos_width: u32 = title_views
+            .iter()
+            .map(|view| view.bounds().size.width + TITLE_SPACING as u32)
+            .sum();
+        let title_size = Size::new(
+            canvas_bounding_box.size.width.saturating_sub(logos_width),
+            TITLE_FONTS[0].character_size.height,
+        );
+
+        title_views.push(TitleView::TextView(
+            TextBox::new(
+                &self.station_name,
+                Rectangle::new(Point::zero(), title_size),
+                TITLE_FONTS.map(|font| MonoTextStyle::new(font, Rgb888::WHITE)),
+            )
+            .with_max_lines(1)
+            .with_vertical_alignment(VerticalAlignment::Middle)
+            .with_abbreviations(TRANSIT_ABBREVIATIONS),
+        ));
 
         // Generate the title layout
         let title_layout = LinearLayout::horizontal(Views::new(&mut title_views))
             .with_alignment(vertical::Center)
-            .with_spacing(spacing::FixedMargin(2))
+            .with_spacing(spacing::FixedMargin(TITLE_SPACING))
             .arrange();
 
         remaining_height -= title_layout.bounds().size.height;
 
         let mut arrival_layouts = Vec::new();
+        let elapsed = self.created.elapsed();
 
         let display_items = self
             .state
             .lock()
             .combined_arrivals
             .iter()
+            .filter(|arrival| !drop_departed || arrival.minutes_until_due() >= 0)
             .map(|arrival| {
                 (
                     arrival.schedule_arrival.format("%_H:%M").to_string(),
@@ -279,7 +343,7 @@ where
                         UpcomingTrainDirection::Arrival => "A".to_owned(),
                         UpcomingTrainDirection::Departure => "D".to_owned(),
                     },
-                    format!("{:<20}", arrival.destination_name),
+                    arrival.destination_name.clone(),
                     match arrival.status {
                         UpcomingTrainStatus::OnTime => "On Time".to_string(),
                         UpcomingTrainStatus::Early(mins) => format!("{} mins early", mins),
@@ -328,11 +392,15 @@ where
                     Point::zero(),
                     MonoTextStyle::new(&mono_font::ascii::FONT_5X7, Rgb888::WHITE),
                 ))
-                .append(Text::new(
-                    destination_name,
-                    Point::zero(),
-                    MonoTextStyle::new(&mono_font::ascii::FONT_5X7, Rgb888::WHITE),
-                ))
+                .append(
+                    Marquee::new(
+                        destination_name,
+                        Point::zero(),
+                        DESTINATION_WIDTH,
+                        MonoTextStyle::new(&mono_font::ascii::FONT_5X7, Rgb888::WHITE),
+                    )
+                    .at(elapsed),
+                )
                 .append(Text::new(
                     status,
                     Point::zero(),
@@ -371,14 +439,48 @@ where
     }
 }
 
-impl Drop for UpcomingArrivals {
-    fn drop(&mut self) {
-        self.cancel_token.cancel();
+impl<D> Render<D> for UpcomingArrivals
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        match self.stale() {
+            Some(age) => {
+                self.draw(&mut FadedCanvas::new(canvas, STALE_BRIGHTNESS), true)?;
+                draw_stale_badge(canvas, age)
+            }
+            None => self.draw(canvas, false),
+        }
+    }
+
+    fn usefulness(&self) -> UsefulnessVal {
+        let next_train = self
+            .state
+            .lock()
+            .combined_arrivals
+            .iter()
+            .map(UpcomingTrain::minutes_until_due)
+            .filter(|mins| *mins >= 0)
+            .min();
+
+        let usefulness = match next_train {
+            Some(mins) if mins <= DUE_SOON_MINS => UsefulnessVal::VeryUseful,
+            Some(mins) if mins <= DUE_LATER_MINS => UsefulnessVal::Useful,
+            Some(_) => UsefulnessVal::SomewhatUseful,
+            None => UsefulnessVal::BarelyUseful,
+        };
 
-        if let Some(task_handle) = self.update_task_handle.take() {
-            task_handle.abort();
+        // Stale arrivals can't be trusted enough to take over the display
+        if self.stale().is_some() {
+            usefulness.min(UsefulnessVal::SomewhatUseful)
+        } else {
+            usefulness
         }
     }
+
+    fn health(&self) -> Vec<TaskHealth> {
+        self.pollers.iter().map(Poller::health).collect()
+    }
 }
 
 pub struct UpcomingArrivalsFactory<D>
@@ -416,3 +518,68 @@ where
         Ok(Box::new(UpcomingArrivals::new(config)?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        amtrak_provider::AmtrakProvider,
+        septa_provider::SeptaProvider,
+        source::FixtureSource,
+        UpcomingTrainDirection::{Arrival, Departure},
+        UpcomingTrainStatus::{Early, Late, OnTime, Unknown},
+        UpcomingTrainsState,
+    };
+    use crate::recordings;
+    use rustic_pixel_display::provider::Provider;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn combines_the_fixture_arrivals() {
+        recordings::set_recordings_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures"));
+        let mut state = UpcomingTrainsState::default();
+
+        let mut septa = SeptaProvider::new(
+            FixtureSource::load(Path::new("upcoming_arrivals/septa_suburban_station.json"))
+                .unwrap(),
+        );
+        let arrivals = septa.fetch().await.unwrap();
+        septa.apply(&mut state, arrivals).unwrap();
+
+        let mut amtrak = AmtrakProvider::new(
+            "PHL".to_owned(),
+            FixtureSource::load(Path::new("upcoming_arrivals/amtrak_phl.json")).unwrap(),
+        );
+        let arrivals = amtrak.fetch().await.unwrap();
+        amtrak.apply(&mut state, arrivals).unwrap();
+
+        let trains: Vec<_> = state
+            .combined_arrivals
+            .iter()
+            .map(|train| (train.train_id.as_str(), train.status, train.direction))
+            .collect();
+        assert_eq!(
+            trains,
+            [
+                ("9553", OnTime, Arrival),
+                ("845", OnTime, Arrival),
+                ("483", Late(5), Arrival),
+                ("171-18", Late(8), Departure),
+                ("9736", OnTime, Arrival),
+                ("257", Unknown, Arrival),
+                ("445", OnTime, Arrival),
+                ("2163-18", OnTime, Departure),
+                ("367", OnTime, Arrival),
+                ("3355", OnTime, Arrival),
+                ("652-18", Early(2), Arrival),
+                ("176-18", Unknown, Departure),
+            ]
+        );
+        assert!(state
+            .combined_arrivals
+            .windows(2)
+            .all(|pair| pair[0].schedule_arrival <= pair[1].schedule_arrival));
+
+        // Amtrak trains ending their trip in the station show where they come from
+        assert_eq!(state.combined_arrivals[10].destination_name, "Harrisburg");
+    }
+}
@@ -1,27 +1,92 @@
-use std::error::Error;
+use std::sync::Arc;
 
-use chrono::FixedOffset;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, FixedOffset, NaiveDateTime};
 use log::warn;
+use parking_lot::Mutex;
+use rustic_pixel_display::provider::{PollSettings, Poller, Provider};
 use septa_api::{requests::ArrivalsRequest, responses::Arrivals, types::RegionalRailStop, Client};
+use serde::{Deserialize, Serialize};
 
-use super::{UpcomingTrain, UpcomingTrainStatus};
+use super::{
+    source::{
+        ArrivalSource, ArrivalSourceConfig, FixtureSource, RecordingSource, ReplaySource,
+        Reschedule,
+    },
+    UpcomingTrain, UpcomingTrainDirection, UpcomingTrainStatus, UpcomingTrainsState,
+    REFRESH_INTERVAL,
+};
 
-pub(super) struct SeptaProvider {
+/// The parts of a SEPTA arrival the render uses, as returned by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct SeptaArrival {
+    /// Scheduled time in the station, in Philadelphia's local time
+    sched_time: NaiveDateTime,
+    destination: String,
+    train_id: String,
+
+    /// Either `On Time`, `N/A` or how late the train is, i.e. `5 min`
+    status: String,
+}
+
+impl From<Arrivals> for SeptaArrival {
+    fn from(value: Arrivals) -> Self {
+        Self {
+            sched_time: value.sched_time,
+            destination: value.destination.to_string(),
+            train_id: value.train_id,
+            status: value.status,
+        }
+    }
+}
+
+impl Reschedule for SeptaArrival {
+    fn reschedule(&mut self, by: Duration) {
+        self.sched_time += by;
+    }
+}
+
+impl From<SeptaArrival> for UpcomingTrain {
+    fn from(value: SeptaArrival) -> Self {
+        UpcomingTrain {
+            schedule_arrival: value
+                .sched_time
+                .and_local_timezone(FixedOffset::east_opt(-4 * 3600).unwrap())
+                .unwrap(),
+            destination_name: value.destination,
+            direction: UpcomingTrainDirection::Arrival,
+            train_id: value.train_id,
+            status: if value.status == "On Time" {
+                UpcomingTrainStatus::OnTime
+            } else if value.status == "N/A" {
+                UpcomingTrainStatus::Unknown
+            } else if let Ok(mins) = value.status.trim_end_matches(" min").parse::<u32>() {
+                UpcomingTrainStatus::Late(mins)
+            } else {
+                warn!("Unknown SEPTA train status {}", value.status);
+                UpcomingTrainStatus::Unknown
+            },
+        }
+    }
+}
+
+/// Requests the arrivals of a station from the SEPTA API
+pub(super) struct LiveSeptaSource {
     station: RegionalRailStop,
     client: Client,
 }
 
-impl SeptaProvider {
+impl LiveSeptaSource {
     pub(super) fn new(station: RegionalRailStop) -> Self {
         let client = Client::new();
 
         Self { station, client }
     }
+}
 
-    pub(super) async fn arrivals(&self) -> Result<Vec<UpcomingTrain>, Box<dyn Error>> {
-        let Self {
-            station, client, ..
-        } = self;
+impl ArrivalSource<SeptaArrival> for LiveSeptaSource {
+    async fn arrivals(&mut self) -> Result<Vec<SeptaArrival>> {
+        let Self { station, client } = self;
 
         let response = client
             .arrivals(ArrivalsRequest {
@@ -29,40 +94,88 @@ impl SeptaProvider {
                 results: None,
                 direction: None,
             })
-            .await?;
+            .await
+            .map_err(|e| anyhow!("{e}"))?;
 
-        // Sort the arrivals
-        let mut arrivals: Vec<septa_api::responses::Arrivals> = Vec::new();
-        arrivals.extend(response.northbound.into_iter());
-        arrivals.extend(response.southbound.into_iter());
+        Ok(response
+            .northbound
+            .into_iter()
+            .chain(response.southbound)
+            .map(Into::into)
+            .collect())
+    }
+}
+
+pub(super) struct SeptaProvider<S> {
+    source: S,
+}
+
+impl<S> SeptaProvider<S>
+where
+    S: ArrivalSource<SeptaArrival>,
+{
+    pub(super) fn new(source: S) -> Self {
+        Self { source }
+    }
+}
+
+impl<S> Provider for SeptaProvider<S>
+where
+    S: ArrivalSource<SeptaArrival>,
+{
+    type Data = Vec<UpcomingTrain>;
+    type State = UpcomingTrainsState;
+
+    fn name(&self) -> &'static str {
+        "septa"
+    }
+
+    async fn fetch(&mut self) -> Result<Self::Data> {
+        let mut arrivals = self.source.arrivals().await?;
         arrivals.sort_by(|a, b| a.sched_time.cmp(&b.sched_time));
 
-        arrivals.into_iter().map(|train| train.try_into()).collect()
+        Ok(arrivals.into_iter().map(Into::into).collect())
+    }
+
+    fn apply(&mut self, state: &mut Self::State, data: Self::Data) -> Result<()> {
+        state.septa_arrivals = data;
+        state.combine();
+        Ok(())
     }
 }
 
-impl TryFrom<Arrivals> for UpcomingTrain {
-    type Error = Box<dyn Error>;
+/// Starts polling the arrivals of `station` from the configured source. Only the live arrivals
+/// are cached, recorded ones would otherwise replace them on the next start.
+pub(super) fn spawn_poller(
+    station: RegionalRailStop,
+    source: &ArrivalSourceConfig,
+    state: Arc<Mutex<UpcomingTrainsState>>,
+) -> Result<Poller> {
+    let settings = PollSettings::every(REFRESH_INTERVAL);
+    let cache_key = format!("septa_arrivals_{station}");
 
-    fn try_from(value: Arrivals) -> Result<Self, Self::Error> {
-        Ok(UpcomingTrain {
-            schedule_arrival: value
-                .sched_time
-                .and_local_timezone(FixedOffset::east_opt(-4 * 3600).unwrap())
-                .unwrap(),
-            destination_name: value.destination.to_string(),
-            direction: super::UpcomingTrainDirection::Arrival,
-            train_id: value.train_id,
-            status: if value.status == "On Time" {
-                UpcomingTrainStatus::OnTime
-            } else if value.status == "N/A" {
-                UpcomingTrainStatus::Unknown
-            } else if let Ok(mins) = value.status.trim_end_matches(" min").parse::<u32>() {
-                UpcomingTrainStatus::Late(mins)
-            } else {
-                warn!("Unknown SEPTA train status {}", value.status);
-                UpcomingTrainStatus::Unknown
-            },
-        })
+    match source {
+        ArrivalSourceConfig::Live => Poller::spawn_cached(
+            SeptaProvider::new(LiveSeptaSource::new(station)),
+            settings,
+            state,
+            cache_key,
+        ),
+        ArrivalSourceConfig::Record(path) => Poller::spawn_cached(
+            SeptaProvider::new(RecordingSource::new(LiveSeptaSource::new(station), path)?),
+            settings,
+            state,
+            cache_key,
+        ),
+        ArrivalSourceConfig::Fixture(path) => Poller::spawn(
+            SeptaProvider::new(FixtureSource::load(path)?),
+            settings,
+            state,
+        ),
+        ArrivalSourceConfig::Replay(path) => Poller::spawn(
+            SeptaProvider::new(ReplaySource::load(path)?),
+            settings,
+            state,
+        ),
     }
 }
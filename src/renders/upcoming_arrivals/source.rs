@@ -0,0 +1,178 @@
+use crate::recordings::recording_path;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    future::Future,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncWriteExt;
+
+/// Where a provider gets the raw arrivals of a station from
+pub(super) trait ArrivalSource<T>: Send + 'static {
+    fn arrivals(&mut self) -> impl Future<Output = Result<Vec<T>>> + Send;
+}
+
+/// Which [`ArrivalSource`] a provider uses, the recorded sources let the render run without the
+/// internet. Recordings are named relative to the [recordings directory](crate::recordings).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrivalSourceConfig {
+    /// Calls the API of the transit agency
+    #[default]
+    Live,
+
+    /// Calls the API and appends every response to a JSONL file that can be replayed later
+    Record(PathBuf),
+
+    /// Always returns the single response recorded in a JSON file
+    Fixture(PathBuf),
+
+    /// Returns the responses recorded in a JSONL file one after the other, starting over once
+    /// they run out
+    Replay(PathBuf),
+}
+
+/// Recorded arrivals are moved forward in time when they are replayed, so that trains that were
+/// upcoming when they were recorded are upcoming again
+pub(super) trait Reschedule {
+    fn reschedule(&mut self, by: Duration);
+}
+
+/// A response as it is stored by [`RecordingSource`] and read back by [`FixtureSource`] and
+/// [`ReplaySource`]
+#[derive(Serialize, Deserialize)]
+struct RecordedResponse<A> {
+    fetched_at: DateTime<Utc>,
+    arrivals: A,
+}
+
+impl<T: Reschedule> RecordedResponse<Vec<T>> {
+    /// Returns the arrivals as if the response had been fetched just now
+    fn replay(self) -> Vec<T> {
+        let Self {
+            fetched_at,
+            mut arrivals,
+        } = self;
+
+        let by = Utc::now() - fetched_at;
+        arrivals
+            .iter_mut()
+            .for_each(|arrival| arrival.reschedule(by));
+
+        arrivals
+    }
+}
+
+pub(super) struct FixtureSource<T> {
+    contents: String,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> FixtureSource<T> {
+    pub(super) fn load(name: &Path) -> Result<Self> {
+        let path = recording_path(name)?;
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read the fixture {}", path.display()))?;
+
+        Ok(Self {
+            contents,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<T> ArrivalSource<T> for FixtureSource<T>
+where
+    T: DeserializeOwned + Reschedule + Send + 'static,
+{
+    async fn arrivals(&mut self) -> Result<Vec<T>> {
+        let response: RecordedResponse<Vec<T>> = serde_json::from_str(&self.contents)?;
+        Ok(response.replay())
+    }
+}
+
+pub(super) struct ReplaySource<T> {
+    /// One recorded response per line
+    lines: Vec<String>,
+    next: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> ReplaySource<T> {
+    pub(super) fn load(name: &Path) -> Result<Self> {
+        let path = recording_path(name)?;
+        let lines: Vec<String> = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read the recording {}", path.display()))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        if lines.is_empty() {
+            bail!("The recording {} has no responses", path.display());
+        }
+
+        Ok(Self {
+            lines,
+            next: 0,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<T> ArrivalSource<T> for ReplaySource<T>
+where
+    T: DeserializeOwned + Reschedule + Send + 'static,
+{
+    async fn arrivals(&mut self) -> Result<Vec<T>> {
+        let line = &self.lines[self.next];
+        self.next = (self.next + 1) % self.lines.len();
+
+        let response: RecordedResponse<Vec<T>> = serde_json::from_str(line)?;
+        Ok(response.replay())
+    }
+}
+
+/// Passes the responses of another source through, appending each of them to a JSONL file
+pub(super) struct RecordingSource<S> {
+    source: S,
+    path: PathBuf,
+}
+
+impl<S> RecordingSource<S> {
+    pub(super) fn new(source: S, name: &Path) -> Result<Self> {
+        Ok(Self {
+            source,
+            path: recording_path(name)?,
+        })
+    }
+}
+
+impl<S, T> ArrivalSource<T> for RecordingSource<S>
+where
+    S: ArrivalSource<T>,
+    T: Serialize + Send + Sync,
+{
+    async fn arrivals(&mut self) -> Result<Vec<T>> {
+        let arrivals = self.source.arrivals().await?;
+
+        let mut line = serde_json::to_string(&RecordedResponse {
+            fetched_at: Utc::now(),
+            arrivals: &arrivals,
+        })?;
+        line.push('\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Could not open the recording {}", self.path.display()))?;
+        file.write_all(line.as_bytes()).await?;
+
+        Ok(arrivals)
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use embedded_graphics::{
     mono_font::{self, MonoTextStyle},
     pixelcolor::Rgb888,
@@ -11,15 +11,16 @@ use embedded_layout::{
     prelude::Chain,
     view_group::Views,
 };
-use log::error;
 use parking_lot::Mutex;
-use rustic_pixel_display::render::{Render, RenderFactory};
-use serde::Deserialize;
+use rustic_pixel_display::{
+    health::TaskHealth,
+    provider::{PollSettings, Poller, Provider},
+    render::{draw_stale_badge, FadedCanvas, Render, RenderFactory, STALE_BRIGHTNESS},
+};
+use serde::{Deserialize, Serialize};
 use std::{
     convert::Infallible, io::Read, marker::PhantomData, net::IpAddr, sync::Arc, time::Duration,
 };
-use tokio::{select, task::JoinHandle};
-use tokio_util::sync::CancellationToken;
 use weer_api::{chrono::Utc, BaseApi, Client};
 
 #[derive(Clone, Debug, Deserialize)]
@@ -39,7 +40,7 @@ impl From<Location> for weer_api::Query {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct DisplayForecast {
     location_name: String,
     temperature: f32,
@@ -64,75 +65,95 @@ impl From<weer_api::Forecast> for DisplayForecast {
     }
 }
 
+fn default_stale_after_secs() -> u64 {
+    2 * 60 * 60
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Configuration {
     pub api_key: String,
     pub location: Location,
+
+    /// The forecast is greyed out once it hasn't been updated for this long
+    #[serde(default = "default_stale_after_secs")]
+    pub stale_after_secs: u64,
 }
 
-pub struct Weather {
-    state: Arc<Mutex<DisplayForecast>>,
+/// Fetches the forecast for the configured location
+struct ForecastProvider {
+    api_key: String,
+    location: Location,
+}
 
-    /// Flag used to gracefully terminate the render and driver threads
-    cancel_token: CancellationToken,
+impl Provider for ForecastProvider {
+    type Data = DisplayForecast;
+    type State = DisplayForecast;
 
-    /// Handle to the task used to update the SEPTA information
-    update_forecast_handle: Option<JoinHandle<Result<()>>>,
-}
+    fn name(&self) -> &'static str {
+        "weather"
+    }
 
-impl Weather {
-    pub fn new(config: Configuration) -> Self {
-        let client = Client::new(&config.api_key, true);
+    async fn fetch(&mut self) -> Result<Self::Data> {
+        let Self { api_key, location } = self;
+        let api_key = api_key.clone();
+        let location = location.clone();
 
-        let display_state = Arc::new(Mutex::new(DisplayForecast::default()));
-        let cancel_token = CancellationToken::new();
+        // The client blocks, so it's moved off of the runtime so the poller's timeout can fire
+        tokio::task::spawn_blocking(move || {
+            Client::new(&api_key, true)
+                .forecast()
+                .query(location.into())
+                .dt(Utc::now())
+                .call()
+                .map(Into::into)
+                .map_err(|e| anyhow!("{e}"))
+        })
+        .await?
+    }
 
-        let task_cancel_token = cancel_token.clone();
-        let task_display_state = display_state.clone();
+    fn apply(&mut self, state: &mut Self::State, data: Self::Data) -> Result<()> {
+        *state = data;
+        Ok(())
+    }
+}
 
-        let update_forecast_handle = tokio::task::spawn(async move {
-            loop {
-                let start_time = tokio::time::Instant::now();
-                let refresh_duration;
+pub struct Weather {
+    state: Arc<Mutex<DisplayForecast>>,
 
-                match client
-                    .forecast()
-                    .query(config.location.clone().into())
-                    .dt(Utc::now())
-                    .call()
-                {
-                    Ok(result) => {
-                        *task_display_state.lock() = result.into();
-                        refresh_duration = Duration::from_secs(30 * 60);
-                    }
-                    Err(e) => {
-                        error!("Could not get updated information {e}");
-                        refresh_duration = Duration::from_secs(30);
-                    }
-                }
+    /// Keeps the forecast up to date
+    poller: Poller,
 
-                select! {
-                    _ = tokio::time::sleep_until(start_time + refresh_duration) => {},
-                    _ = task_cancel_token.cancelled() => break,
-                }
-            }
+    stale_after: Duration,
+}
+
+impl Weather {
+    pub fn new(config: Configuration) -> Result<Self> {
+        let state = Arc::new(Mutex::new(DisplayForecast::default()));
 
-            Ok(())
-        });
+        let cache_key = format!("weather_{:?}", config.location);
+        let provider = ForecastProvider {
+            api_key: config.api_key,
+            location: config.location,
+        };
+        let poller = Poller::spawn_cached(
+            provider,
+            PollSettings::every(Duration::from_secs(30 * 60))
+                .with_retry_delay(Duration::from_secs(30), Duration::from_secs(30 * 60)),
+            state.clone(),
+            cache_key,
+        )?;
 
-        Self {
-            state: display_state,
-            cancel_token,
-            update_forecast_handle: Some(update_forecast_handle),
-        }
+        Ok(Self {
+            state,
+            poller,
+            stale_after: Duration::from_secs(config.stale_after_secs),
+        })
     }
-}
 
-impl<D> Render<D> for Weather
-where
-    D: DrawTarget<Color = Rgb888, Error = Infallible>,
-{
-    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+    fn draw<D>(&self, canvas: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888>,
+    {
         let display_state = self.state.lock();
 
         let color_from_temp = |temp: f32| -> Rgb888 {
@@ -240,14 +261,23 @@ where
     }
 }
 
-impl Drop for Weather {
-    fn drop(&mut self) {
-        self.cancel_token.cancel();
-
-        if let Some(task_handle) = self.update_forecast_handle.take() {
-            task_handle.abort();
+impl<D> Render<D> for Weather
+where
+    D: DrawTarget<Color = Rgb888, Error = Infallible>,
+{
+    fn render(&self, canvas: &mut D) -> Result<(), D::Error> {
+        match self.poller.stale(self.stale_after) {
+            Some(age) => {
+                self.draw(&mut FadedCanvas::new(canvas, STALE_BRIGHTNESS))?;
+                draw_stale_badge(canvas, age)
+            }
+            None => self.draw(canvas),
         }
     }
+
+    fn health(&self) -> Vec<TaskHealth> {
+        vec![self.poller.health()]
+    }
 }
 
 pub struct WeatherFactory<D>
@@ -282,6 +312,6 @@ where
 
     fn load_from_config<R: Read>(&self, reader: R) -> Result<Box<dyn Render<D>>> {
         let config: Configuration = serde_json::from_reader(reader)?;
-        Ok(Box::new(Weather::new(config)))
+        Ok(Box::new(Weather::new(config)?))
     }
 }